@@ -0,0 +1,87 @@
+// Times the grid-heavy paths on a large maze. Run with `cargo bench`.
+use pacman_game::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::hint::black_box;
+use std::time::Instant;
+
+const WIDTH: usize = 401;
+const HEIGHT: usize = 201;
+
+fn time(label: &str, iters: u32, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let per_iter = start.elapsed() / iters;
+    println!("{:<24} {:>10.1?} / iter", label, per_iter);
+}
+
+fn main() {
+    println!("maze {}x{}", WIDTH, HEIGHT);
+    let options = MazeOptions::default();
+    time("generate_maze", 20, || {
+        let mut rng = StdRng::seed_from_u64(1);
+        black_box(generate_maze(&mut rng, WIDTH, HEIGHT, options));
+    });
+
+    let game = Game::with_seed(1, 1, WIDTH, HEIGHT);
+    time("clone grid", 2000, || {
+        black_box(game.grid.clone());
+    });
+    time("scan grid", 2000, || {
+        let mut pellets = 0;
+        for y in 0..game.height {
+            for x in 0..game.width {
+                if matches!(game.grid[y][x], Tile::Pellet | Tile::Power) {
+                    pellets += 1;
+                }
+            }
+        }
+        black_box(pellets);
+    });
+    time("bfs_distance", 200, || {
+        black_box(bfs_distance(
+            &game.grid,
+            game.width,
+            game.height,
+            game.player,
+            true,
+        ));
+    });
+
+    // No input: Pac-Man stays put and the time goes to the ghosts.
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut game = game;
+    game.intro_ticks = 0;
+    time("100 ticks (idle)", 20, || {
+        for _ in 0..100 {
+            tick(&mut game, &mut rng, None, false);
+        }
+        black_box(game.score);
+    });
+
+    // Typical play on the default maze: the demo bot's inputs, recorded once
+    // and replayed so its own searches stay out of the timing. From about
+    // level 14 ghosts move more than once a tick and share more floods.
+    for level in [1, 20] {
+        let config = GameConfig {
+            level,
+            lives: 20,
+            ..GameConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut game = Game::new_with_config(config.clone(), &mut rng);
+        let mut recording = Recording::new(3, config.width, config.height);
+        while recording.ticks < 1000 && game.lives > 0 {
+            let dir = demo_dir(&game);
+            recording.record_tick(dir);
+            tick(&mut game, &mut rng, dir, dir.is_some());
+        }
+        let label = format!("{} ticks (played, level {})", recording.ticks, level);
+        time(&label, 20, || {
+            black_box(replay(&recording, config.clone()));
+        });
+    }
+}
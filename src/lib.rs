@@ -0,0 +1,4966 @@
+//! Game core for the terminal Pac-Man: maze generation, ghost AI and the
+//! per-tick simulation, with no terminal I/O. The `pacman` binary drives it.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
+use std::ops::{Index, IndexMut};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const PEN_W: usize = 9;
+pub const PEN_H: usize = 5;
+pub const GHOST_RELEASE_INTERVAL: u32 = 90;
+pub const EYES_PEN_MOVES: u32 = 15;
+// Pellets eaten before each ghost leaves the pen on levels 1, 2 and 3+, counted
+// only while it is the next one due out, and after a death (7, 17 and 32 in all).
+pub const GHOST_DOT_LIMITS: [[u32; 4]; 3] = [[0, 0, 30, 60], [0, 0, 0, 50], [0, 0, 0, 0]];
+pub const DEATH_DOT_LIMITS: [u32; 4] = [0, 7, 10, 15];
+// Ticks everything holds still after Pac-Man is caught, before the reset.
+pub const DEATH_FREEZE_TICKS: u32 = 15;
+pub const BONUS_MIN_TICKS: u32 = 600;
+pub const BONUS_MAX_TICKS: u32 = 1100;
+pub const BONUS_LIFETIME_TICKS: u32 = 260;
+pub const BONUS_POWER_BOOST: u32 = 40;
+// Collected bonuses shown under the maze; older ones drop off the front.
+pub const BONUS_TRAY_LEN: usize = 7;
+pub const SPEED_BONUS_CHANCE: f32 = 0.2;
+pub const SPEED_TICKS: u32 = 80;
+pub const TICK_MS_LEVEL_STEP: u64 = 3;
+pub const MIN_LEVEL_TICK_MS: u64 = 45;
+pub const POWER_TICKS: u32 = 90;
+pub const START_LIVES: u32 = 3;
+pub const MAX_LIVES: u32 = 5;
+// With combos on, every COMBO_STEP pellets eaten without a break of more than
+// COMBO_GAP_TICKS ticks raise the pellet multiplier by one, up to COMBO_MAX.
+pub const COMBO_STEP: u32 = 8;
+pub const COMBO_GAP_TICKS: u32 = 4;
+pub const COMBO_MAX: u32 = 4;
+// Ticks a turn that isn't open yet stays queued, waiting for the next opening.
+pub const TURN_BUFFER_TICKS: u32 = 6;
+pub const FRIGHT_FLASH_TICKS: u32 = 30;
+pub const FRIGHT_FLASH_PERIOD: u32 = 4;
+// Chance a frightened ghost takes a random turn at an intersection instead of fleeing.
+pub const FRIGHT_RANDOM_CHANCE: f64 = 0.25;
+// Order equal-distance ghost turns are settled in when `fixed_ties` is on.
+pub const TIE_PRIORITY: [Dir; 4] = [Dir::Up, Dir::Left, Dir::Down, Dir::Right];
+// The demo bot chases frightened ghosts only while at least this much power is
+// left, and otherwise keeps more than DEMO_DANGER_DIST steps from any live ghost.
+pub const DEMO_CHASE_POWER: u32 = 30;
+pub const DEMO_DANGER_DIST: i32 = 3;
+pub const BRAID_CHANCE: f32 = 0.45;
+pub const EXTRA_OPENINGS: f32 = 0.08;
+pub const GHOST_MOVE_INTERVAL_BASE: f32 = 2.0;
+pub const GHOST_MOVE_INTERVAL_MIN: f32 = 0.7;
+pub const GHOST_SPEED_LEVEL_SCALE: f32 = 0.08;
+// Frightened ghosts wait longer between moves and eyes hurry home; both scale a
+// ghost's own move interval.
+pub const FRIGHT_INTERVAL_SCALE: f32 = 1.5;
+pub const EYES_INTERVAL_SCALE: f32 = 0.5;
+// Cruise Elroy: Blinky speeds up once this few pellets are left, 10 more per
+// level up to the cap, and again at half that.
+pub const ELROY_PELLETS: usize = 20;
+pub const ELROY_PELLETS_PER_LEVEL: usize = 10;
+pub const ELROY_PELLETS_MAX: usize = 120;
+pub const ELROY_INTERVAL_SCALES: [f32; 2] = [0.9, 0.8];
+// Phase lengths in ticks (about 7 s, 5 s and 20 s at the default tick rate).
+pub const SCATTER_TICKS: u32 = 100;
+pub const SCATTER_TICKS_SHORT: u32 = 70;
+pub const CHASE_TICKS: u32 = 285;
+pub const SHORT_SCATTER_LEVEL: u32 = 5;
+pub const PINKY_LEAD: isize = 4;
+pub const INKY_LEAD: isize = 2;
+pub const CLYDE_SHY_DISTANCE: isize = 8;
+pub const MIN_ROOM_SIZE: usize = 3;
+pub const DEFAULT_ROOM_SIZE: usize = 5;
+// Smallest generated maze: room for the ghost pen with corridors and pellets
+// around it. See maze_size.
+pub const MIN_GRID_W: usize = 21;
+pub const MIN_GRID_H: usize = 15;
+pub const DEFAULT_GRID_W: usize = 31;
+pub const DEFAULT_GRID_H: usize = 21;
+pub const REVEAL_TICKS: u32 = 20;
+// The READY! pause before each level, about two seconds at the default tick rate.
+pub const READY_TICKS: u32 = 30;
+pub const DEFAULT_SPLIT_SCORES: [u32; 4] = [1000, 5000, 10000, 20000];
+// With persistent pellets the board is cut into this many bands of rows, and
+// clearing each one is a level.
+pub const BOARD_REGIONS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tile {
+    Wall,
+    Empty,
+    Pellet,
+    Power,
+    Gate,
+}
+
+// The maze as one row-major Vec: tile (x, y) lives at `y * width + x`.
+// `grid[y]` is row y as a slice, so `grid[y][x]` works as well as get/set.
+#[derive(Clone, PartialEq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, fill: Tile) -> Grid {
+        Grid {
+            width,
+            height,
+            tiles: vec![fill; width * height],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn get(&self, pos: Pos) -> Tile {
+        self.tiles[pos.y * self.width + pos.x]
+    }
+
+    #[inline]
+    pub fn set(&mut self, pos: Pos, tile: Tile) {
+        self.tiles[pos.y * self.width + pos.x] = tile;
+    }
+
+    #[inline]
+    pub fn rows(&self) -> std::slice::Chunks<'_, Tile> {
+        self.tiles.chunks(self.width)
+    }
+
+    #[inline]
+    pub fn rows_mut(&mut self) -> std::slice::ChunksMut<'_, Tile> {
+        self.tiles.chunks_mut(self.width)
+    }
+}
+
+impl Index<usize> for Grid {
+    type Output = [Tile];
+
+    #[inline]
+    fn index(&self, y: usize) -> &[Tile] {
+        &self.tiles[y * self.width..(y + 1) * self.width]
+    }
+}
+
+impl IndexMut<usize> for Grid {
+    #[inline]
+    fn index_mut(&mut self, y: usize) -> &mut [Tile] {
+        &mut self.tiles[y * self.width..(y + 1) * self.width]
+    }
+}
+
+// Steps from a BFS start to every tile, laid out like Grid; -1 where the
+// start can't reach.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Distances {
+    width: usize,
+    steps: Vec<i32>,
+}
+
+impl Distances {
+    #[inline]
+    pub fn get(&self, pos: Pos) -> i32 {
+        self.steps[pos.y * self.width + pos.x]
+    }
+
+    #[inline]
+    pub fn set(&mut self, pos: Pos, steps: i32) {
+        self.steps[pos.y * self.width + pos.x] = steps;
+    }
+
+    pub fn max(&self) -> i32 {
+        self.steps.iter().copied().max().unwrap_or(-1)
+    }
+
+    // Marks every tile unreached, keeping the allocation.
+    fn reset(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.steps.clear();
+        self.steps.resize(width * height, -1);
+    }
+}
+
+impl Index<usize> for Distances {
+    type Output = [i32];
+
+    #[inline]
+    fn index(&self, y: usize) -> &[i32] {
+        &self.steps[y * self.width..(y + 1) * self.width]
+    }
+}
+
+// Floods made during one update_ghosts, keyed by their start tile and whether
+// the gate was open, so ghosts after the same tile share a map and the
+// player's is flooded once a tick however many moves the ghosts make. The
+// worst case is four ghosts with four distinct targets, none of them the
+// player: five floods, plus one per distinct home of eyes heading back, and
+// gate-open copies for ghosts on their way out of the pen. Maps past `used`
+// are spare allocations.
+#[derive(Default)]
+struct FloodCache {
+    maps: Vec<((Pos, bool), Distances)>,
+    used: usize,
+    queue: VecDeque<Pos>,
+}
+
+impl FloodCache {
+    fn clear(&mut self) {
+        self.used = 0;
+    }
+
+    // Index into `maps` of the flood from `start`, flooding on the first ask.
+    fn flood(&mut self, grid: &Grid, size: (usize, usize), start: Pos, gate_open: bool) -> usize {
+        let key = (start, gate_open);
+        let cached = &self.maps[..self.used];
+        if let Some(idx) = cached.iter().position(|(k, _)| *k == key) {
+            return idx;
+        }
+        if self.used == self.maps.len() {
+            self.maps.push((key, Distances::default()));
+        }
+        let (k, dist) = &mut self.maps[self.used];
+        *k = key;
+        let (width, height) = size;
+        bfs_distance_into(grid, width, height, start, gate_open, dist, &mut self.queue);
+        self.used += 1;
+        self.used - 1
+    }
+
+    fn get(&self, idx: usize) -> &Distances {
+        &self.maps[idx].1
+    }
+
+    // The gate-closed flood from `start`, if the last update made one.
+    fn cached(&self, start: Pos) -> Option<&Distances> {
+        let cached = &self.maps[..self.used];
+        let idx = cached.iter().position(|(k, _)| *k == (start, false))?;
+        Some(&cached[idx].1)
+    }
+}
+
+// Frightened is global (see power_timer) bar ghosts revived while it lasts
+// (see ghost_revived); this is what a ghost is on its own.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GhostState {
+    Normal,
+    // Eaten: harmless, and heading back to the pen to revive.
+    Eyes,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GhostPhase {
+    Scatter,
+    Chase,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GhostKind {
+    Blinky,
+    Pinky,
+    Inky,
+    Clyde,
+}
+
+impl GhostKind {
+    // Same order as GHOST_COLORS.
+    pub fn for_index(idx: usize) -> GhostKind {
+        match idx % 4 {
+            0 => GhostKind::Blinky,
+            1 => GhostKind::Pinky,
+            2 => GhostKind::Inky,
+            _ => GhostKind::Clyde,
+        }
+    }
+
+    pub fn scatter_corner(self, width: usize, height: usize) -> Pos {
+        match self {
+            GhostKind::Blinky => Pos { x: width - 2, y: 1 },
+            GhostKind::Pinky => Pos { x: 1, y: 1 },
+            GhostKind::Inky => Pos {
+                x: width - 2,
+                y: height - 2,
+            },
+            GhostKind::Clyde => Pos {
+                x: 1,
+                y: height - 2,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BonusKind {
+    Fruit(Fruit),
+    SpeedShoes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fruit {
+    Cherry,
+    Strawberry,
+    Orange,
+    Apple,
+    Melon,
+    Galaxian,
+    Bell,
+    Key,
+}
+
+impl Fruit {
+    pub fn score(self) -> u32 {
+        match self {
+            Fruit::Cherry => 100,
+            Fruit::Strawberry => 300,
+            Fruit::Orange => 500,
+            Fruit::Apple => 700,
+            Fruit::Melon => 1000,
+            Fruit::Galaxian => 2000,
+            Fruit::Bell => 3000,
+            Fruit::Key => 5000,
+        }
+    }
+}
+
+// The arcade's fruit for levels 1 to 13; every later level gets a key.
+pub const FRUIT_TABLE: [Fruit; 13] = [
+    Fruit::Cherry,
+    Fruit::Strawberry,
+    Fruit::Orange,
+    Fruit::Orange,
+    Fruit::Apple,
+    Fruit::Apple,
+    Fruit::Melon,
+    Fruit::Melon,
+    Fruit::Galaxian,
+    Fruit::Galaxian,
+    Fruit::Bell,
+    Fruit::Bell,
+    Fruit::Key,
+];
+
+pub fn level_fruit(level: u32) -> Fruit {
+    FRUIT_TABLE[(level.max(1) as usize - 1).min(FRUIT_TABLE.len() - 1)]
+}
+
+#[derive(Clone, Copy)]
+pub enum SplitKind {
+    LevelCleared { level: u32, took: Duration },
+    Score(u32),
+}
+
+#[derive(Clone, Copy)]
+pub struct Split {
+    pub kind: SplitKind,
+    pub at: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pos {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Dir {
+    pub fn opposite(self) -> Dir {
+        match self {
+            Dir::Up => Dir::Down,
+            Dir::Down => Dir::Up,
+            Dir::Left => Dir::Right,
+            Dir::Right => Dir::Left,
+        }
+    }
+
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Dir::Up => (0, -1),
+            Dir::Down => (0, 1),
+            Dir::Left => (-1, 0),
+            Dir::Right => (1, 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MazeGen {
+    Prim,
+    Dfs,
+    Kruskal,
+}
+
+#[derive(Clone, Copy)]
+pub struct MazeOptions {
+    pub gen: MazeGen,
+    pub rooms: usize,
+    pub room_size: usize,
+    pub tunnels: usize,
+    // Carve the left half only and mirror it onto the right.
+    pub symmetric: bool,
+    // Chance to knock a dead end through to a neighbor, and for any other
+    // cell to get an extra opening; both in 0..=1, higher is more open.
+    pub braid: f32,
+    pub extra_openings: f32,
+    // Share of cells allowed to stay dead ends after braiding; below 1 a
+    // final pass keeps opening them up until the share is met.
+    pub dead_ends: f32,
+}
+
+impl Default for MazeOptions {
+    fn default() -> Self {
+        Self {
+            gen: MazeGen::Prim,
+            rooms: 0,
+            room_size: DEFAULT_ROOM_SIZE,
+            tunnels: 1,
+            symmetric: false,
+            braid: BRAID_CHANCE,
+            extra_openings: EXTRA_OPENINGS,
+            dead_ends: 1.0,
+        }
+    }
+}
+
+fn ghost_dot_limits(level: u32, ghosts: usize) -> Vec<u32> {
+    let limits = GHOST_DOT_LIMITS[(level.max(1) as usize - 1).min(GHOST_DOT_LIMITS.len() - 1)];
+    (0..ghosts).map(|i| limits[i % 4]).collect()
+}
+
+// The first two scatters are long on early levels; after that they shorten.
+fn scatter_ticks(level: u32, round: u32) -> u32 {
+    if level >= SHORT_SCATTER_LEVEL || round >= 2 {
+        SCATTER_TICKS_SHORT
+    } else {
+        SCATTER_TICKS
+    }
+}
+
+// The size a generated maze is actually built at. Mazes are carved on odd
+// cells, so even sizes round down, and anything under MIN_GRID_W x MIN_GRID_H
+// is raised to it.
+pub fn maze_size(width: usize, height: usize) -> (usize, usize) {
+    let odd = |n: usize| if n % 2 == 0 { n - 1 } else { n };
+    (odd(width.max(MIN_GRID_W)), odd(height.max(MIN_GRID_H)))
+}
+
+// Each level shaves a few ms off the tick, down to a floor. A base that is
+// already below the floor is left alone.
+pub fn level_tick_ms(base: u64, level: u32) -> u64 {
+    let faster = base.saturating_sub(TICK_MS_LEVEL_STEP * level.saturating_sub(1) as u64);
+    faster.max(MIN_LEVEL_TICK_MS.min(base))
+}
+
+fn ghost_move_interval(level: u32) -> f32 {
+    let scale = 1.0 + (level.saturating_sub(1) as f32) * GHOST_SPEED_LEVEL_SCALE;
+    (GHOST_MOVE_INTERVAL_BASE / scale).max(GHOST_MOVE_INTERVAL_MIN)
+}
+
+// Pellets left at which Blinky enters each Cruise Elroy stage.
+pub fn elroy_thresholds(level: u32) -> [usize; 2] {
+    let extra = ELROY_PELLETS_PER_LEVEL * level.saturating_sub(1) as usize;
+    let first = (ELROY_PELLETS + extra).min(ELROY_PELLETS_MAX);
+    [first, first / 2]
+}
+
+pub struct Game {
+    pub width: usize,
+    pub height: usize,
+    pub grid: Grid,
+    pub player: Pos,
+    pub player_spawn: Pos,
+    pub ghosts: Vec<Pos>,
+    pub ghost_spawns: Vec<Pos>,
+    pub score: u32,
+    pub lives: u32,
+    pub level: u32,
+    pub pellets_left: usize,
+    pub power_timer: u32,
+    pub dir: Option<Dir>,
+    // A requested turn that wasn't open yet, taken as soon as it is.
+    pub queued_dir: Option<Dir>,
+    pub queued_ticks: u32,
+    // Per-ghost move clocks: a ghost moves each time its timer passes its interval.
+    pub ghost_timers: Vec<f32>,
+    pub ghost_intervals: Vec<f32>,
+    pub ghost_release: Vec<u32>,
+    pub ghost_dots: Vec<u32>,
+    pub pen_bounds: PenBounds,
+    pub bonus_pos: Option<Pos>,
+    pub bonus_kind: BonusKind,
+    pub bonus_timer: u32,
+    pub bonus_spawn_in: u32,
+    // Bonuses collected this run, oldest first, at most BONUS_TRAY_LEN.
+    pub bonus_tray: Vec<BonusKind>,
+    pub speed_timer: u32,
+    pub death_freeze: u32,
+    pub ghost_last_seen: Vec<Option<Pos>>,
+    pub ghost_kinds: Vec<GhostKind>,
+    pub ghost_states: Vec<GhostState>,
+    pub ghost_dirs: Vec<Option<Dir>>,
+    // Ghosts just turned around (see turn_around) whose next move keeps to the
+    // reversed heading.
+    pub ghost_reverse: Vec<bool>,
+    // Eyes that made it home while power was still on: they come back out
+    // chasing and biting, and stay that way until the next power pellet.
+    pub ghost_revived: Vec<bool>,
+    // Ticks until the player, and each ghost, can go through a tunnel again;
+    // see GameConfig::teleport_cooldown.
+    pub player_teleport_cooldown: u32,
+    pub ghost_teleport_cooldown: Vec<u32>,
+    pub phase: GhostPhase,
+    pub phase_timer: u32,
+    // Scatter phases started so far this level; later ones are shorter.
+    pub phase_round: u32,
+    // Ghosts eaten on the current power pellet.
+    pub ghost_chain: u32,
+    // Pellets eaten in the current streak, and ticks since the last one.
+    pub combo: u32,
+    pub ticks_since_eat: u32,
+    pub run_started: Instant,
+    pub level_started: Instant,
+    pub splits: Vec<Split>,
+    pub split_scores: VecDeque<u32>,
+    pub intro_ticks: u32,
+    // Ticks actually played this run; drives the player's chomping.
+    pub ticks: u64,
+    // Ticks of READY! left before anything moves; counts down after the reveal.
+    pub ready_ticks: u32,
+    pub config: GameConfig,
+    floods: FloodCache,
+    // Set for games made with `seeded`/`with_rng`, so `tick` and `skip` need no
+    // random source passed in.
+    rng: Option<OwnRng>,
+}
+
+// The random source a Game can keep for itself.
+pub type OwnRng = Box<dyn RngCore>;
+
+// Everything tunable about a run. The `pacman` binary fills this from the PACMAN_*
+// environment variables; embedders and tests can build one directly.
+#[derive(Clone)]
+pub struct GameConfig {
+    /// Maze width in tiles, unless `map` is set; generated mazes go through `maze_size`.
+    /// Default: `DEFAULT_GRID_W`.
+    pub width: usize,
+    /// Maze height in tiles, unless `map` is set; generated mazes go through `maze_size`.
+    /// Default: `DEFAULT_GRID_H`.
+    pub height: usize,
+    /// Level the run starts on. Default: 1.
+    pub level: u32,
+    /// Lives at the start of the run; 0 falls back to the default. Default: `START_LIVES`.
+    pub lives: u32,
+    /// Most lives the player can hold, at the start or from any award; 0 falls
+    /// back to the default. Default: `MAX_LIVES`.
+    pub max_lives: u32,
+    /// Ticks a power pellet lasts; 0 falls back to the default. Default: `POWER_TICKS`.
+    pub power_ticks: u32,
+    /// Maze generation algorithm, rooms and tunnels. Default: Prim's, no rooms, one tunnel.
+    pub maze: MazeOptions,
+    /// Let bonuses occasionally spawn as speed shoes. Default: off.
+    pub speed_shoes: bool,
+    /// Drop bonuses on a random free tile instead of just outside the pen gate. Default: off.
+    pub random_bonus: bool,
+    /// Ticks the board stays frozen after a death; 0 resets at once. Default: `DEATH_FREEZE_TICKS`.
+    pub death_freeze_ticks: u32,
+    /// Ghosts chase only by line of sight. Default: off.
+    pub ghost_vision: bool,
+    /// Settle equal-distance ghost turns by `TIE_PRIORITY` instead of at random. Default: off.
+    pub fixed_ties: bool,
+    /// Sweep the maze in at the start of each level. Default: off.
+    pub reveal: bool,
+    /// Score milestones recorded as splits. Default: `DEFAULT_SPLIT_SCORES`.
+    pub split_scores: Vec<u32>,
+    /// Also collect pellets on tiles next to the player. Default: off.
+    pub magnet: bool,
+    /// Multiply pellet points for eating them in quick succession. Default: off.
+    pub combo: bool,
+    /// Keep one board for several levels: clearing each of its `BOARD_REGIONS`
+    /// bands moves up a level in place, and eaten pellets stay eaten. Default: off.
+    pub persistent_pellets: bool,
+    /// Minimum path length from the pen gate to the player spawn. Default: 0.
+    pub spawn_distance: u32,
+    /// Ticks of READY! before each level starts moving; 0 skips it. Default: 0.
+    pub ready_ticks: u32,
+    /// Ticks after going through a tunnel before the same player or ghost can
+    /// go through one again; 0 lets everyone straight back, like the arcade. Default: 0.
+    pub teleport_cooldown: u32,
+    /// Hand-drawn maze played on every level instead of a generated one. Default: none.
+    pub map: Option<CustomMap>,
+    /// Hand-drawn mazes played one per level in this order, starting over
+    /// after the last; takes the place of `map` unless empty. Default: none.
+    pub rotation: Vec<CustomMap>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_GRID_W,
+            height: DEFAULT_GRID_H,
+            level: 1,
+            lives: START_LIVES,
+            max_lives: MAX_LIVES,
+            power_ticks: POWER_TICKS,
+            maze: MazeOptions::default(),
+            speed_shoes: false,
+            random_bonus: false,
+            death_freeze_ticks: DEATH_FREEZE_TICKS,
+            ghost_vision: false,
+            fixed_ties: false,
+            reveal: false,
+            split_scores: DEFAULT_SPLIT_SCORES.to_vec(),
+            magnet: false,
+            combo: false,
+            persistent_pellets: false,
+            spawn_distance: 0,
+            ready_ticks: 0,
+            teleport_cooldown: 0,
+            map: None,
+            rotation: Vec::new(),
+        }
+    }
+}
+
+impl GameConfig {
+    // The hand-drawn maze `level` is played on, if any: its turn in the
+    // rotation, else the fixed map.
+    fn level_map(&self, level: u32) -> Option<&CustomMap> {
+        if self.rotation.is_empty() {
+            return self.map.as_ref();
+        }
+        let turn = level.saturating_sub(1) as usize % self.rotation.len();
+        Some(&self.rotation[turn])
+    }
+}
+
+impl Game {
+    fn apply_input(&mut self, desired_dir: Option<Dir>, input_active: bool) {
+        self.queued_ticks = self.queued_ticks.saturating_sub(1);
+        if self.queued_ticks == 0 {
+            self.queued_dir = None;
+        }
+        if !input_active {
+            self.dir = None;
+            self.queued_dir = None;
+        } else if let Some(dir) = desired_dir {
+            if can_move_player(&self.grid, self.width, self.height, self.player, dir) {
+                // Carrying on straight keeps a pending turn; anything else replaces it.
+                if self.dir != Some(dir) {
+                    self.queued_dir = None;
+                }
+                self.dir = Some(dir);
+            } else if self.dir != Some(dir) {
+                self.queued_dir = Some(dir);
+                self.queued_ticks = TURN_BUFFER_TICKS;
+            }
+        }
+    }
+
+    fn move_player(&mut self) {
+        if let Some(queued) = self.queued_dir {
+            if can_move_player(&self.grid, self.width, self.height, self.player, queued) {
+                self.dir = Some(queued);
+                self.queued_dir = None;
+            }
+        }
+        if let Some(dir) = self.dir {
+            let left = &mut self.player_teleport_cooldown;
+            let full = self.config.teleport_cooldown;
+            if PlayerMover.can_move(&self.grid, self.width, self.height, self.player, dir)
+                && pass_tunnel(left, full, self.player, dir, self.width)
+            {
+                self.player = PlayerMover.step(self.player, dir, self.width);
+            } else {
+                self.dir = None;
+            }
+        }
+    }
+
+    fn consume_tile(&mut self, events: &mut Vec<GameEvent>) {
+        match self.grid.get(self.player) {
+            Tile::Pellet => {
+                self.grid.set(self.player, Tile::Empty);
+                self.score_pellet(10);
+                self.pellets_left = self.pellets_left.saturating_sub(1);
+                self.count_dot();
+                events.push(GameEvent::PelletEaten);
+            }
+            Tile::Power => {
+                self.grid.set(self.player, Tile::Empty);
+                self.score_pellet(50);
+                self.pellets_left = self.pellets_left.saturating_sub(1);
+                self.power_timer = self.config.power_ticks;
+                self.ghost_chain = 0;
+                self.ghost_revived.fill(false);
+                // Ghosts already out turn tail the moment power starts.
+                (0..self.ghosts.len()).for_each(|idx| self.turn_around(idx));
+                self.count_dot();
+                events.push(GameEvent::PowerEaten);
+            }
+            _ => {}
+        }
+        if self.config.magnet {
+            self.magnet_pellets(events);
+        }
+    }
+
+    fn score_pellet(&mut self, points: u32) {
+        self.score += points * self.combo_multiplier();
+        self.combo += 1;
+        self.ticks_since_eat = 0;
+    }
+
+    // What the next pellet's points are multiplied by; always 1 with combos off.
+    pub fn combo_multiplier(&self) -> u32 {
+        if !self.config.combo {
+            return 1;
+        }
+        (1 + self.combo / COMBO_STEP).min(COMBO_MAX)
+    }
+
+    // Bands of the board with no pellets left; see `BOARD_REGIONS`.
+    pub fn regions_cleared(&self) -> usize {
+        let mut left = [false; BOARD_REGIONS];
+        for (y, row) in self.grid.rows().enumerate() {
+            if row.iter().any(|t| matches!(t, Tile::Pellet | Tile::Power)) {
+                left[y * BOARD_REGIONS / self.height] = true;
+            }
+        }
+        left.iter().filter(|&&l| !l).count()
+    }
+
+    fn finish_level(&mut self) {
+        self.splits.push(Split {
+            kind: SplitKind::LevelCleared {
+                level: self.level,
+                took: self.level_started.elapsed(),
+            },
+            at: self.run_started.elapsed(),
+        });
+        self.level_started = Instant::now();
+        self.level += 1;
+    }
+
+    // A region cleared with persistent pellets: the level goes up, but the
+    // board and everyone on it stay where they are.
+    fn clear_region(&mut self) {
+        self.finish_level();
+        self.ghost_intervals = vec![ghost_move_interval(self.level); self.ghosts.len()];
+        self.ghost_dots = ghost_dot_limits(self.level, self.ghosts.len());
+    }
+
+    // Only the next ghost due out of the pen counts eaten pellets; reaching its
+    // limit lets it out early. The release timer still frees it otherwise.
+    fn count_dot(&mut self) {
+        let waiting = (0..self.ghosts.len())
+            .find(|&i| self.ghost_release[i] > 0 && self.ghost_states[i] == GhostState::Normal);
+        if let Some(idx) = waiting {
+            self.ghost_dots[idx] = self.ghost_dots[idx].saturating_sub(1);
+            if self.ghost_dots[idx] == 0 {
+                self.ghost_release[idx] = 0;
+            }
+        }
+    }
+
+    // Plain pellets only: power pellets still have to be stepped on.
+    fn magnet_pellets(&mut self, events: &mut Vec<GameEvent>) {
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+            if !can_move_player(&self.grid, self.width, self.height, self.player, dir) {
+                continue;
+            }
+            let Pos { x: nx, y: ny } = step(self.player, dir, self.width);
+            if self.grid[ny][nx] == Tile::Pellet {
+                self.grid[ny][nx] = Tile::Empty;
+                self.score_pellet(10);
+                self.pellets_left = self.pellets_left.saturating_sub(1);
+                self.count_dot();
+                events.push(GameEvent::PelletEaten);
+            }
+        }
+    }
+
+    fn try_collect_bonus(&mut self, rng: &mut impl Rng, events: &mut Vec<GameEvent>) {
+        if let Some(pos) = self.bonus_pos {
+            if pos == self.player {
+                match self.bonus_kind {
+                    BonusKind::Fruit(fruit) => {
+                        self.score += fruit.score();
+                        self.power_timer =
+                            (self.power_timer + BONUS_POWER_BOOST).max(BONUS_POWER_BOOST);
+                    }
+                    BonusKind::SpeedShoes => {
+                        self.speed_timer = SPEED_TICKS;
+                    }
+                }
+                events.push(GameEvent::BonusEaten {
+                    kind: self.bonus_kind,
+                });
+                if self.bonus_tray.len() == BONUS_TRAY_LEN {
+                    self.bonus_tray.remove(0);
+                }
+                self.bonus_tray.push(self.bonus_kind);
+                self.bonus_pos = None;
+                self.bonus_timer = 0;
+                self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+            }
+        }
+    }
+
+    fn update_bonus(&mut self, rng: &mut impl Rng) {
+        if self.bonus_pos.is_some() {
+            if self.bonus_timer > 0 {
+                self.bonus_timer -= 1;
+            } else {
+                self.bonus_pos = None;
+                self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+            }
+        } else if self.bonus_spawn_in > 0 {
+            self.bonus_spawn_in -= 1;
+        } else {
+            let spawn = if self.config.random_bonus {
+                random_bonus_spawn(self, rng)
+            } else {
+                fixed_bonus_spawn(self)
+            };
+            if let Some(pos) = spawn {
+                self.bonus_pos = Some(pos);
+                let speed = self.config.speed_shoes && rng.gen::<f32>() < SPEED_BONUS_CHANCE;
+                self.bonus_kind = if speed {
+                    BonusKind::SpeedShoes
+                } else {
+                    BonusKind::Fruit(level_fruit(self.level))
+                };
+                self.bonus_timer = BONUS_LIFETIME_TICKS;
+            }
+            self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+        }
+    }
+
+    fn update_ghosts(&mut self, rng: &mut impl Rng) {
+        self.floods.clear();
+        let moves: Vec<u32> = (0..self.ghosts.len())
+            .map(|idx| self.ghost_moves(idx))
+            .collect();
+        self.move_eyes(&moves, rng);
+
+        let (w, h) = (self.width, self.height);
+        let fixed = self.config.fixed_ties;
+        let full = self.config.teleport_cooldown;
+        let rounds = (0..self.ghosts.len())
+            .filter(|&idx| self.ghost_states[idx] != GhostState::Eyes)
+            .map(|idx| moves[idx])
+            .max()
+            .unwrap_or(0);
+        for round in 0..rounds {
+            let targets: Vec<Pos> = (0..self.ghosts.len())
+                .map(|idx| self.ghost_target(idx))
+                .collect();
+            self.floods.flood(&self.grid, (w, h), self.player, false);
+            for (idx, ghost) in self.ghosts.iter_mut().enumerate() {
+                if self.ghost_states[idx] == GhostState::Eyes || moves[idx] <= round {
+                    continue;
+                }
+                if self.ghost_release[idx] > 0 {
+                    self.ghost_release[idx] = self.ghost_release[idx].saturating_sub(1);
+                    let dir = ghost_next_dir_pen(
+                        *ghost,
+                        &self.grid,
+                        self.width,
+                        self.height,
+                        &self.pen_bounds,
+                        rng,
+                    );
+                    if let Some(dir) = dir {
+                        *ghost = step(*ghost, dir, self.width);
+                    }
+                    continue;
+                }
+                // The gate only lets ghosts out: once clear of the pen they treat it
+                // as a wall, and only eyes go back in.
+                let leaving = is_in_pen_bounds(*ghost, &self.pen_bounds);
+                // A ghost that turned around heads back the way it came.
+                let reversing = std::mem::take(&mut self.ghost_reverse[idx]);
+                let back = self.ghost_dirs[idx]
+                    .filter(|d| reversing && can_move_ghost(&self.grid, w, h, *ghost, *d, leaving));
+                let dir = if back.is_some() {
+                    back
+                } else if self.power_timer > 0 && !self.ghost_revived[idx] {
+                    let last = self.ghost_dirs[idx];
+                    let player_map = self.floods.flood(&self.grid, (w, h), self.player, leaving);
+                    let dist = self.floods.get(player_map);
+                    ghost_next_dir_flee(*ghost, &self.grid, w, h, dist, last, rng, leaving, fixed)
+                } else if self.config.ghost_vision {
+                    let last = self.ghost_dirs[idx];
+                    let last_seen = &mut self.ghost_last_seen[idx];
+                    match ghost_vision_target(&self.grid, *ghost, self.player, last_seen) {
+                        Some(target) => {
+                            let seen_map = self.floods.flood(&self.grid, (w, h), target, leaving);
+                            let dist = self.floods.get(seen_map);
+                            ghost_next_dir(
+                                *ghost, &self.grid, w, h, dist, last, rng, leaving, fixed,
+                            )
+                        }
+                        None => ghost_wander_dir(*ghost, &self.grid, w, h, last, rng, leaving),
+                    }
+                } else {
+                    let target_map = self.floods.flood(&self.grid, (w, h), targets[idx], leaving);
+                    let to_target = self.floods.get(target_map);
+                    let last = self.ghost_dirs[idx];
+                    ghost_next_dir(
+                        *ghost, &self.grid, w, h, to_target, last, rng, leaving, fixed,
+                    )
+                };
+                let left = &mut self.ghost_teleport_cooldown[idx];
+                if let Some(dir) = dir.filter(|&dir| pass_tunnel(left, full, *ghost, dir, w)) {
+                    *ghost = step(*ghost, dir, self.width);
+                    self.ghost_dirs[idx] = Some(dir);
+                }
+            }
+        }
+    }
+
+    // 0 until the first Cruise Elroy threshold, then 1 and 2.
+    pub fn elroy_stage(&self) -> usize {
+        let [first, second] = elroy_thresholds(self.level);
+        usize::from(self.pellets_left <= first) + usize::from(self.pellets_left <= second)
+    }
+
+    fn update_elroy(&mut self) {
+        let base = ghost_move_interval(self.level);
+        let interval = match self.elroy_stage() {
+            0 => base,
+            stage => base * ELROY_INTERVAL_SCALES[stage - 1],
+        };
+        for (idx, kind) in self.ghost_kinds.iter().enumerate() {
+            if *kind == GhostKind::Blinky {
+                self.ghost_intervals[idx] = interval;
+            }
+        }
+    }
+
+    // Whether ghost `idx` can be eaten: power is on, and it is neither eyes
+    // nor back from being eaten on this power pellet.
+    pub fn ghost_frightened(&self, idx: usize) -> bool {
+        self.power_timer > 0
+            && self.ghost_states[idx] == GhostState::Normal
+            && !self.ghost_revived[idx]
+    }
+
+    // How often ghost `idx` moves right now: its own interval, stretched while
+    // frightened and shortened as eyes.
+    fn ghost_interval(&self, idx: usize) -> f32 {
+        let interval = self.ghost_intervals[idx];
+        match self.ghost_states[idx] {
+            GhostState::Eyes => interval * EYES_INTERVAL_SCALE,
+            GhostState::Normal if self.ghost_frightened(idx) => interval * FRIGHT_INTERVAL_SCALE,
+            GhostState::Normal => interval,
+        }
+    }
+
+    // Advances ghost `idx`'s clock by a tick and returns how many moves it owes.
+    fn ghost_moves(&mut self, idx: usize) -> u32 {
+        let interval = self.ghost_interval(idx);
+        let timer = &mut self.ghost_timers[idx];
+        *timer += 1.0;
+        let mut moves = 0;
+        while *timer >= interval {
+            *timer -= interval;
+            moves += 1;
+        }
+        moves
+    }
+
+    // Eyes race home through the gate to their spawn, then wait out a short
+    // spell in the pen like a fresh ghost.
+    fn move_eyes(&mut self, moves: &[u32], rng: &mut impl Rng) {
+        for (idx, &ghost_moves) in moves.iter().enumerate() {
+            if self.ghost_states[idx] != GhostState::Eyes {
+                continue;
+            }
+            let home = self.ghost_spawns[idx];
+            for _ in 0..ghost_moves {
+                let pos = self.ghosts[idx];
+                if pos == home {
+                    break;
+                }
+                let (w, h) = (self.width, self.height);
+                let home_map = self.floods.flood(&self.grid, (w, h), home, true);
+                let dist = self.floods.get(home_map);
+                let fixed = self.config.fixed_ties;
+                let dir = ghost_next_dir(pos, &self.grid, w, h, dist, None, rng, true, fixed);
+                let left = &mut self.ghost_teleport_cooldown[idx];
+                let full = self.config.teleport_cooldown;
+                if let Some(dir) = dir.filter(|&dir| pass_tunnel(left, full, pos, dir, w)) {
+                    self.ghosts[idx] = step(pos, dir, self.width);
+                    self.ghost_dirs[idx] = Some(dir);
+                }
+            }
+            if self.ghosts[idx] == home {
+                self.ghost_states[idx] = GhostState::Normal;
+                self.ghost_revived[idx] = self.power_timer > 0;
+                self.ghost_release[idx] = EYES_PEN_MOVES;
+                self.ghost_dirs[idx] = None;
+            }
+        }
+    }
+
+    fn ghost_target(&self, idx: usize) -> Pos {
+        match self.phase {
+            GhostPhase::Scatter => {
+                let corner = self.ghost_kinds[idx].scatter_corner(self.width, self.height);
+                let (x, y) = (corner.x as isize, corner.y as isize);
+                nearest_open_tile(&self.grid, &self.pen_bounds, x, y)
+            }
+            GhostPhase::Chase => self.chase_target(idx),
+        }
+    }
+
+    // Ghost-side distances to the player from the last ghost update, for
+    // debugging the ghosts; None if they didn't flood from where the player is.
+    pub fn player_distances(&self) -> Option<&Distances> {
+        self.floods.cached(self.player)
+    }
+
+    // Where each ghost is steering, as update_ghosts picks it: home for eyes,
+    // and None while a ghost is in the pen or has no target (frightened, or
+    // chasing by sight).
+    pub fn ghost_targets(&self) -> Vec<Option<Pos>> {
+        (0..self.ghosts.len())
+            .map(|idx| {
+                if self.ghost_states[idx] == GhostState::Eyes {
+                    Some(self.ghost_spawns[idx])
+                } else if self.ghost_release[idx] > 0
+                    || self.ghost_frightened(idx)
+                    || self.config.ghost_vision
+                {
+                    None
+                } else {
+                    Some(self.ghost_target(idx))
+                }
+            })
+            .collect()
+    }
+
+    // Alternates scatter and chase; the clock stands still while ghosts are frightened.
+    fn tick_phase(&mut self) {
+        if self.power_timer > 0 {
+            return;
+        }
+        self.phase_timer = self.phase_timer.saturating_sub(1);
+        if self.phase_timer > 0 {
+            return;
+        }
+        self.phase = match self.phase {
+            GhostPhase::Scatter => {
+                self.phase_timer = CHASE_TICKS;
+                GhostPhase::Chase
+            }
+            GhostPhase::Chase => {
+                self.phase_round += 1;
+                self.phase_timer = scatter_ticks(self.level, self.phase_round);
+                GhostPhase::Scatter
+            }
+        };
+        (0..self.ghosts.len()).for_each(|idx| self.turn_around(idx));
+    }
+
+    // Turns ghost `idx` to face back the way it came, if it's out in the maze,
+    // and holds it to that heading for its next move.
+    fn turn_around(&mut self, idx: usize) {
+        let out = self.ghost_release[idx] == 0
+            && self.ghost_states[idx] != GhostState::Eyes
+            && !is_in_pen_bounds(self.ghosts[idx], &self.pen_bounds);
+        if let Some(dir) = self.ghost_dirs[idx].filter(|_| out) {
+            self.ghost_dirs[idx] = Some(dir.opposite());
+            self.ghost_reverse[idx] = true;
+        }
+    }
+
+    // Arcade-style chase targets: Blinky goes straight for the player, Pinky
+    // aims ahead of them, Inky doubles the vector from Blinky to a point ahead
+    // of the player, and Clyde gives up and heads home once he gets close.
+    fn chase_target(&self, idx: usize) -> Pos {
+        let (dx, dy) = self.dir.map_or((0, 0), Dir::delta);
+        let ahead = |n: isize| {
+            (
+                self.player.x as isize + dx * n,
+                self.player.y as isize + dy * n,
+            )
+        };
+        let kind = self.ghost_kinds[idx];
+        let (x, y) = match kind {
+            GhostKind::Blinky => return self.player,
+            GhostKind::Pinky => ahead(PINKY_LEAD),
+            GhostKind::Inky => {
+                let blinky = self
+                    .ghost_kinds
+                    .iter()
+                    .position(|k| *k == GhostKind::Blinky)
+                    .map_or(self.player, |i| self.ghosts[i]);
+                let (x, y) = ahead(INKY_LEAD);
+                (2 * x - blinky.x as isize, 2 * y - blinky.y as isize)
+            }
+            GhostKind::Clyde => {
+                let ghost = self.ghosts[idx];
+                let gx = ghost.x as isize - self.player.x as isize;
+                let gy = ghost.y as isize - self.player.y as isize;
+                if gx * gx + gy * gy > CLYDE_SHY_DISTANCE * CLYDE_SHY_DISTANCE {
+                    return self.player;
+                }
+                let corner = kind.scatter_corner(self.width, self.height);
+                (corner.x as isize, corner.y as isize)
+            }
+        };
+        nearest_open_tile(&self.grid, &self.pen_bounds, x, y)
+    }
+
+    fn record_score_splits(&mut self) {
+        while let Some(&milestone) = self.split_scores.front() {
+            if self.score < milestone {
+                break;
+            }
+            self.split_scores.pop_front();
+            self.splits.push(Split {
+                kind: SplitKind::Score(milestone),
+                at: self.run_started.elapsed(),
+            });
+        }
+    }
+
+    // Maze rows uncovered so far by the level-start sweep.
+    pub fn revealed_rows(&self) -> usize {
+        if self.intro_ticks == 0 {
+            return self.height;
+        }
+        let done = REVEAL_TICKS.saturating_sub(self.intro_ticks) as usize;
+        self.height * done / REVEAL_TICKS as usize
+    }
+
+    fn tick_power_timer(&mut self) {
+        if self.power_timer > 0 {
+            self.power_timer -= 1;
+        }
+        if self.power_timer == 0 {
+            self.ghost_chain = 0;
+            self.ghost_revived.fill(false);
+        }
+    }
+
+    // Every extra life goes through here, so none can take the player past
+    // the config's `max_lives`.
+    pub fn award_life(&mut self) {
+        self.lives = (self.lives + 1).min(self.config.max_lives);
+    }
+
+    fn tick_teleport_cooldowns(&mut self) {
+        self.player_teleport_cooldown = self.player_teleport_cooldown.saturating_sub(1);
+        for cooldown in &mut self.ghost_teleport_cooldown {
+            *cooldown = cooldown.saturating_sub(1);
+        }
+    }
+
+    fn tick_speed_timer(&mut self) {
+        if self.speed_timer > 0 {
+            self.speed_timer -= 1;
+        }
+    }
+
+    // Speed shoes grant one extra legal step per tick while active.
+    fn player_steps(&self) -> u32 {
+        if self.speed_timer > 0 {
+            2
+        } else {
+            1
+        }
+    }
+
+    // `player_before` and `ghosts_before` are the positions at the start of the
+    // tick, so a ghost and Pac-Man trading places head-on still meet.
+    fn handle_collisions(
+        &mut self,
+        rng: &mut impl Rng,
+        player_before: Pos,
+        ghosts_before: &[Pos],
+        events: &mut Vec<GameEvent>,
+    ) {
+        let mut hit = None;
+        for (idx, ghost) in self.ghosts.iter().enumerate() {
+            let swapped = *ghost == player_before && ghosts_before[idx] == self.player;
+            if (*ghost == self.player || swapped) && self.ghost_states[idx] != GhostState::Eyes {
+                hit = Some(idx);
+                break;
+            }
+        }
+
+        if let Some(idx) = hit {
+            if self.ghost_frightened(idx) {
+                self.score += 200;
+                self.ghost_states[idx] = GhostState::Eyes;
+                self.ghost_last_seen[idx] = None;
+                self.ghost_chain += 1;
+                events.push(GameEvent::GhostEaten {
+                    chain: self.ghost_chain,
+                });
+            } else {
+                if self.lives > 0 {
+                    self.lives -= 1;
+                }
+                events.push(GameEvent::PlayerDied);
+                if self.config.death_freeze_ticks > 0 {
+                    self.death_freeze = self.config.death_freeze_ticks;
+                } else {
+                    self.reset_after_death(rng);
+                }
+            }
+        }
+    }
+
+    // Counts down the post-death freeze, resetting positions once it runs out.
+    // Returns true while the board should stay frozen.
+    fn tick_death_freeze(&mut self, rng: &mut impl Rng) -> bool {
+        if self.death_freeze == 0 {
+            return false;
+        }
+        self.death_freeze -= 1;
+        if self.death_freeze == 0 {
+            self.reset_after_death(rng);
+        }
+        true
+    }
+
+    // Wall-clock split timers shouldn't count time spent paused.
+    pub fn resume_clocks(&mut self, paused_for: Duration) {
+        self.run_started += paused_for;
+        self.level_started += paused_for;
+    }
+
+    pub fn skip_death_freeze(&mut self, rng: &mut impl Rng) {
+        if self.death_freeze > 0 {
+            self.death_freeze = 0;
+            self.reset_after_death(rng);
+        }
+    }
+
+    pub fn apply_skip(&mut self, skip: Skip, rng: &mut impl Rng) {
+        match skip {
+            Skip::Intro => self.intro_ticks = 0,
+            Skip::Freeze => self.skip_death_freeze(rng),
+        }
+    }
+
+    fn reset_after_death(&mut self, rng: &mut impl Rng) {
+        self.player = self.player_spawn;
+        self.ghosts = self.ghost_spawns.clone();
+        self.ghost_release.clear();
+        for i in 0..self.ghost_spawns.len() {
+            self.ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
+        }
+        self.ghost_last_seen = vec![None; self.ghost_spawns.len()];
+        self.ghost_dots = (0..self.ghost_spawns.len())
+            .map(|i| DEATH_DOT_LIMITS[i % 4])
+            .collect();
+        self.ghost_states = vec![GhostState::Normal; self.ghost_spawns.len()];
+        self.ghost_dirs = vec![None; self.ghost_spawns.len()];
+        self.ghost_reverse = vec![false; self.ghost_spawns.len()];
+        self.ghost_revived = vec![false; self.ghost_spawns.len()];
+        self.player_teleport_cooldown = 0;
+        self.ghost_teleport_cooldown = vec![0; self.ghost_spawns.len()];
+        self.queued_dir = None;
+        self.power_timer = 0;
+        self.ghost_chain = 0;
+        self.combo = 0;
+        self.speed_timer = 0;
+        self.bonus_pos = None;
+        self.bonus_timer = 0;
+        self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PenBounds {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+// Every level replays the custom map (or takes its turn in the rotation) if
+// there is one, else gets a fresh maze.
+fn level_maze(
+    config: &GameConfig,
+    level: u32,
+    rng: &mut impl Rng,
+) -> (Grid, usize, Vec<Pos>, PenBounds) {
+    match config.level_map(level) {
+        Some(map) => map.maze(),
+        None => generate_maze(rng, config.width, config.height, config.maze),
+    }
+}
+
+impl Game {
+    // Same seed, same maze, spawns and pellets: default settings only, so the
+    // PACMAN_* environment can't change the result.
+    pub fn with_seed(seed: u64, level: u32, width: usize, height: usize) -> Game {
+        let config = GameConfig {
+            width,
+            height,
+            level,
+            ..GameConfig::default()
+        };
+        Game::new_with_config(config, &mut StdRng::seed_from_u64(seed))
+    }
+
+    pub fn new_with_config(mut config: GameConfig, rng: &mut impl Rng) -> Game {
+        if config.lives == 0 {
+            config.lives = START_LIVES;
+        }
+        if config.max_lives == 0 {
+            config.max_lives = MAX_LIVES;
+        }
+        config.lives = config.lives.min(config.max_lives);
+        if config.power_ticks == 0 {
+            config.power_ticks = POWER_TICKS;
+        }
+        (config.width, config.height) = match config.level_map(config.level) {
+            Some(map) => (map.grid.width(), map.grid.height()),
+            None => maze_size(config.width, config.height),
+        };
+        let (width, height) = (config.width, config.height);
+        let (grid, pellets_left, ghost_spawns, pen_bounds) = level_maze(&config, config.level, rng);
+        let spawn_distance = config.spawn_distance;
+        let player = match config.level_map(config.level).and_then(|m| m.player_spawn) {
+            Some(spawn) => spawn,
+            None => pick_player_spawn(&grid, &ghost_spawns, &pen_bounds, spawn_distance, rng),
+        };
+        let player_spawn = player;
+
+        let ghost_count = ghost_spawns.len();
+        let mut ghost_release = Vec::new();
+        for i in 0..ghost_count {
+            ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
+        }
+
+        let bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+        Game {
+            width,
+            height,
+            grid,
+            player,
+            player_spawn,
+            ghosts: ghost_spawns.clone(),
+            ghost_spawns,
+            score: 0,
+            lives: config.lives,
+            level: config.level,
+            pellets_left,
+            power_timer: 0,
+            dir: None,
+            queued_dir: None,
+            queued_ticks: 0,
+            ghost_timers: vec![0.0; ghost_count],
+            ghost_intervals: vec![ghost_move_interval(config.level); ghost_count],
+            ghost_release,
+            ghost_dots: ghost_dot_limits(config.level, ghost_count),
+            pen_bounds,
+            bonus_pos: None,
+            bonus_kind: BonusKind::Fruit(level_fruit(config.level)),
+            bonus_timer: 0,
+            bonus_spawn_in,
+            bonus_tray: Vec::new(),
+            speed_timer: 0,
+            death_freeze: 0,
+            ghost_last_seen: vec![None; ghost_count],
+            ghost_kinds: (0..ghost_count).map(GhostKind::for_index).collect(),
+            ghost_states: vec![GhostState::Normal; ghost_count],
+            ghost_dirs: vec![None; ghost_count],
+            ghost_reverse: vec![false; ghost_count],
+            ghost_revived: vec![false; ghost_count],
+            player_teleport_cooldown: 0,
+            ghost_teleport_cooldown: vec![0; ghost_count],
+            phase: GhostPhase::Scatter,
+            phase_timer: scatter_ticks(config.level, 0),
+            phase_round: 0,
+            ghost_chain: 0,
+            combo: 0,
+            ticks_since_eat: 0,
+            run_started: Instant::now(),
+            level_started: Instant::now(),
+            splits: Vec::new(),
+            split_scores: config.split_scores.iter().copied().collect(),
+            intro_ticks: if config.reveal { REVEAL_TICKS } else { 0 },
+            ready_ticks: config.ready_ticks,
+            ticks: 0,
+            config,
+            floods: FloodCache::default(),
+            rng: None,
+        }
+    }
+
+    // A game that keeps the random source it was built with, for the methods
+    // below. The same seed and inputs replay the same game.
+    pub fn seeded(config: GameConfig, seed: u64) -> Game {
+        Game::with_rng(config, Box::new(StdRng::seed_from_u64(seed)))
+    }
+
+    pub fn with_rng(config: GameConfig, mut rng: OwnRng) -> Game {
+        let mut game = Game::new_with_config(config, &mut rng);
+        game.rng = Some(rng);
+        game
+    }
+
+    pub fn set_rng(&mut self, rng: OwnRng) {
+        self.rng = Some(rng);
+    }
+
+    pub fn take_rng(&mut self) -> Option<OwnRng> {
+        self.rng.take()
+    }
+
+    // Runs `f` with the game's own random source, for the functions that take
+    // one. Panics if the game wasn't given one.
+    pub fn with_own_rng<T>(&mut self, f: impl FnOnce(&mut Game, &mut OwnRng) -> T) -> T {
+        let mut rng = self
+            .rng
+            .take()
+            .expect("game has no random source; build it with Game::seeded or set_rng");
+        let out = f(self, &mut rng);
+        self.rng = Some(rng);
+        out
+    }
+
+    // `tick` and `apply_skip` with the game's own random source.
+    pub fn tick(&mut self, desired_dir: Option<Dir>, input_active: bool) -> Vec<GameEvent> {
+        self.with_own_rng(|game, rng| tick(game, rng, desired_dir, input_active))
+    }
+
+    pub fn skip(&mut self, skip: Skip) {
+        self.with_own_rng(|game, rng| game.apply_skip(skip, rng))
+    }
+}
+
+pub fn next_level(game: &mut Game, rng: &mut impl Rng) {
+    game.finish_level();
+    restart_level(game, rng);
+}
+
+// Lays the current level out afresh on the config's maze, at whatever size
+// that is now; the score, lives and level carry over.
+pub fn restart_level(game: &mut Game, rng: &mut impl Rng) {
+    let (grid, pellets_left, ghost_spawns, pen_bounds) = level_maze(&game.config, game.level, rng);
+    (game.width, game.height) = (grid.width(), grid.height());
+    (game.config.width, game.config.height) = (game.width, game.height);
+    let spawn_distance = game.config.spawn_distance;
+    let map = game.config.level_map(game.level);
+    game.player = match map.and_then(|m| m.player_spawn) {
+        Some(spawn) => spawn,
+        None => pick_player_spawn(&grid, &ghost_spawns, &pen_bounds, spawn_distance, rng),
+    };
+    game.grid = grid;
+    // Floods of the old maze don't apply to the new one.
+    game.floods.clear();
+    game.pellets_left = pellets_left;
+    game.player_spawn = game.player;
+    game.ghost_spawns = ghost_spawns;
+    game.ghosts = game.ghost_spawns.clone();
+    game.ghost_release.clear();
+    for i in 0..game.ghost_spawns.len() {
+        game.ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
+    }
+    game.ghost_last_seen = vec![None; game.ghost_spawns.len()];
+    game.ghost_kinds = (0..game.ghost_spawns.len())
+        .map(GhostKind::for_index)
+        .collect();
+    game.ghost_dots = ghost_dot_limits(game.level, game.ghost_spawns.len());
+    game.ghost_states = vec![GhostState::Normal; game.ghost_spawns.len()];
+    game.ghost_dirs = vec![None; game.ghost_spawns.len()];
+    game.ghost_reverse = vec![false; game.ghost_spawns.len()];
+    game.ghost_revived = vec![false; game.ghost_spawns.len()];
+    game.player_teleport_cooldown = 0;
+    game.ghost_teleport_cooldown = vec![0; game.ghost_spawns.len()];
+    game.phase = GhostPhase::Scatter;
+    game.phase_timer = scatter_ticks(game.level, 0);
+    game.phase_round = 0;
+    game.ghost_chain = 0;
+    game.combo = 0;
+    game.pen_bounds = pen_bounds;
+    game.power_timer = 0;
+    game.speed_timer = 0;
+    game.death_freeze = 0;
+    game.intro_ticks = if game.config.reveal { REVEAL_TICKS } else { 0 };
+    game.ready_ticks = game.config.ready_ticks;
+    game.dir = None;
+    game.queued_dir = None;
+    game.ghost_timers = vec![0.0; game.ghost_spawns.len()];
+    game.ghost_intervals = vec![ghost_move_interval(game.level); game.ghost_spawns.len()];
+    game.bonus_pos = None;
+    game.bonus_timer = 0;
+    game.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
+}
+
+// What happened during a tick, in order, for sounds, popups or logging.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEvent {
+    PelletEaten,
+    PowerEaten,
+    BonusEaten { kind: BonusKind },
+    // `chain` counts ghosts eaten on the same power pellet, starting at 1.
+    GhostEaten { chain: u32 },
+    PlayerDied,
+    LevelCleared,
+}
+
+pub fn tick(
+    game: &mut Game,
+    rng: &mut impl Rng,
+    desired_dir: Option<Dir>,
+    input_active: bool,
+) -> Vec<GameEvent> {
+    let mut events = Vec::new();
+    if game.intro_ticks > 0 {
+        game.intro_ticks -= 1;
+        return events;
+    }
+    if game.ready_ticks > 0 {
+        game.ready_ticks -= 1;
+        return events;
+    }
+    if game.tick_death_freeze(rng) {
+        return events;
+    }
+    game.ticks += 1;
+    game.ticks_since_eat = game.ticks_since_eat.saturating_add(1);
+    if game.ticks_since_eat > COMBO_GAP_TICKS {
+        game.combo = 0;
+    }
+
+    game.tick_teleport_cooldowns();
+    let (player_before, ghosts_before) = (game.player, game.ghosts.clone());
+    let persistent = game.config.persistent_pellets;
+    let regions_before = persistent.then(|| game.regions_cleared());
+    game.apply_input(desired_dir, input_active);
+    for _ in 0..game.player_steps() {
+        game.move_player();
+        game.consume_tile(&mut events);
+        game.try_collect_bonus(rng, &mut events);
+    }
+    game.record_score_splits();
+
+    // The counter is what ends the level, so debug builds check it against the board.
+    debug_assert_eq!(
+        game.pellets_left,
+        count_pellets(&game.grid),
+        "pellets_left drifted from the grid"
+    );
+    if game.pellets_left == 0 {
+        next_level(game, rng);
+        events.push(GameEvent::LevelCleared);
+        return events;
+    }
+    if let Some(before) = regions_before {
+        for _ in before..game.regions_cleared() {
+            game.clear_region();
+            events.push(GameEvent::LevelCleared);
+        }
+    }
+
+    game.update_bonus(rng);
+    game.tick_phase();
+    game.update_elroy();
+    game.update_ghosts(rng);
+    game.tick_power_timer();
+    game.tick_speed_timer();
+    game.handle_collisions(rng, player_before, &ghosts_before, &mut events);
+    game.record_score_splits();
+    events
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameSummary {
+    pub score: u32,
+    pub level: u32,
+    pub lives: u32,
+    // Ticks actually played; fewer than asked for if the game ended.
+    pub ticks: usize,
+}
+
+// Plays a default-sized game without a terminal. `inputs[i]` is the direction
+// held on tick i; `None`, or running past the end, means no key is held. The
+// same seed and inputs always give the same summary.
+pub fn simulate(seed: u64, inputs: &[Option<Dir>], ticks: usize) -> GameSummary {
+    let mut game = Game::seeded(GameConfig::default(), seed);
+    let mut played = 0;
+    while played < ticks && game.lives > 0 {
+        let input = inputs.get(played).copied().flatten();
+        game.tick(input, input.is_some());
+        played += 1;
+    }
+    GameSummary {
+        score: game.score,
+        level: game.level,
+        lives: game.lives,
+        ticks: played,
+    }
+}
+
+// Key presses that change the game between ticks rather than through `tick`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Skip {
+    Intro,
+    Freeze,
+}
+
+// One run's inputs: the direction held from each tick on (listed only when it
+// changes) and the skips pressed before a tick. With the seed and maze size
+// that is enough to play the run again exactly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub ticks: usize,
+    pub inputs: Vec<(usize, Option<Dir>)>,
+    pub skips: Vec<(usize, Skip)>,
+}
+
+impl Recording {
+    pub fn new(seed: u64, width: usize, height: usize) -> Recording {
+        Recording {
+            seed,
+            width,
+            height,
+            ..Recording::default()
+        }
+    }
+
+    // Call once per tick with the direction passed to `tick`.
+    pub fn record_tick(&mut self, dir: Option<Dir>) {
+        if self.inputs.last().and_then(|&(_, held)| held) != dir {
+            self.inputs.push((self.ticks, dir));
+        }
+        self.ticks += 1;
+    }
+
+    // Call when a skip is applied; it replays just before the next tick.
+    pub fn record_skip(&mut self, skip: Skip) {
+        self.skips.push((self.ticks, skip));
+    }
+}
+
+// Feeds a recording back one tick at a time.
+pub struct Playback {
+    pub recording: Recording,
+    tick: usize,
+    next_input: usize,
+    next_skip: usize,
+    held: Option<Dir>,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Playback {
+        Playback {
+            recording,
+            tick: 0,
+            next_input: 0,
+            next_skip: 0,
+            held: None,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.tick >= self.recording.ticks
+    }
+
+    // Applies the skips due before the next tick and returns the direction to
+    // pass to it.
+    pub fn next_tick(&mut self, game: &mut Game, rng: &mut impl Rng) -> Option<Dir> {
+        let rec = &self.recording;
+        while let Some(&(_, skip)) = rec.skips.get(self.next_skip).filter(|s| s.0 <= self.tick) {
+            game.apply_skip(skip, rng);
+            self.next_skip += 1;
+        }
+        while let Some(&(_, dir)) = rec.inputs.get(self.next_input).filter(|i| i.0 <= self.tick) {
+            self.held = dir;
+            self.next_input += 1;
+        }
+        self.tick += 1;
+        self.held
+    }
+}
+
+// Plays a recording on a fresh game built from `config` at the recorded size.
+pub fn replay(recording: &Recording, config: GameConfig) -> GameSummary {
+    let config = GameConfig {
+        width: recording.width,
+        height: recording.height,
+        ..config
+    };
+    let mut game = Game::seeded(config, recording.seed);
+    let mut playback = Playback::new(recording.clone());
+    let mut played = 0;
+    while !playback.finished() && game.lives > 0 {
+        let dir = game.with_own_rng(|game, rng| playback.next_tick(game, rng));
+        game.tick(dir, dir.is_some());
+        played += 1;
+    }
+    GameSummary {
+        score: game.score,
+        level: game.level,
+        lives: game.lives,
+        ticks: played,
+    }
+}
+
+pub fn save_recording(recording: &Recording, path: &Path) -> io::Result<()> {
+    std::fs::write(path, recording_text(recording))
+}
+
+fn recording_text(recording: &Recording) -> String {
+    let mut text = format!(
+        "version {}\nseed {}\nsize {} {}\nticks {}\n",
+        env!("CARGO_PKG_VERSION"),
+        recording.seed,
+        recording.width,
+        recording.height,
+        recording.ticks
+    );
+    for &(at, dir) in &recording.inputs {
+        text.push_str(&format!("{} {}\n", at, dir.map_or("none", dir_name)));
+    }
+    for &(at, skip) in &recording.skips {
+        let name = match skip {
+            Skip::Intro => "intro",
+            Skip::Freeze => "freeze",
+        };
+        text.push_str(&format!("{} skip {}\n", at, name));
+    }
+    text
+}
+
+fn dir_name(dir: Dir) -> &'static str {
+    match dir {
+        Dir::Up => "up",
+        Dir::Down => "down",
+        Dir::Left => "left",
+        Dir::Right => "right",
+    }
+}
+
+// A snapshot of a running game for bug reports: everything a save holds, with
+// the seed the run was started with so the first game can be replayed up to
+// `ticks` from a recording.
+pub fn save_state(game: &Game, seed: u64, path: &Path) -> io::Result<()> {
+    std::fs::write(path, state_json(game, seed))
+}
+
+// Hand-written like the high-score file, from game_fields: a field with one
+// value is written as that value and any other as an array, `none` as null.
+// The fields a save repeats per maze row, split and ghost become arrays too.
+fn state_json(game: &Game, seed: u64) -> String {
+    const REPEATED: [&str; 3] = ["grid", "split", "ghost"];
+    const LISTS: [&str; 2] = ["tray", "milestones"];
+    let value = |word: &String| match word.as_str() {
+        "none" => "null".to_string(),
+        "true" | "false" => word.clone(),
+        _ if word.parse::<f64>().is_ok_and(f64::is_finite) => word.clone(),
+        _ => format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"")),
+    };
+    let field = |key: &str, values: &[String]| match values {
+        [one] if !LISTS.contains(&key) => value(one),
+        _ => {
+            let values: Vec<String> = values.iter().map(value).collect();
+            format!("[{}]", values.join(", "))
+        }
+    };
+    let mut members = vec![format!("\"seed\": {}", seed)];
+    // game_fields keeps each repeated field's lines together.
+    let mut fields = game_fields(game).into_iter().peekable();
+    while let Some((key, values)) = fields.next() {
+        if !REPEATED.contains(&key) {
+            members.push(format!("\"{}\": {}", key, field(key, &values)));
+            continue;
+        }
+        let mut entries = vec![format!("    {}", field(key, &values))];
+        while let Some((_, values)) = fields.next_if(|(next, _)| *next == key) {
+            entries.push(format!("    {}", field(key, &values)));
+        }
+        members.push(format!("\"{}\": [\n{}\n  ]", key, entries.join(",\n")));
+    }
+    let members: Vec<String> = members.iter().map(|m| format!("  {}", m)).collect();
+    format!("{{\n{}\n}}\n", members.join(",\n"))
+}
+
+pub fn load_recording(path: &Path) -> Result<Recording, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_recording(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Recordings are `seed N`, `size W H` and `ticks N` lines followed by
+// `TICK DIR` (up, down, left, right or none) and `TICK skip intro|freeze` lines.
+// A `version` line names the release that wrote the file; it is only there for
+// bug reports, so any value is accepted.
+pub fn parse_recording(text: &str) -> Result<Recording, String> {
+    let mut rec = Recording::default();
+    let (mut seed, mut size, mut ticks) = (None, None, None);
+    for (i, line) in text.lines().enumerate() {
+        let bad = || format!("line {}: can't read '{}'", i + 1, line);
+        let num = |word: &str| word.parse::<usize>().map_err(|_| bad());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["version", _] => {}
+            ["seed", n] => seed = Some(n.parse::<u64>().map_err(|_| bad())?),
+            ["size", w, h] => size = Some((num(w)?, num(h)?)),
+            ["ticks", n] => ticks = Some(num(n)?),
+            [at, "skip", "intro"] => rec.skips.push((num(at)?, Skip::Intro)),
+            [at, "skip", "freeze"] => rec.skips.push((num(at)?, Skip::Freeze)),
+            [at, dir] => {
+                let dir = match *dir {
+                    "up" => Some(Dir::Up),
+                    "down" => Some(Dir::Down),
+                    "left" => Some(Dir::Left),
+                    "right" => Some(Dir::Right),
+                    "none" => None,
+                    _ => return Err(bad()),
+                };
+                rec.inputs.push((num(at)?, dir));
+            }
+            _ => return Err(bad()),
+        }
+    }
+    rec.seed = seed.ok_or("missing 'seed' line")?;
+    (rec.width, rec.height) = size.ok_or("missing 'size' line")?;
+    rec.ticks = ticks.ok_or("missing 'ticks' line")?;
+    let sorted = rec.inputs.windows(2).all(|w| w[0].0 <= w[1].0)
+        && rec.skips.windows(2).all(|w| w[0].0 <= w[1].0);
+    if !sorted {
+        return Err("ticks must be listed in order".to_string());
+    }
+    Ok(rec)
+}
+
+fn bonus_name(kind: BonusKind) -> &'static str {
+    match kind {
+        BonusKind::Fruit(Fruit::Cherry) => "cherry",
+        BonusKind::Fruit(Fruit::Strawberry) => "strawberry",
+        BonusKind::Fruit(Fruit::Orange) => "orange",
+        BonusKind::Fruit(Fruit::Apple) => "apple",
+        BonusKind::Fruit(Fruit::Melon) => "melon",
+        BonusKind::Fruit(Fruit::Galaxian) => "galaxian",
+        BonusKind::Fruit(Fruit::Bell) => "bell",
+        BonusKind::Fruit(Fruit::Key) => "key",
+        BonusKind::SpeedShoes => "speed_shoes",
+    }
+}
+
+// A game in progress, so load_game can pick it up exactly where it stopped.
+// The settings aren't saved; they come from the GameConfig it's loaded with.
+pub fn save_game(game: &Game, path: &Path) -> io::Result<()> {
+    std::fs::write(path, game_text(game))
+}
+
+fn game_text(game: &Game) -> String {
+    let lines: Vec<String> = game_fields(game)
+        .into_iter()
+        .map(|(key, values)| format!("{} {}", key, values.join(" ")))
+        .collect();
+    lines.join("\n") + "\n"
+}
+
+// Every field of a save in order, as its key and values: game_text writes one
+// `KEY VALUES` line for each and state_json one member, so the two can't drift.
+fn game_fields(game: &Game) -> Vec<(&'static str, Vec<String>)> {
+    let words = |values: &[&dyn Display]| values.iter().map(|v| v.to_string()).collect();
+    let pos = |pos: Pos| words(&[&pos.x, &pos.y]);
+    let maybe_pos = |at: Option<Pos>| at.map_or(words(&[&"none"]), pos);
+    let dir = |dir: Option<Dir>| dir.map_or("none", dir_name);
+    let mut fields = vec![("size", words(&[&game.width, &game.height]))];
+    for row in game.grid.rows() {
+        let row: String = row.iter().map(|&tile| tile_char(tile)).collect();
+        fields.push(("grid", vec![row]));
+    }
+    let pen = &game.pen_bounds;
+    fields.push(("pen", words(&[&pen.x0, &pen.y0, &pen.x1, &pen.y1])));
+    let (x, y) = (game.player.x, game.player.y);
+    fields.push(("player", words(&[&x, &y, &dir(game.dir)])));
+    fields.push(("spawn", pos(game.player_spawn)));
+    let queued = words(&[&dir(game.queued_dir), &game.queued_ticks]);
+    fields.push(("queued", queued));
+    for (key, value) in [
+        ("score", game.score),
+        ("lives", game.lives),
+        ("level", game.level),
+        ("power", game.power_timer),
+        ("speed", game.speed_timer),
+        ("freeze", game.death_freeze),
+        ("intro", game.intro_ticks),
+        ("ready", game.ready_ticks),
+        ("chain", game.ghost_chain),
+    ] {
+        fields.push((key, words(&[&value])));
+    }
+    fields.push(("combo", words(&[&game.combo, &game.ticks_since_eat])));
+    fields.push(("pellets", words(&[&game.pellets_left])));
+    fields.push(("ticks", words(&[&game.ticks])));
+    let mut teleport = words(&[&game.player_teleport_cooldown]);
+    teleport.extend(game.ghost_teleport_cooldown.iter().map(u32::to_string));
+    fields.push(("teleport", teleport));
+    let phase = match game.phase {
+        GhostPhase::Scatter => "scatter",
+        GhostPhase::Chase => "chase",
+    };
+    let phase = words(&[&phase, &game.phase_timer, &game.phase_round]);
+    fields.push(("phase", phase));
+    let mut bonus = maybe_pos(game.bonus_pos);
+    let kind = bonus_name(game.bonus_kind);
+    bonus.extend(words(&[&kind, &game.bonus_timer, &game.bonus_spawn_in]));
+    fields.push(("bonus", bonus));
+    let tray = game.bonus_tray.iter().map(|&kind| bonus_name(kind));
+    fields.push(("tray", tray.map(str::to_string).collect()));
+    let milestones = game.split_scores.iter().map(u32::to_string);
+    fields.push(("milestones", milestones.collect()));
+    let run = game.run_started.elapsed().as_secs_f64();
+    let level = game.level_started.elapsed().as_secs_f64();
+    fields.push(("clock", words(&[&run, &level])));
+    for split in &game.splits {
+        let at = split.at.as_secs_f64();
+        let split = match split.kind {
+            SplitKind::LevelCleared { level, took } => {
+                words(&[&"level", &level, &took.as_secs_f64(), &at])
+            }
+            SplitKind::Score(score) => words(&[&"score", &score, &at]),
+        };
+        fields.push(("split", split));
+    }
+    for idx in 0..game.ghosts.len() {
+        let state = match game.ghost_states[idx] {
+            GhostState::Normal if game.ghost_revived[idx] => "revived",
+            GhostState::Normal => "normal",
+            GhostState::Eyes => "eyes",
+        };
+        let mut ghost = pos(game.ghosts[idx]);
+        ghost.extend(pos(game.ghost_spawns[idx]));
+        ghost.extend(words(&[
+            &dir(game.ghost_dirs[idx]),
+            &state,
+            &game.ghost_release[idx],
+            &game.ghost_dots[idx],
+            &game.ghost_timers[idx],
+            &game.ghost_intervals[idx],
+            &game.ghost_reverse[idx],
+        ]));
+        ghost.extend(maybe_pos(game.ghost_last_seen[idx]));
+        fields.push(("ghost", ghost));
+    }
+    fields
+}
+
+pub fn load_game(path: &Path, config: GameConfig) -> Result<Game, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_game(&text, config).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Reads what game_text writes: one `KEY VALUES` line per field, with `grid`
+// once per maze row and `ghost` and `split` once per ghost and split.
+pub fn parse_game(text: &str, config: GameConfig) -> Result<Game, String> {
+    // Start from a fresh game for what a save doesn't carry (the config and
+    // caches) and overwrite everything it does.
+    let mut game = Game::new_with_config(config, &mut StdRng::seed_from_u64(0));
+    let (mut size, mut rows, mut ghosts) = (None, Vec::new(), 0);
+    let mut keys: Vec<&str> = Vec::new();
+    game.splits.clear();
+    for (i, line) in text.lines().enumerate() {
+        let bad = || format!("line {}: can't read '{}'", i + 1, line);
+        let num = |word: &str| word.parse::<usize>().map_err(|_| bad());
+        let int = |word: &str| word.parse::<u32>().map_err(|_| bad());
+        let secs = |word: &str| {
+            let secs = word.parse::<f64>().map_err(|_| bad())?;
+            Duration::try_from_secs_f64(secs).map_err(|_| bad())
+        };
+        let pos = |x: &str, y: &str| {
+            Ok::<_, String>(Pos {
+                x: num(x)?,
+                y: num(y)?,
+            })
+        };
+        let maybe_pos = |words: &[&str]| match words {
+            ["none"] => Ok(None),
+            [x, y] => pos(x, y).map(Some),
+            _ => Err(bad()),
+        };
+        let dir = |word: &str| match word {
+            "up" => Ok(Some(Dir::Up)),
+            "down" => Ok(Some(Dir::Down)),
+            "left" => Ok(Some(Dir::Left)),
+            "right" => Ok(Some(Dir::Right)),
+            "none" => Ok(None),
+            _ => Err(bad()),
+        };
+        let bonus = |word: &str| {
+            let mut kinds = FRUIT_TABLE.map(BonusKind::Fruit).to_vec();
+            kinds.push(BonusKind::SpeedShoes);
+            let found = kinds.into_iter().find(|&kind| bonus_name(kind) == word);
+            found.ok_or_else(bad)
+        };
+        if let Some(row) = line.strip_prefix("grid ") {
+            let row: Option<Vec<Tile>> = row.chars().map(char_tile).collect();
+            rows.push(row.ok_or_else(bad)?);
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => continue,
+            ["ghost", ..] | ["split", ..] => {}
+            [key, ..] if keys.contains(key) => {
+                return Err(format!("line {}: '{}' is given twice", i + 1, key));
+            }
+            [key, ..] => keys.push(key),
+        }
+        match words.as_slice() {
+            ["size", w, h] => size = Some((num(w)?, num(h)?)),
+            ["pen", x0, y0, x1, y1] => {
+                let (x0, y0, x1, y1) = (num(x0)?, num(y0)?, num(x1)?, num(y1)?);
+                game.pen_bounds = PenBounds { x0, y0, x1, y1 };
+            }
+            ["player", x, y, d] => (game.player, game.dir) = (pos(x, y)?, dir(d)?),
+            ["spawn", x, y] => game.player_spawn = pos(x, y)?,
+            ["queued", d, n] => (game.queued_dir, game.queued_ticks) = (dir(d)?, int(n)?),
+            ["score", n] => game.score = int(n)?,
+            ["lives", n] => game.lives = int(n)?,
+            ["level", n] => game.level = int(n)?,
+            ["power", n] => game.power_timer = int(n)?,
+            ["speed", n] => game.speed_timer = int(n)?,
+            ["freeze", n] => game.death_freeze = int(n)?,
+            ["intro", n] => game.intro_ticks = int(n)?,
+            ["ready", n] => game.ready_ticks = int(n)?,
+            ["chain", n] => game.ghost_chain = int(n)?,
+            // Saves from before combos have no such line; they start a fresh streak.
+            ["combo", n, since] => (game.combo, game.ticks_since_eat) = (int(n)?, int(since)?),
+            ["pellets", n] => game.pellets_left = num(n)?,
+            ["ticks", n] => game.ticks = n.parse().map_err(|_| bad())?,
+            // Saves from before teleport cooldowns have no such line; nobody is cooling down.
+            ["teleport", player, ghosts @ ..] => {
+                game.player_teleport_cooldown = int(player)?;
+                let ghosts = ghosts.iter().map(|n| int(n));
+                game.ghost_teleport_cooldown = ghosts.collect::<Result<_, _>>()?;
+            }
+            ["phase", phase, timer, round] => {
+                game.phase = match *phase {
+                    "scatter" => GhostPhase::Scatter,
+                    "chase" => GhostPhase::Chase,
+                    _ => return Err(bad()),
+                };
+                (game.phase_timer, game.phase_round) = (int(timer)?, int(round)?);
+            }
+            ["bonus", at @ .., kind, timer, spawn_in] => {
+                game.bonus_pos = maybe_pos(at)?;
+                game.bonus_kind = bonus(kind)?;
+                (game.bonus_timer, game.bonus_spawn_in) = (int(timer)?, int(spawn_in)?);
+            }
+            ["tray", kinds @ ..] => {
+                game.bonus_tray = kinds
+                    .iter()
+                    .map(|kind| bonus(kind))
+                    .collect::<Result<_, _>>()?;
+            }
+            ["milestones", scores @ ..] => {
+                game.split_scores = scores.iter().map(|n| int(n)).collect::<Result<_, _>>()?;
+            }
+            ["clock", run, level] => {
+                let now = Instant::now();
+                game.run_started = now.checked_sub(secs(run)?).unwrap_or(now);
+                game.level_started = now.checked_sub(secs(level)?).unwrap_or(now);
+            }
+            ["split", "level", level, took, at] => game.splits.push(Split {
+                kind: SplitKind::LevelCleared {
+                    level: int(level)?,
+                    took: secs(took)?,
+                },
+                at: secs(at)?,
+            }),
+            ["split", "score", score, at] => game.splits.push(Split {
+                kind: SplitKind::Score(int(score)?),
+                at: secs(at)?,
+            }),
+            ["ghost", x, y, sx, sy, d, state, release, dots, timer, interval, reverse, seen @ ..] =>
+            {
+                if ghosts == 0 {
+                    game.ghosts.clear();
+                    game.ghost_spawns.clear();
+                    game.ghost_dirs.clear();
+                    game.ghost_states.clear();
+                    game.ghost_release.clear();
+                    game.ghost_dots.clear();
+                    game.ghost_timers.clear();
+                    game.ghost_intervals.clear();
+                    game.ghost_reverse.clear();
+                    game.ghost_revived.clear();
+                    game.ghost_last_seen.clear();
+                }
+                game.ghosts.push(pos(x, y)?);
+                game.ghost_spawns.push(pos(sx, sy)?);
+                game.ghost_dirs.push(dir(d)?);
+                game.ghost_states.push(match *state {
+                    "normal" | "revived" => GhostState::Normal,
+                    "eyes" => GhostState::Eyes,
+                    _ => return Err(bad()),
+                });
+                game.ghost_revived.push(*state == "revived");
+                game.ghost_release.push(int(release)?);
+                game.ghost_dots.push(int(dots)?);
+                game.ghost_timers.push(timer.parse().map_err(|_| bad())?);
+                game.ghost_intervals
+                    .push(interval.parse().map_err(|_| bad())?);
+                game.ghost_reverse.push(reverse.parse().map_err(|_| bad())?);
+                game.ghost_last_seen.push(maybe_pos(seen)?);
+                ghosts += 1;
+            }
+            _ => return Err(bad()),
+        }
+    }
+    let required = [
+        "size",
+        "pen",
+        "player",
+        "spawn",
+        "queued",
+        "score",
+        "lives",
+        "level",
+        "power",
+        "speed",
+        "freeze",
+        "intro",
+        "ready",
+        "chain",
+        "pellets",
+        "ticks",
+        "phase",
+        "bonus",
+        "tray",
+        "milestones",
+        "clock",
+    ];
+    if let Some(key) = required.iter().find(|key| !keys.contains(key)) {
+        return Err(format!("missing '{}' line", key));
+    }
+    if ghosts == 0 {
+        return Err("missing 'ghost' lines".to_string());
+    }
+    if !keys.contains(&"teleport") {
+        game.ghost_teleport_cooldown = vec![0; ghosts];
+    } else if game.ghost_teleport_cooldown.len() != ghosts {
+        return Err(format!("'teleport' needs {} ghosts", ghosts));
+    }
+
+    let (width, height) = size.ok_or("missing 'size' line")?;
+    if rows.len() != height || rows.iter().any(|row| row.len() != width) {
+        return Err(format!("the grid isn't {}x{}", width, height));
+    }
+    if let Some(map) = game.config.level_map(game.level) {
+        let (map_w, map_h) = (map.grid.width(), map.grid.height());
+        if (map_w, map_h) != (width, height) {
+            return Err(format!(
+                "the save is {}x{} but the map is {}x{}",
+                width, height, map_w, map_h
+            ));
+        }
+    }
+    let mut grid = Grid::new(width, height, Tile::Wall);
+    for (y, row) in rows.iter().enumerate() {
+        grid[y].copy_from_slice(row);
+    }
+    let on_board = |pos: &Pos| pos.x < width && pos.y < height;
+    // Ghosts can stand in the gate; Pac-Man, and the bonus and the spots
+    // ghosts last saw him at, only where he can walk.
+    let ghost_spots = game.ghosts.iter().chain(&game.ghost_spawns).copied();
+    let ghost_spots: Vec<Pos> = ghost_spots.collect();
+    let player_spots = [game.player, game.player_spawn].into_iter();
+    let player_spots = player_spots.chain(game.bonus_pos);
+    let player_spots = player_spots.chain(game.ghost_last_seen.iter().flatten().copied());
+    let player_spots: Vec<Pos> = player_spots.collect();
+    if !ghost_spots.iter().chain(&player_spots).all(on_board) {
+        return Err(format!("a position is off the {}x{} board", width, height));
+    }
+    let walled = ghost_spots.iter().any(|&pos| grid.get(pos) == Tile::Wall);
+    let walkable = |pos: &Pos| PlayerMover.can_enter(grid.get(*pos));
+    if walled || !player_spots.iter().all(walkable) {
+        return Err("a position is inside a wall".to_string());
+    }
+    let pellets = count_pellets(&grid);
+    if game.pellets_left != pellets {
+        return Err(format!(
+            "'pellets' is {} but the grid has {}",
+            game.pellets_left, pellets
+        ));
+    }
+    (game.width, game.height, game.grid) = (width, height, grid);
+    (game.config.width, game.config.height) = (width, height);
+    game.ghost_kinds = (0..ghosts).map(GhostKind::for_index).collect();
+    Ok(game)
+}
+
+// Steps from every tile to the nearest ghost that can bite, -1 where none can
+// reach. Eyes are harmless, and so are frightened ghosts while at least
+// DEMO_CHASE_POWER ticks of power are left; power running lower than that
+// still counts as none, since a ghost about to recover bites.
+pub fn ghost_danger_field(game: &Game) -> Vec<Vec<i32>> {
+    let safe = game.power_timer >= DEMO_CHASE_POWER;
+    let threats: Vec<Pos> = (0..game.ghosts.len())
+        .filter(|&idx| game.ghost_states[idx] != GhostState::Eyes)
+        .filter(|&idx| !(safe && game.ghost_frightened(idx)))
+        .map(|idx| game.ghosts[idx])
+        .collect();
+    let dist = bfs_distance_multi(&game.grid, game.width, game.height, &threats, true);
+    (0..game.height).map(|y| dist[y].to_vec()).collect()
+}
+
+// The attract-mode player: heads for the nearest pellet, or the nearest ghost
+// while power lasts, preferring moves that stay clear of ghosts that can bite.
+pub fn demo_dir(game: &Game) -> Option<Dir> {
+    let (width, height) = (game.width, game.height);
+    let grid = &game.grid;
+    let from_player = bfs_distance(grid, width, height, game.player, false);
+    let reach = |pos: Pos| Some(from_player[pos.y][pos.x]).filter(|&d| d >= 0);
+    let edible: Vec<Pos> = (0..game.ghosts.len())
+        .filter(|&idx| game.ghost_frightened(idx))
+        .map(|idx| game.ghosts[idx])
+        .collect();
+    let chasing = game.power_timer >= DEMO_CHASE_POWER;
+
+    let prey = if chasing {
+        edible
+            .iter()
+            .filter_map(|&pos| reach(pos).map(|d| (d, pos)))
+            .min_by_key(|&(d, _)| d)
+    } else {
+        None
+    };
+    let target = prey.or_else(|| {
+        let mut best: Option<(i32, Pos)> = None;
+        for y in 0..height {
+            for x in 0..width {
+                if !matches!(grid[y][x], Tile::Pellet | Tile::Power) {
+                    continue;
+                }
+                let pos = Pos { x, y };
+                if let Some(d) = reach(pos) {
+                    if best.map_or(true, |(b, _)| d < b) {
+                        best = Some((d, pos));
+                    }
+                }
+            }
+        }
+        best
+    });
+    let to_target = target.map(|(_, pos)| bfs_distance(grid, width, height, pos, false));
+
+    let field = ghost_danger_field(game);
+    let danger = |pos: Pos| match field[pos.y][pos.x] {
+        -1 => i32::MAX,
+        d => d,
+    };
+
+    // Safe moves first, nearest the target; with none safe, the one farthest from a ghost.
+    [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        .into_iter()
+        .filter(|&dir| can_move_player(grid, width, height, game.player, dir))
+        .min_by_key(|&dir| {
+            let next = step(game.player, dir, width);
+            let near = danger(next);
+            let unsafe_move = near <= DEMO_DANGER_DIST;
+            let dist = to_target.as_ref().map_or(0, |dist| dist[next.y][next.x]);
+            (unsafe_move, if unsafe_move { -near } else { 0 }, dist)
+        })
+}
+
+// Random open tile outside the pen, at least `min_gate_dist` steps from the gate.
+// Small mazes may have no such tile; then the farthest one is used instead.
+fn pick_player_spawn(
+    grid: &Grid,
+    ghost_spawns: &[Pos],
+    pen: &PenBounds,
+    min_gate_dist: u32,
+    rng: &mut impl Rng,
+) -> Pos {
+    let (width, height) = (grid.width(), grid.height());
+    let mut candidates: Vec<Pos> = empty_cells(grid)
+        .into_iter()
+        .filter(|p| !ghost_spawns.contains(p) && !is_in_pen_bounds(*p, pen))
+        .collect();
+    candidates.shuffle(rng);
+    let gate = (0..height)
+        .flat_map(|y| (0..width).map(move |x| Pos { x, y }))
+        .find(|&p| grid.get(p) == Tile::Gate);
+    let dist = match gate {
+        Some(gate) if min_gate_dist > 0 => bfs_distance(grid, width, height, gate, true),
+        _ => return *candidates.first().expect("maze has empty cells"),
+    };
+    let gate_dist = |p: &Pos| dist[p.y][p.x];
+    candidates
+        .iter()
+        .copied()
+        .find(|p| gate_dist(p) >= min_gate_dist as i32)
+        .or_else(|| candidates.iter().copied().max_by_key(gate_dist))
+        .expect("maze has empty cells")
+}
+
+// Closest open tile outside the pen to a point that may be off the board or inside a wall.
+fn nearest_open_tile(grid: &Grid, pen: &PenBounds, x: isize, y: isize) -> Pos {
+    let (width, height) = (grid.width(), grid.height());
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    empty_cells(grid)
+        .into_iter()
+        .filter(|p| !is_in_pen_bounds(*p, pen))
+        .min_by_key(|p| p.x.abs_diff(x) + p.y.abs_diff(y))
+        .unwrap_or(Pos { x, y })
+}
+
+fn empty_cells(grid: &Grid) -> Vec<Pos> {
+    let mut cells = Vec::new();
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            if grid[y][x] != Tile::Wall && grid[y][x] != Tile::Gate {
+                cells.push(Pos { x, y });
+            }
+        }
+    }
+    cells
+}
+
+// Movement rules shared by everything that walks the grid. Implementors only
+// decide which tiles they may enter; bounds checks and stepping are common.
+pub trait Mover {
+    fn can_enter(&self, tile: Tile) -> bool;
+
+    fn can_move(&self, grid: &Grid, width: usize, height: usize, pos: Pos, dir: Dir) -> bool {
+        let ny = pos.y as isize + dir.delta().1;
+        if ny < 0 || ny as usize >= height {
+            return false;
+        }
+        let next = step(pos, dir, width);
+        self.can_enter(grid.get(next))
+    }
+
+    fn step(&self, pos: Pos, dir: Dir, width: usize) -> Pos {
+        step(pos, dir, width)
+    }
+
+    fn legal_moves(&self, grid: &Grid, width: usize, height: usize, pos: Pos) -> Vec<Dir> {
+        [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+            .into_iter()
+            .filter(|dir| self.can_move(grid, width, height, pos, *dir))
+            .collect()
+    }
+}
+
+pub struct PlayerMover;
+
+impl Mover for PlayerMover {
+    fn can_enter(&self, tile: Tile) -> bool {
+        !matches!(tile, Tile::Wall | Tile::Gate)
+    }
+}
+
+pub struct GhostMover {
+    pub gate_open: bool,
+}
+
+impl Mover for GhostMover {
+    fn can_enter(&self, tile: Tile) -> bool {
+        match tile {
+            Tile::Wall => false,
+            Tile::Gate => self.gate_open,
+            _ => true,
+        }
+    }
+}
+
+pub fn can_move_player(grid: &Grid, width: usize, height: usize, pos: Pos, dir: Dir) -> bool {
+    PlayerMover.can_move(grid, width, height, pos, dir)
+}
+
+pub fn can_move_ghost(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    pos: Pos,
+    dir: Dir,
+    gate_open: bool,
+) -> bool {
+    GhostMover { gate_open }.can_move(grid, width, height, pos, dir)
+}
+
+// Pellets and power pellets still on the board.
+pub fn count_pellets(grid: &Grid) -> usize {
+    grid.rows()
+        .flatten()
+        .filter(|t| matches!(t, Tile::Pellet | Tile::Power))
+        .count()
+}
+
+// Columns wrap around, so leaving one side of a tunnel row comes out the
+// other. Rows without a tunnel are walled at both ends, so nothing else wraps.
+pub fn step(pos: Pos, dir: Dir, width: usize) -> Pos {
+    let (dx, dy) = dir.delta();
+    Pos {
+        x: (pos.x as isize + dx).rem_euclid(width as isize) as usize,
+        y: (pos.y as isize + dy) as usize,
+    }
+}
+
+// Whether a mover may step `dir` from `pos`: anything but a tunnel wrap may,
+// and a wrap only once the mover's cooldown has run out, which then starts
+// over from `full`.
+fn pass_tunnel(left: &mut u32, full: u32, pos: Pos, dir: Dir, width: usize) -> bool {
+    if step(pos, dir, width).x.abs_diff(pos.x) <= 1 {
+        return true;
+    }
+    if *left > 0 {
+        return false;
+    }
+    *left = full;
+    true
+}
+
+pub fn bfs_distance(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    start: Pos,
+    gate_open: bool,
+) -> Distances {
+    let mut dist = Distances::default();
+    let mut queue = VecDeque::new();
+    bfs_distance_into(grid, width, height, start, gate_open, &mut dist, &mut queue);
+    dist
+}
+
+// bfs_distance into buffers the caller keeps, so repeated floods don't allocate.
+pub fn bfs_distance_into(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    start: Pos,
+    gate_open: bool,
+    dist: &mut Distances,
+    queue: &mut VecDeque<Pos>,
+) {
+    dist.reset(width, height);
+    queue.clear();
+    dist.set(start, 0);
+    queue.push_back(start);
+    spread(grid, width, height, gate_open, dist, queue);
+}
+
+// bfs_distance from several starts at once: every tile gets the steps to the
+// nearest of them, and no start at all leaves the whole map unreached.
+pub fn bfs_distance_multi(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    starts: &[Pos],
+    gate_open: bool,
+) -> Distances {
+    let mut dist = Distances::default();
+    let mut queue = VecDeque::new();
+    dist.reset(width, height);
+    for &start in starts {
+        if dist.get(start) == -1 {
+            dist.set(start, 0);
+            queue.push_back(start);
+        }
+    }
+    spread(grid, width, height, gate_open, &mut dist, &mut queue);
+    dist
+}
+
+// Runs a BFS outward from whatever is already queued in `dist`.
+fn spread(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    gate_open: bool,
+    dist: &mut Distances,
+    queue: &mut VecDeque<Pos>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let base = dist.get(pos);
+        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+            if !can_move_ghost(grid, width, height, pos, dir, gate_open) {
+                continue;
+            }
+            let next = step(pos, dir, width);
+            if dist.get(next) == -1 {
+                dist.set(next, base + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+// Ghosts never double back on `last` unless it is their only way out.
+fn ghost_turns(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    pos: Pos,
+    last: Option<Dir>,
+    gate_open: bool,
+) -> Vec<Dir> {
+    let mut legal: Vec<Dir> = [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        .into_iter()
+        .filter(|&dir| can_move_ghost(grid, width, height, pos, dir, gate_open))
+        .collect();
+    let back = last.map(Dir::opposite);
+    if legal.iter().any(|&dir| Some(dir) != back) {
+        legal.retain(|&dir| Some(dir) != back);
+    }
+    legal
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn ghost_next_dir(
+    pos: Pos,
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    dist: &Distances,
+    last: Option<Dir>,
+    rng: &mut impl Rng,
+    gate_open: bool,
+    fixed_ties: bool,
+) -> Option<Dir> {
+    let mut options = Vec::new();
+    let mut best = i32::MAX;
+    for dir in ghost_turns(grid, width, height, pos, last, gate_open) {
+        let next = step(pos, dir, width);
+        let d = dist[next.y][next.x];
+        if d >= 0 && d < best {
+            best = d;
+            options.clear();
+            options.push(dir);
+        } else if d >= 0 && d == best {
+            options.push(dir);
+        }
+    }
+    break_tie(&options, rng, fixed_ties)
+}
+
+// With `fixed` the first of `TIE_PRIORITY` among `options` wins and the RNG
+// is left untouched, so replays don't hinge on how many draws came before.
+fn break_tie(options: &[Dir], rng: &mut impl Rng, fixed: bool) -> Option<Dir> {
+    if fixed {
+        TIE_PRIORITY.into_iter().find(|dir| options.contains(dir))
+    } else {
+        options.choose(rng).copied()
+    }
+}
+
+// Frightened ghosts head for the tile farthest from Pac-Man, but now and
+// then take a random turn at a junction so they can't be herded exactly.
+#[allow(clippy::too_many_arguments)]
+pub fn ghost_next_dir_flee(
+    pos: Pos,
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    dist: &Distances,
+    last: Option<Dir>,
+    rng: &mut impl Rng,
+    gate_open: bool,
+    fixed_ties: bool,
+) -> Option<Dir> {
+    let turns = ghost_turns(grid, width, height, pos, last, gate_open);
+    if turns.len() > 1 && rng.gen_bool(FRIGHT_RANDOM_CHANCE) {
+        return turns.choose(rng).copied();
+    }
+    let mut options = Vec::new();
+    let mut best = -1;
+    for dir in turns {
+        let next = step(pos, dir, width);
+        let d = dist[next.y][next.x];
+        if d >= 0 && d > best {
+            best = d;
+            options.clear();
+            options.push(dir);
+        } else if d >= 0 && d == best {
+            options.push(dir);
+        }
+    }
+    break_tie(&options, rng, fixed_ties)
+}
+
+// True when `from` and `to` share a row or column with no wall or gate between them.
+fn has_line_of_sight(grid: &Grid, from: Pos, to: Pos) -> bool {
+    let blocked = |x: usize, y: usize| matches!(grid[y][x], Tile::Wall | Tile::Gate);
+    if from.x == to.x {
+        let (y0, y1) = (from.y.min(to.y), from.y.max(to.y));
+        (y0..=y1).all(|y| !blocked(from.x, y))
+    } else if from.y == to.y {
+        // Ghosts can see the long way round through a tunnel, like they can walk it.
+        let (x0, x1) = (from.x.min(to.x), from.x.max(to.x));
+        let clear = |x0: usize, x1: usize| (x0..=x1).all(|x| !blocked(x, from.y));
+        clear(x0, x1) || (clear(x1, grid.width() - 1) && clear(0, x0))
+    } else {
+        false
+    }
+}
+
+// Where a vision ghost is headed: Pac-Man while in sight, else the spot it
+// last saw him until it gets there, and nowhere (wander) after that.
+pub fn ghost_vision_target(
+    grid: &Grid,
+    pos: Pos,
+    player: Pos,
+    last_seen: &mut Option<Pos>,
+) -> Option<Pos> {
+    if has_line_of_sight(grid, pos, player) {
+        *last_seen = Some(player);
+    } else if *last_seen == Some(pos) {
+        *last_seen = None;
+    }
+    *last_seen
+}
+
+fn ghost_wander_dir(
+    pos: Pos,
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    last: Option<Dir>,
+    rng: &mut impl Rng,
+    gate_open: bool,
+) -> Option<Dir> {
+    ghost_turns(grid, width, height, pos, last, gate_open)
+        .choose(rng)
+        .copied()
+}
+
+#[derive(Clone)]
+pub struct CustomMap {
+    pub grid: Grid,
+    pub ghost_spawns: Vec<Pos>,
+    pub pen_bounds: PenBounds,
+    pub player_spawn: Option<Pos>,
+}
+
+impl CustomMap {
+    // Same shape generate_maze returns, so a level can come from either.
+    fn maze(&self) -> (Grid, usize, Vec<Pos>, PenBounds) {
+        (
+            self.grid.clone(),
+            count_pellets(&self.grid),
+            self.ghost_spawns.clone(),
+            self.pen_bounds,
+        )
+    }
+}
+
+pub fn save_maze(
+    grid: &Grid,
+    pen: &PenBounds,
+    player_spawn: Option<Pos>,
+    path: &Path,
+) -> io::Result<()> {
+    std::fs::write(path, maze_text(grid, pen, player_spawn))
+}
+
+fn tile_char(tile: Tile) -> char {
+    match tile {
+        Tile::Empty => ' ',
+        Tile::Wall => '#',
+        Tile::Pellet => '.',
+        Tile::Power => 'o',
+        Tile::Gate => '=',
+    }
+}
+
+fn char_tile(c: char) -> Option<Tile> {
+    match c {
+        ' ' => Some(Tile::Empty),
+        '#' => Some(Tile::Wall),
+        '.' => Some(Tile::Pellet),
+        'o' => Some(Tile::Power),
+        '=' => Some(Tile::Gate),
+        _ => None,
+    }
+}
+
+// Inverse of parse_maze. Every floor tile inside the pen becomes a `G`, so the
+// loaded pen has the same bounds and the same ghosts spawn first.
+fn maze_text(grid: &Grid, pen: &PenBounds, player_spawn: Option<Pos>) -> String {
+    let mut text = String::new();
+    for (y, row) in grid.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let pos = Pos { x, y };
+            text.push(match tile {
+                Tile::Empty if player_spawn == Some(pos) => 'P',
+                Tile::Empty if in_pen_interior(pos, pen) => 'G',
+                &tile => tile_char(tile),
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// The arcade layout, in map-file format: 28×31 with the pen in the middle and
+// a tunnel through each side.
+pub const CLASSIC_MAZE: [&str; 31] = [
+    "############################",
+    "#............##............#",
+    "#.####.#####.##.#####.####.#",
+    "#o####.#####.##.#####.####o#",
+    "#.####.#####.##.#####.####.#",
+    "#..........................#",
+    "#.####.##.########.##.####.#",
+    "#.####.##.########.##.####.#",
+    "#......##....##....##......#",
+    "######.##### ## #####.######",
+    "######.##### ## #####.######",
+    "######.##          ##.######",
+    "######.## ###=#### ##.######",
+    "######.## #GGGGGG# ##.######",
+    "      .   #GGGGGG#   .      ",
+    "######.## #GGGGGG# ##.######",
+    "######.## ######## ##.######",
+    "######.##          ##.######",
+    "######.## ######## ##.######",
+    "######.## ######## ##.######",
+    "#............##............#",
+    "#.####.#####.##.#####.####.#",
+    "#.####.#####.##.#####.####.#",
+    "#o..##.......P .......##..o#",
+    "###.##.##.########.##.##.###",
+    "###.##.##.########.##.##.###",
+    "#......##....##....##......#",
+    "#.##########.##.##########.#",
+    "#.##########.##.##########.#",
+    "#..........................#",
+    "############################",
+];
+
+pub fn classic_maze() -> CustomMap {
+    parse_maze(&CLASSIC_MAZE.join("\n")).expect("the classic maze is a valid map")
+}
+
+// Fixed-seed mazes that follow the classic one in the rotation.
+pub const ROTATION_MAPS: [&str; 3] = [
+    include_str!("../maps/rotation-1.txt"),
+    include_str!("../maps/rotation-2.txt"),
+    include_str!("../maps/rotation-3.txt"),
+];
+
+// The mazes a rotation cycles through, the classic one first.
+pub fn rotation_mazes() -> Vec<CustomMap> {
+    let rest = ROTATION_MAPS.iter().map(|text| parse_maze(text));
+    let rest = rest.map(|map| map.expect("the rotation maps are valid maps"));
+    std::iter::once(classic_maze()).chain(rest).collect()
+}
+
+pub fn load_maze(path: &Path) -> Result<CustomMap, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_maze(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Map files use `#` wall, `.` pellet, `o` power pellet, `=` pen gate and space
+// for empty floor. `P` marks the player spawn (optional) and each `G` a ghost
+// spawn; the pen is the box of walls around the `G`s.
+pub fn parse_maze(text: &str) -> Result<CustomMap, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let height = lines.len();
+    let width = lines.first().map_or(0, |l| l.chars().count());
+    if width < 3 || height < 3 {
+        return Err("a map needs at least 3 rows and 3 columns".to_string());
+    }
+    let mut grid = Grid::new(width, height, Tile::Wall);
+    let mut player_spawn = None;
+    let mut pen_spawns = Vec::new();
+    let mut gates = Vec::new();
+    for (y, line) in lines.iter().enumerate() {
+        let len = line.chars().count();
+        if len != width {
+            return Err(format!(
+                "line {} is {} characters wide, expected {}",
+                y + 1,
+                len,
+                width
+            ));
+        }
+        for (x, c) in line.chars().enumerate() {
+            let pos = Pos { x, y };
+            let at = || format!("line {}, column {}", y + 1, x + 1);
+            grid[y][x] = match c {
+                '#' => Tile::Wall,
+                '.' => Tile::Pellet,
+                'o' => Tile::Power,
+                ' ' => Tile::Empty,
+                '=' => {
+                    gates.push(pos);
+                    Tile::Gate
+                }
+                'G' => {
+                    pen_spawns.push(pos);
+                    Tile::Empty
+                }
+                'P' if player_spawn.is_some() => {
+                    return Err(format!("{}: second player spawn 'P'", at()));
+                }
+                'P' => {
+                    player_spawn = Some(pos);
+                    Tile::Empty
+                }
+                _ => return Err(format!("{}: unknown tile '{}'", at(), c)),
+            };
+            let edge = y == 0 || y == height - 1;
+            if edge && grid[y][x] != Tile::Wall {
+                return Err(format!("{}: the outer edge must be wall", at()));
+            }
+        }
+        // Open side edges are tunnels, which need an exit at both ends.
+        let (left, right) = (grid[y][0], grid[y][width - 1]);
+        let gated = left == Tile::Gate || right == Tile::Gate;
+        if gated || (left == Tile::Wall) != (right == Tile::Wall) {
+            return Err(format!(
+                "line {}: a tunnel needs an opening at both ends of the row",
+                y + 1
+            ));
+        }
+    }
+
+    if pen_spawns.is_empty() {
+        return Err("no ghost spawn 'G'".to_string());
+    }
+    let gate = match gates[..] {
+        [gate] => gate,
+        _ => return Err(format!("expected one gate '=', found {}", gates.len())),
+    };
+    // The pen wall rings the spawns, so a `G` on a tunnel row's open edge
+    // would put it outside the map.
+    let x0 = pen_spawns.iter().map(|p| p.x).min().unwrap_or(0);
+    let y0 = pen_spawns.iter().map(|p| p.y).min().unwrap_or(0);
+    let x1 = pen_spawns.iter().map(|p| p.x).max().unwrap_or(0) + 1;
+    let y1 = pen_spawns.iter().map(|p| p.y).max().unwrap_or(0) + 1;
+    let pen = match (x0.checked_sub(1), y0.checked_sub(1)) {
+        (Some(x0), Some(y0)) if x1 < width && y1 < height => PenBounds { x0, y0, x1, y1 },
+        _ => return Err("the ghost pen must sit inside the map".to_string()),
+    };
+    for y in pen.y0..=pen.y1 {
+        for x in pen.x0..=pen.x1 {
+            let pos = Pos { x, y };
+            let tile = grid[y][x];
+            if is_pen_wall(pos, &pen) && !matches!(tile, Tile::Wall | Tile::Gate) {
+                return Err(format!(
+                    "line {}, column {}: the ghost pen must be walled in",
+                    y + 1,
+                    x + 1
+                ));
+            }
+            if in_pen_interior(pos, &pen) && matches!(tile, Tile::Pellet | Tile::Power) {
+                return Err("pellets inside the ghost pen can't be eaten".to_string());
+            }
+        }
+    }
+    if !is_pen_wall(gate, &pen) {
+        return Err("the gate '=' must be part of the ghost pen wall".to_string());
+    }
+    if player_spawn.is_some_and(|p| is_in_pen_bounds(p, &pen)) {
+        return Err("the player spawn 'P' is inside the ghost pen".to_string());
+    }
+
+    let start = player_spawn
+        .or_else(|| find_start(&grid, width, height, &pen))
+        .ok_or("no open floor outside the ghost pen")?;
+    let reachable = flood(&grid, width, height, &pen, start);
+    if has_unreachable(&grid, width, height, &pen, &reachable) {
+        return Err("part of the maze can't be reached from the rest".to_string());
+    }
+    if count_pellets(&grid) == 0 {
+        return Err("the map has no pellets to eat".to_string());
+    }
+    let gate_opens = [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        .into_iter()
+        .filter(|d| GhostMover { gate_open: true }.can_move(&grid, width, height, gate, *d))
+        .map(|d| step(gate, d, width))
+        .any(|p| !is_in_pen_bounds(p, &pen) && reachable[p.y][p.x]);
+    if !gate_opens {
+        return Err("the gate '=' doesn't open onto the maze".to_string());
+    }
+
+    Ok(CustomMap {
+        grid,
+        ghost_spawns: pick_ghost_spawns(&pen_spawns),
+        pen_bounds: pen,
+        player_spawn,
+    })
+}
+
+pub fn generate_maze(
+    rng: &mut impl Rng,
+    width: usize,
+    height: usize,
+    options: MazeOptions,
+) -> (Grid, usize, Vec<Pos>, PenBounds) {
+    let mut grid = Grid::new(width, height, Tile::Wall);
+    let cells_w = (width - 1) / 2;
+    let cells_h = (height - 1) / 2;
+    // A symmetric maze carves up to and including the middle cell column.
+    let carve_w = if options.symmetric {
+        (cells_w + 1) / 2
+    } else {
+        cells_w
+    };
+    match options.gen {
+        MazeGen::Prim => carve_prim(&mut grid, carve_w, cells_h, rng),
+        MazeGen::Dfs => carve_dfs(&mut grid, carve_w, cells_h, rng),
+        MazeGen::Kruskal => carve_kruskal(&mut grid, carve_w, cells_h, rng),
+    }
+
+    braid_maze(&mut grid, carve_w, cells_h, options, rng);
+    remove_dead_ends(&mut grid, carve_w, cells_h, options.dead_ends, rng);
+    carve_rooms(&mut grid, width, height, options, rng);
+    if options.symmetric {
+        mirror_left_half(&mut grid);
+    }
+    carve_tunnels(&mut grid, width, height, options.tunnels);
+
+    let (pen_all, _door, pen_spawns, pen_bounds) = carve_ghost_pen(&mut grid, width, height);
+    ensure_connected(&mut grid, width, height, &pen_bounds);
+    if options.symmetric {
+        open_mirrored_walls(&mut grid, &pen_bounds);
+    }
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            if grid[y][x] == Tile::Empty && !pen_all.contains(&Pos { x, y }) {
+                grid[y][x] = Tile::Pellet;
+            }
+        }
+    }
+
+    let power_spots = [
+        Pos { x: 1, y: 1 },
+        Pos { x: width - 2, y: 1 },
+        Pos { x: 1, y: height - 2 },
+        Pos { x: width - 2, y: height - 2 },
+    ];
+    for pos in power_spots {
+        if grid.get(pos) != Tile::Wall {
+            grid.set(pos, Tile::Power);
+        }
+    }
+
+    // Ensure pen cells have no pellets (keep the gate intact).
+    for &pos in &pen_all {
+        if grid.get(pos) == Tile::Gate {
+            continue;
+        }
+        if grid.get(pos) != Tile::Wall {
+            grid.set(pos, Tile::Empty);
+        }
+    }
+
+    let ghost_spawns = pick_ghost_spawns(&pen_spawns);
+    let pellets = count_pellets(&grid);
+    (grid, pellets, ghost_spawns, pen_bounds)
+}
+
+// Copies the left half of every row onto the right; a middle column stays put.
+fn mirror_left_half(grid: &mut Grid) {
+    let width = grid.width();
+    for row in grid.rows_mut() {
+        for x in 0..width / 2 {
+            row[width - 1 - x] = row[x];
+        }
+    }
+}
+
+// ensure_connected opens one wall at a time, so a symmetric maze gets the
+// mirror image of each opening too. Opening walls can't disconnect anything.
+fn open_mirrored_walls(grid: &mut Grid, pen: &PenBounds) {
+    let width = grid.width();
+    for (y, row) in grid.rows_mut().enumerate() {
+        for x in 0..width {
+            let mirror = row[width - 1 - x];
+            let in_pen = is_in_pen_bounds(Pos { x, y }, pen);
+            if row[x] == Tile::Wall && mirror != Tile::Wall && !in_pen {
+                row[x] = mirror;
+            }
+        }
+    }
+}
+
+// Opens both ends of evenly spaced rows so they wrap around. Tunnel rows are
+// odd, so the first and last cell columns next to the openings are already open.
+fn carve_tunnels(grid: &mut Grid, width: usize, height: usize, tunnels: usize) {
+    let mut rows: Vec<usize> = (1..=tunnels)
+        .map(|i| (i * height / (tunnels + 1)) | 1)
+        .filter(|y| *y < height - 1)
+        .collect();
+    rows.dedup();
+    for y in rows {
+        grid[y][0] = Tile::Empty;
+        grid[y][width - 1] = Tile::Empty;
+    }
+}
+
+// Opens up to `options.rooms` rectangular rooms, each kept clear of the ghost pen and
+// its surrounding ring so the pen walls stay intact. ensure_connected later
+// stitches anything a room isolates back together.
+fn carve_rooms(
+    grid: &mut Grid,
+    width: usize,
+    height: usize,
+    options: MazeOptions,
+    rng: &mut impl Rng,
+) {
+    let count = options.rooms;
+    let (px0, py0, px1, py1) = pen_bounds(width, height);
+    let max_w = options.room_size.min(width.saturating_sub(2));
+    let max_h = options.room_size.min(height.saturating_sub(2));
+    if count == 0 || max_w < MIN_ROOM_SIZE || max_h < MIN_ROOM_SIZE {
+        return;
+    }
+
+    let mut carved = 0;
+    for _ in 0..count * 20 {
+        if carved == count {
+            break;
+        }
+        let w = rng.gen_range(MIN_ROOM_SIZE..=max_w);
+        let h = rng.gen_range(MIN_ROOM_SIZE..=max_h);
+        let x0 = rng.gen_range(1..=width - 1 - w);
+        let y0 = rng.gen_range(1..=height - 1 - h);
+        let (x1, y1) = (x0 + w - 1, y0 + h - 1);
+        let overlaps_pen = x0 <= px1 + 1 && x1 + 1 >= px0 && y0 <= py1 + 1 && y1 + 1 >= py0;
+        if overlaps_pen {
+            continue;
+        }
+        for row in grid.rows_mut().take(y1 + 1).skip(y0) {
+            for tile in row.iter_mut().take(x1 + 1).skip(x0) {
+                *tile = Tile::Empty;
+            }
+        }
+        carved += 1;
+    }
+}
+
+// Randomized Prim's: grows from a frontier, giving many short dead-end branches.
+fn carve_prim(grid: &mut Grid, cells_w: usize, cells_h: usize, rng: &mut impl Rng) {
+    let mut in_maze = vec![vec![false; cells_w]; cells_h];
+    let mut frontier: Vec<(usize, usize)> = Vec::new();
+
+    let start = (rng.gen_range(0..cells_w), rng.gen_range(0..cells_h));
+    in_maze[start.1][start.0] = true;
+    carve_cell(grid, start.0, start.1);
+    add_frontier(start.0, start.1, cells_w, cells_h, &in_maze, &mut frontier);
+
+    while !frontier.is_empty() {
+        let idx = rng.gen_range(0..frontier.len());
+        let (cx, cy) = frontier.swap_remove(idx);
+        if in_maze[cy][cx] {
+            continue;
+        }
+
+        let mut neighbors = Vec::new();
+        if cy > 0 && in_maze[cy - 1][cx] {
+            neighbors.push((cx, cy - 1));
+        }
+        if cy + 1 < cells_h && in_maze[cy + 1][cx] {
+            neighbors.push((cx, cy + 1));
+        }
+        if cx > 0 && in_maze[cy][cx - 1] {
+            neighbors.push((cx - 1, cy));
+        }
+        if cx + 1 < cells_w && in_maze[cy][cx + 1] {
+            neighbors.push((cx + 1, cy));
+        }
+
+        if neighbors.is_empty() {
+            continue;
+        }
+
+        let (nx, ny) = *neighbors.choose(rng).unwrap();
+        in_maze[cy][cx] = true;
+        carve_between(grid, cx, cy, nx, ny);
+        carve_cell(grid, cx, cy);
+        add_frontier(cx, cy, cells_w, cells_h, &in_maze, &mut frontier);
+    }
+}
+
+// Recursive backtracker: long, winding passages with few branches.
+fn carve_dfs(grid: &mut Grid, cells_w: usize, cells_h: usize, rng: &mut impl Rng) {
+    let mut visited = vec![vec![false; cells_w]; cells_h];
+    let start = (rng.gen_range(0..cells_w), rng.gen_range(0..cells_h));
+    visited[start.1][start.0] = true;
+    carve_cell(grid, start.0, start.1);
+    let mut stack = vec![start];
+
+    while let Some(&(cx, cy)) = stack.last() {
+        let mut neighbors = Vec::new();
+        if cy > 0 && !visited[cy - 1][cx] {
+            neighbors.push((cx, cy - 1));
+        }
+        if cy + 1 < cells_h && !visited[cy + 1][cx] {
+            neighbors.push((cx, cy + 1));
+        }
+        if cx > 0 && !visited[cy][cx - 1] {
+            neighbors.push((cx - 1, cy));
+        }
+        if cx + 1 < cells_w && !visited[cy][cx + 1] {
+            neighbors.push((cx + 1, cy));
+        }
+
+        match neighbors.choose(rng) {
+            Some(&(nx, ny)) => {
+                visited[ny][nx] = true;
+                carve_between(grid, cx, cy, nx, ny);
+                carve_cell(grid, nx, ny);
+                stack.push((nx, ny));
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+}
+
+// Randomized Kruskal: joins cells via shuffled walls, giving many short, evenly spread branches.
+fn carve_kruskal(grid: &mut Grid, cells_w: usize, cells_h: usize, rng: &mut impl Rng) {
+    let mut parent: Vec<usize> = (0..cells_w * cells_h).collect();
+    let mut edges = Vec::new();
+    for cy in 0..cells_h {
+        for cx in 0..cells_w {
+            carve_cell(grid, cx, cy);
+            if cx + 1 < cells_w {
+                edges.push((cx, cy, cx + 1, cy));
+            }
+            if cy + 1 < cells_h {
+                edges.push((cx, cy, cx, cy + 1));
+            }
+        }
+    }
+    edges.shuffle(rng);
+
+    for (cx, cy, nx, ny) in edges {
+        let a = find_root(&mut parent, cy * cells_w + cx);
+        let b = find_root(&mut parent, ny * cells_w + nx);
+        if a != b {
+            parent[a] = b;
+            carve_between(grid, cx, cy, nx, ny);
+        }
+    }
+}
+
+fn find_root(parent: &mut [usize], mut idx: usize) -> usize {
+    while parent[idx] != idx {
+        parent[idx] = parent[parent[idx]];
+        idx = parent[idx];
+    }
+    idx
+}
+
+fn add_frontier(
+    cx: usize,
+    cy: usize,
+    cells_w: usize,
+    cells_h: usize,
+    in_maze: &[Vec<bool>],
+    frontier: &mut Vec<(usize, usize)>,
+) {
+    if cy > 0 && !in_maze[cy - 1][cx] {
+        frontier.push((cx, cy - 1));
+    }
+    if cy + 1 < cells_h && !in_maze[cy + 1][cx] {
+        frontier.push((cx, cy + 1));
+    }
+    if cx > 0 && !in_maze[cy][cx - 1] {
+        frontier.push((cx - 1, cy));
+    }
+    if cx + 1 < cells_w && !in_maze[cy][cx + 1] {
+        frontier.push((cx + 1, cy));
+    }
+}
+
+fn carve_cell(grid: &mut Grid, cx: usize, cy: usize) {
+    let gx = cx * 2 + 1;
+    let gy = cy * 2 + 1;
+    grid[gy][gx] = Tile::Empty;
+}
+
+fn carve_between(grid: &mut Grid, cx: usize, cy: usize, nx: usize, ny: usize) {
+    let gx = cx * 2 + 1;
+    let gy = cy * 2 + 1;
+    let ngx = nx * 2 + 1;
+    let ngy = ny * 2 + 1;
+    let wall_x = (gx + ngx) / 2;
+    let wall_y = (gy + ngy) / 2;
+    grid[wall_y][wall_x] = Tile::Empty;
+}
+
+fn carve_ghost_pen(
+    grid: &mut Grid,
+    width: usize,
+    height: usize,
+) -> (Vec<Pos>, Pos, Vec<Pos>, PenBounds) {
+    let (x0, y0, x1, y1) = pen_bounds(width, height);
+
+    let mut pen_all = Vec::new();
+    let mut pen_spawns = Vec::new();
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            if y == y0 || y == y1 || x == x0 || x == x1 {
+                grid[y][x] = Tile::Wall;
+            } else {
+                grid[y][x] = Tile::Empty;
+                pen_all.push(Pos { x, y });
+                pen_spawns.push(Pos { x, y });
+            }
+        }
+    }
+
+    let door_x = (x0 + x1) / 2;
+    let door = Pos { x: door_x, y: y0 };
+    grid.set(door, Tile::Gate);
+    pen_all.push(door);
+
+    // Carve a vertical corridor from the gate upward until we hit open space,
+    // guaranteeing connectivity between the pen and the maze.
+    let mut y = door.y.saturating_sub(1);
+    while y > 0 {
+        if grid[y][door.x] != Tile::Wall {
+            break;
+        }
+        grid[y][door.x] = Tile::Empty;
+        y = y.saturating_sub(1);
+    }
+
+    (
+        pen_all,
+        door,
+        pen_spawns,
+        PenBounds { x0, y0, x1, y1 },
+    )
+}
+
+fn pick_ghost_spawns(pen_spawns: &[Pos]) -> Vec<Pos> {
+    let mut spawns = Vec::new();
+    if pen_spawns.is_empty() {
+        return spawns;
+    }
+    for pos in pen_spawns.iter().take(4) {
+        spawns.push(*pos);
+    }
+    while spawns.len() < 4 {
+        spawns.push(pen_spawns[0]);
+    }
+    spawns
+}
+
+fn pen_bounds(width: usize, height: usize) -> (usize, usize, usize, usize) {
+    let mut pen_w = PEN_W.min(width.saturating_sub(2));
+    let mut pen_h = PEN_H.min(height.saturating_sub(2));
+    if pen_w % 2 == 0 {
+        pen_w = pen_w.saturating_sub(1);
+    }
+    if pen_h % 2 == 0 {
+        pen_h = pen_h.saturating_sub(1);
+    }
+    pen_w = pen_w.max(3);
+    pen_h = pen_h.max(3);
+
+    let x0 = (width - pen_w) / 2;
+    let y0 = (height - pen_h) / 2;
+    let x1 = x0 + pen_w - 1;
+    let y1 = y0 + pen_h - 1;
+    (x0, y0, x1, y1)
+}
+
+fn in_pen_interior(pos: Pos, pen: &PenBounds) -> bool {
+    pos.x > pen.x0 && pos.x < pen.x1 && pos.y > pen.y0 && pos.y < pen.y1
+}
+
+fn is_pen_wall(pos: Pos, pen: &PenBounds) -> bool {
+    (pos.x >= pen.x0 && pos.x <= pen.x1 && (pos.y == pen.y0 || pos.y == pen.y1))
+        || (pos.y >= pen.y0 && pos.y <= pen.y1 && (pos.x == pen.x0 || pos.x == pen.x1))
+}
+
+fn ensure_connected(grid: &mut Grid, width: usize, height: usize, pen: &PenBounds) {
+    let start = find_start(grid, width, height, pen);
+    if start.is_none() {
+        return;
+    }
+    let mut reachable = flood(grid, width, height, pen, start.unwrap());
+
+    let mut iterations = 0;
+    while has_unreachable(grid, width, height, pen, &reachable) && iterations < width * height {
+        let mut carved = false;
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let pos = Pos { x, y };
+                if grid[y][x] != Tile::Wall {
+                    continue;
+                }
+                if is_pen_wall(pos, pen) {
+                    continue;
+                }
+                if grid[y][x] == Tile::Gate {
+                    continue;
+                }
+                let mut has_reach = false;
+                let mut has_unreach = false;
+                for (dx, dy) in [(0isize, -1isize), (0, 1), (-1, 0), (1, 0)] {
+                    let nx = (x as isize + dx) as usize;
+                    let ny = (y as isize + dy) as usize;
+                    let npos = Pos { x: nx, y: ny };
+                    if !is_walkable_for_player(grid, width, height, pen, npos) {
+                        continue;
+                    }
+                    if reachable[ny][nx] {
+                        has_reach = true;
+                    } else {
+                        has_unreach = true;
+                    }
+                }
+                if has_reach && has_unreach {
+                    grid[y][x] = Tile::Empty;
+                    carved = true;
+                    break;
+                }
+            }
+            if carved {
+                break;
+            }
+        }
+
+        if !carved {
+            break;
+        }
+        reachable = flood(grid, width, height, pen, start.unwrap());
+        iterations += 1;
+    }
+}
+
+fn find_start(grid: &Grid, width: usize, height: usize, pen: &PenBounds) -> Option<Pos> {
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let pos = Pos { x, y };
+            if is_walkable_for_player(grid, width, height, pen, pos) {
+                return Some(pos);
+            }
+        }
+    }
+    None
+}
+
+fn is_walkable_for_player(
+    grid: &Grid,
+    _width: usize,
+    _height: usize,
+    pen: &PenBounds,
+    pos: Pos,
+) -> bool {
+    if is_in_pen_bounds(pos, pen) {
+        return false;
+    }
+    !matches!(grid.get(pos), Tile::Wall | Tile::Gate)
+}
+
+fn is_in_pen_bounds(pos: Pos, pen: &PenBounds) -> bool {
+    pos.x >= pen.x0 && pos.x <= pen.x1 && pos.y >= pen.y0 && pos.y <= pen.y1
+}
+
+fn flood(grid: &Grid, width: usize, height: usize, pen: &PenBounds, start: Pos) -> Vec<Vec<bool>> {
+    let mut seen = vec![vec![false; width]; height];
+    let mut q = VecDeque::new();
+    seen[start.y][start.x] = true;
+    q.push_back(start);
+    while let Some(pos) = q.pop_front() {
+        for (dx, dy) in [(0isize, -1isize), (0, 1), (-1, 0), (1, 0)] {
+            let nx = pos.x as isize + dx;
+            let ny = pos.y as isize + dy;
+            if nx <= 0 || ny <= 0 || nx >= (width - 1) as isize || ny >= (height - 1) as isize
+            {
+                continue;
+            }
+            let nx = nx as usize;
+            let ny = ny as usize;
+            let npos = Pos { x: nx, y: ny };
+            if seen[ny][nx] {
+                continue;
+            }
+            if !is_walkable_for_player(grid, width, height, pen, npos) {
+                continue;
+            }
+            seen[ny][nx] = true;
+            q.push_back(npos);
+        }
+    }
+    seen
+}
+
+fn has_unreachable(
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    pen: &PenBounds,
+    reachable: &[Vec<bool>],
+) -> bool {
+    (1..height - 1).any(|y| {
+        (1..width - 1).any(|x| {
+            let pos = Pos { x, y };
+            is_walkable_for_player(grid, width, height, pen, pos) && !reachable[y][x]
+        })
+    })
+}
+
+pub fn ghost_next_dir_pen(
+    pos: Pos,
+    grid: &Grid,
+    width: usize,
+    height: usize,
+    pen: &PenBounds,
+    rng: &mut impl Rng,
+) -> Option<Dir> {
+    let mut options = Vec::new();
+    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+        if !can_move_ghost(grid, width, height, pos, dir, false) {
+            continue;
+        }
+        let next = step(pos, dir, width);
+        if in_pen_interior(next, pen) {
+            options.push(dir);
+        }
+    }
+    options.choose(rng).copied()
+}
+
+// Like the arcade's fruit spot: the free tile outside the pen nearest the gate,
+// along the way the ghosts leave, so the same level always uses the same tile
+// unless Pac-Man or a ghost is standing on it.
+fn fixed_bonus_spawn(game: &Game) -> Option<Pos> {
+    let pen = &game.pen_bounds;
+    let gate = (pen.y0..=pen.y1)
+        .flat_map(|y| (pen.x0..=pen.x1).map(move |x| Pos { x, y }))
+        .find(|&pos| game.grid.get(pos) == Tile::Gate)?;
+    let dist = bfs_distance(&game.grid, game.width, game.height, gate, true);
+    (0..game.height)
+        .flat_map(|y| (0..game.width).map(move |x| Pos { x, y }))
+        .filter(|&pos| dist.get(pos) > 0 && !is_in_pen_bounds(pos, pen))
+        .filter(|&pos| matches!(game.grid.get(pos), Tile::Empty | Tile::Pellet | Tile::Power))
+        .filter(|&pos| pos != game.player && !game.ghosts.contains(&pos))
+        .min_by_key(|&pos| dist.get(pos))
+}
+
+fn random_bonus_spawn(game: &Game, rng: &mut impl Rng) -> Option<Pos> {
+    let mut empty_candidates = Vec::new();
+    let mut pellet_candidates = Vec::new();
+    for y in 1..game.height - 1 {
+        for x in 1..game.width - 1 {
+            let tile = game.grid[y][x];
+            if tile != Tile::Empty && tile != Tile::Pellet && tile != Tile::Power {
+                continue;
+            }
+            let pos = Pos { x, y };
+            if is_in_pen_bounds(pos, &game.pen_bounds) {
+                continue;
+            }
+            if game.player == pos {
+                continue;
+            }
+            if game.ghosts.contains(&pos) {
+                continue;
+            }
+            if tile == Tile::Empty {
+                empty_candidates.push(pos);
+            } else {
+                pellet_candidates.push(pos);
+            }
+        }
+    }
+    if !empty_candidates.is_empty() {
+        return empty_candidates.choose(rng).copied();
+    }
+    pellet_candidates.choose(rng).copied()
+}
+
+fn braid_maze(
+    grid: &mut Grid,
+    cells_w: usize,
+    cells_h: usize,
+    options: MazeOptions,
+    rng: &mut impl Rng,
+) {
+    for cy in 0..cells_h {
+        for cx in 0..cells_w {
+            let open = cell_open_neighbors(grid, cx, cy, cells_w, cells_h);
+            let closed = cell_closed_neighbors(grid, cx, cy, cells_w, cells_h);
+
+            let braid = open.len() == 1 && !closed.is_empty() && rng.gen::<f32>() < options.braid;
+            if braid || (!closed.is_empty() && rng.gen::<f32>() < options.extra_openings) {
+                let dir = *closed.choose(rng).unwrap();
+                carve_between_dir(grid, cx, cy, dir);
+            }
+        }
+    }
+}
+
+// Opens a random dead end onto a closed neighbor until no more than
+// `max_share` of the cells are left with a single way in.
+fn remove_dead_ends(
+    grid: &mut Grid,
+    cells_w: usize,
+    cells_h: usize,
+    max_share: f32,
+    rng: &mut impl Rng,
+) {
+    if max_share >= 1.0 {
+        return;
+    }
+    let allowed = (cells_w * cells_h) as f32 * max_share.max(0.0);
+    loop {
+        let mut dead_ends = Vec::new();
+        for cy in 0..cells_h {
+            for cx in 0..cells_w {
+                let open = cell_open_neighbors(grid, cx, cy, cells_w, cells_h);
+                let closed = cell_closed_neighbors(grid, cx, cy, cells_w, cells_h);
+                if open.len() == 1 && !closed.is_empty() {
+                    dead_ends.push((cx, cy, closed));
+                }
+            }
+        }
+        if dead_ends.len() as f32 <= allowed {
+            return;
+        }
+        let (cx, cy, closed) = dead_ends.choose(rng).unwrap();
+        let dir = *closed.choose(rng).unwrap();
+        carve_between_dir(grid, *cx, *cy, dir);
+    }
+}
+
+fn carve_between_dir(grid: &mut Grid, cx: usize, cy: usize, dir: Dir) {
+    let (dx, dy) = dir.delta();
+    let nx = (cx as isize + dx) as usize;
+    let ny = (cy as isize + dy) as usize;
+    carve_between(grid, cx, cy, nx, ny);
+    carve_cell(grid, nx, ny);
+}
+
+fn cell_open_neighbors(
+    grid: &Grid,
+    cx: usize,
+    cy: usize,
+    cells_w: usize,
+    cells_h: usize,
+) -> Vec<Dir> {
+    let mut open = Vec::new();
+    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+        let (dx, dy) = dir.delta();
+        let nx = cx as isize + dx;
+        let ny = cy as isize + dy;
+        if nx < 0 || ny < 0 {
+            continue;
+        }
+        let nx = nx as usize;
+        let ny = ny as usize;
+        if nx >= cells_w || ny >= cells_h {
+            continue;
+        }
+        if is_open_between(grid, cx, cy, nx, ny) {
+            open.push(dir);
+        }
+    }
+    open
+}
+
+fn cell_closed_neighbors(
+    grid: &Grid,
+    cx: usize,
+    cy: usize,
+    cells_w: usize,
+    cells_h: usize,
+) -> Vec<Dir> {
+    let mut closed = Vec::new();
+    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+        let (dx, dy) = dir.delta();
+        let nx = cx as isize + dx;
+        let ny = cy as isize + dy;
+        if nx < 0 || ny < 0 {
+            continue;
+        }
+        let nx = nx as usize;
+        let ny = ny as usize;
+        if nx >= cells_w || ny >= cells_h {
+            continue;
+        }
+        if !is_open_between(grid, cx, cy, nx, ny) {
+            closed.push(dir);
+        }
+    }
+    closed
+}
+
+fn is_open_between(grid: &Grid, cx: usize, cy: usize, nx: usize, ny: usize) -> bool {
+    let gx = cx * 2 + 1;
+    let gy = cy * 2 + 1;
+    let ngx = nx * 2 + 1;
+    let ngy = ny * 2 + 1;
+    let wall_x = (gx + ngx) / 2;
+    let wall_y = (gy + ngy) / 2;
+    grid[wall_y][wall_x] != Tile::Wall
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_game(seed: u64) -> (Game, StdRng) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            ..GameConfig::default()
+        };
+        let game = Game::new_with_config(config, &mut rng);
+        (game, rng)
+    }
+
+    // A direction Pac-Man can step from the spawn.
+    fn open_dir(game: &Game) -> Dir {
+        [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+            .into_iter()
+            .find(|&d| can_move_player(&game.grid, game.width, game.height, game.player, d))
+            .unwrap()
+    }
+
+    #[test]
+    fn reused_bfs_buffers_match_fresh_floods() {
+        let (game, _) = small_game(3);
+        let (w, h) = (game.width, game.height);
+        // Start from a bigger maze's leftovers, so stale sizes or steps would show.
+        let big = Game::with_seed(4, 1, DEFAULT_GRID_W, DEFAULT_GRID_H);
+        let (mut dist, mut queue) = (Distances::default(), VecDeque::new());
+        let (bw, bh) = (big.width, big.height);
+        bfs_distance_into(&big.grid, bw, bh, big.player, true, &mut dist, &mut queue);
+        for start in game.ghost_spawns.iter().copied().chain([game.player]) {
+            for gate_open in [false, true] {
+                bfs_distance_into(&game.grid, w, h, start, gate_open, &mut dist, &mut queue);
+                assert_eq!(dist, bfs_distance(&game.grid, w, h, start, gate_open));
+            }
+        }
+    }
+
+    #[test]
+    fn ghosts_only_turn_back_at_a_dead_end() {
+        // A dead-end corridor along row 1, with the target behind the ghost.
+        let (w, h) = (9, 3);
+        let mut grid = Grid::new(w, h, Tile::Wall);
+        for x in 1..w - 1 {
+            grid.set(Pos { x, y: 1 }, Tile::Empty);
+        }
+        let dist = bfs_distance(&grid, w, h, Pos { x: 1, y: 1 }, false);
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut pos, mut last) = (Pos { x: 3, y: 1 }, Some(Dir::Right));
+        let mut trail = Vec::new();
+        while pos.x != 1 {
+            let dir = ghost_next_dir(pos, &grid, w, h, &dist, last, &mut rng, false, false);
+            pos = step(pos, dir.unwrap(), w);
+            last = dir;
+            trail.push(pos.x);
+        }
+        assert_eq!(trail, [4, 5, 6, 7, 6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn chasing_ghosts_never_go_back_into_the_pen() {
+        for seed in 0..6 {
+            let (mut game, mut rng) = small_game(seed);
+            let pen = game.pen_bounds;
+            let mut out = vec![false; game.ghosts.len()];
+            for round in 0..600 {
+                // Take turns chasing, scattering and fleeing.
+                game.phase = [GhostPhase::Chase, GhostPhase::Scatter][round / 150 % 2];
+                game.power_timer = if round % 200 < 60 { 10 } else { 0 };
+                game.update_ghosts(&mut rng);
+                for (idx, &pos) in game.ghosts.iter().enumerate() {
+                    if game.ghost_states[idx] == GhostState::Eyes {
+                        out[idx] = false;
+                    } else if !is_in_pen_bounds(pos, &pen) {
+                        out[idx] = true;
+                    } else {
+                        assert!(!out[idx], "seed {} ghost {} went back in", seed, idx);
+                    }
+                }
+            }
+            assert!(out.iter().any(|&o| o), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn fixed_ties_make_ghost_moves_independent_of_the_rng() {
+        let run = |fixed_ties: bool, rng_seed: u64| {
+            let (mut game, _) = small_game(7);
+            game.config.fixed_ties = fixed_ties;
+            game.ghost_release.fill(0);
+            let mut rng = StdRng::seed_from_u64(rng_seed);
+            let mut trail = Vec::new();
+            for _ in 0..200 {
+                game.update_ghosts(&mut rng);
+                trail.push(game.ghosts.clone());
+            }
+            (trail, rng.gen::<u64>())
+        };
+        let (fixed, untouched) = run(true, 1);
+        assert!(fixed == run(true, 2).0);
+        assert_eq!(untouched, StdRng::seed_from_u64(1).gen::<u64>());
+        assert!(run(false, 1).0 != run(false, 2).0);
+    }
+
+    #[test]
+    fn vision_ghosts_head_for_where_they_last_saw_pac_man() {
+        let rows = [
+            "###########",
+            "#.........#",
+            "#.###=###.#",
+            "#.#GGGGG#.#",
+            "#.#######.#",
+            "#.........#",
+            "###########",
+        ];
+        let config = GameConfig {
+            map: Some(parse_maze(&rows.join("\n")).unwrap()),
+            ghost_vision: true,
+            ..GameConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut game = Game::new_with_config(config, &mut rng);
+        game.ghost_release.fill(u32::MAX);
+        game.ghost_release[0] = 0;
+        game.ghosts[0] = Pos { x: 3, y: 1 };
+        game.player = Pos { x: 7, y: 1 };
+        while game.ghosts[0] == (Pos { x: 3, y: 1 }) {
+            game.update_ghosts(&mut rng);
+        }
+        assert_eq!(game.ghost_last_seen[0], Some(Pos { x: 7, y: 1 }));
+
+        // Round the corner: the way to Pac-Man is now back left, but the
+        // ghost keeps on to the spot it saw him, then forgets it.
+        game.player = Pos { x: 1, y: 4 };
+        let mut x = game.ghosts[0].x;
+        while game.ghost_last_seen[0].is_some() {
+            game.update_ghosts(&mut rng);
+            assert!(game.ghosts[0].y == 1 && game.ghosts[0].x >= x);
+            x = game.ghosts[0].x;
+        }
+        assert!(x >= 7);
+    }
+
+    #[test]
+    fn vision_reaches_through_tunnels() {
+        let rows = [
+            "###########",
+            "#...#.....#",
+            "....#......",
+            "#.###=###.#",
+            "#.#GGGGG#.#",
+            "#.#######.#",
+            "#.........#",
+            "###########",
+        ];
+        let map = parse_maze(&rows.join("\n")).unwrap();
+        let sees = |from: Pos, to: Pos| ghost_vision_target(&map.grid, from, to, &mut None);
+        let (left, right) = (Pos { x: 2, y: 2 }, Pos { x: 8, y: 2 });
+        assert_eq!(sees(left, right), Some(right));
+        assert_eq!(sees(right, left), Some(left));
+        // The row above has the same wall in the middle but no tunnel.
+        let (left, right) = (Pos { x: 2, y: 1 }, Pos { x: 8, y: 1 });
+        assert_eq!(sees(left, right), None);
+    }
+
+    #[test]
+    fn teleport_cooldown_keeps_the_player_from_going_straight_back() {
+        let rows = [
+            "###########",
+            "#...#.....#",
+            "....#......",
+            "#.###=###.#",
+            "#.#GGGGG#.#",
+            "#.#######.#",
+            "#.........#",
+            "###########",
+        ];
+        let through = |cooldown| {
+            let config = GameConfig {
+                map: Some(parse_maze(&rows.join("\n")).unwrap()),
+                teleport_cooldown: cooldown,
+                ..GameConfig::default()
+            };
+            let mut rng = StdRng::seed_from_u64(5);
+            let mut game = Game::new_with_config(config, &mut rng);
+            game.ghost_release.fill(u32::MAX);
+            game.player = Pos { x: 0, y: 2 };
+            tick(&mut game, &mut rng, Some(Dir::Left), true);
+            assert_eq!(game.player, Pos { x: 10, y: 2 });
+            // Ticks spent pushing back into the tunnel before it lets him through.
+            let mut held = 0;
+            while game.player.x == 10 {
+                tick(&mut game, &mut rng, Some(Dir::Right), true);
+                held += 1;
+            }
+            assert_eq!(game.player, Pos { x: 0, y: 2 });
+            held - 1
+        };
+        assert_eq!(through(0), 0);
+        assert_eq!(through(4), 3);
+    }
+
+    #[test]
+    fn debug_view_reuses_the_ghost_floods() {
+        let (mut game, mut rng) = small_game(3);
+        assert!(game.player_distances().is_none());
+        while game.player_distances().is_none() {
+            game.update_ghosts(&mut rng);
+        }
+        let (w, h) = (game.width, game.height);
+        let fresh = bfs_distance(&game.grid, w, h, game.player, false);
+        assert_eq!(game.player_distances(), Some(&fresh));
+        // Asking again is a hit; only a new start or gate rule floods afresh.
+        let used = game.floods.used;
+        let hit = game.floods.flood(&game.grid, (w, h), game.player, false);
+        assert_eq!((game.floods.used, game.floods.get(hit)), (used, &fresh));
+        let corner = Pos { x: 1, y: 1 };
+        let miss = game.floods.flood(&game.grid, (w, h), corner, true);
+        assert_eq!((game.floods.used, miss), (used + 1, used));
+        assert_eq!(game.floods.flood(&game.grid, (w, h), corner, true), miss);
+
+        game.ghost_release.fill(0);
+        game.ghost_states[1] = GhostState::Eyes;
+        let targets = game.ghost_targets();
+        assert_eq!(targets[0], Some(game.ghost_target(0)));
+        assert_eq!(targets[1], Some(game.ghost_spawns[1]));
+        game.power_timer = POWER_TICKS;
+        let targets = game.ghost_targets();
+        assert_eq!(targets[0], None);
+        assert_eq!(targets[1], Some(game.ghost_spawns[1]));
+    }
+
+    #[test]
+    fn state_dump_has_every_field_of_a_save() {
+        let (mut game, _) = small_game(3);
+        game.dir = Some(Dir::Left);
+        game.ghost_states[2] = GhostState::Eyes;
+        let json = state_json(&game, 3);
+        let size = format!("\"size\": [{}, {}],", game.width, game.height);
+        assert!(json.starts_with(&format!("{{\n  \"seed\": 3,\n  {}", size)));
+        let (x, y) = (game.player.x, game.player.y);
+        assert!(json.contains(&format!("\"player\": [{}, {}, \"left\"],", x, y)));
+        assert!(json.contains("\"score\": 0,") && json.contains("\"tray\": [],"));
+        assert_eq!(json.matches(", \"eyes\", ").count(), 1);
+        // One member per key a save has, whatever gets added to game_fields.
+        let text = game_text(&game);
+        let mut keys: Vec<&str> = text.lines().filter_map(|l| l.split(' ').next()).collect();
+        keys.dedup();
+        for key in keys {
+            let member = format!("\n  \"{}\": ", key);
+            assert_eq!(json.matches(&member).count(), 1, "{}", key);
+        }
+
+        // The grid rows read back as the same maze.
+        let rows: Vec<Vec<Tile>> = json
+            .lines()
+            .skip_while(|line| !line.contains("\"grid\""))
+            .skip(1)
+            .take(game.height)
+            .map(|line| line.trim().trim_end_matches(',').trim_matches('"'))
+            .map(|row| row.chars().map(|c| char_tile(c).unwrap()).collect())
+            .collect();
+        assert!(game.grid.rows().eq(rows.iter()));
+    }
+
+    #[test]
+    fn malformed_maps_say_what_is_wrong() {
+        let good = [
+            "#########",
+            "#o.....o#",
+            "#.##=##.#",
+            "#.#GGG#.#",
+            "#.#####.#",
+            "#...P...#",
+            "#########",
+        ];
+        assert!(parse_maze(&good.join("\n")).is_ok());
+        let cases = [
+            (5, "#...P..#", "line 6 is 8 characters wide, expected 9"),
+            (5, "#.P.P...#", "line 6, column 5: second player spawn 'P'"),
+            (1, "#ox....o#", "line 2, column 3: unknown tile 'x'"),
+            (
+                0,
+                "####.####",
+                "line 1, column 5: the outer edge must be wall",
+            ),
+            (3, "#.#   #.#", "no ghost spawn 'G'"),
+            (
+                3,
+                "#.#GGG..#",
+                "line 4, column 7: the ghost pen must be walled in",
+            ),
+            (
+                5,
+                "#..#P#..#",
+                "part of the maze can't be reached from the rest",
+            ),
+        ];
+        for (line, row, want) in cases {
+            let mut rows = good;
+            rows[line] = row;
+            assert_eq!(parse_maze(&rows.join("\n")).err().unwrap_or_default(), want);
+        }
+        let bare = good.join("\n").replace(['.', 'o'], " ");
+        assert_eq!(
+            parse_maze(&bare).err().unwrap_or_default(),
+            "the map has no pellets to eat"
+        );
+    }
+
+    #[test]
+    fn pens_on_a_tunnel_edge_are_rejected() {
+        let off_left = "#########\n#.......#\nG..P....G\n#..#=#..#\n#.......#\n#########";
+        let off_right = "#########\n#......##\n...P...=G\n#......##\n#.......#\n#########";
+        for text in [off_left, off_right] {
+            let err = parse_maze(text).err().unwrap_or_default();
+            assert_eq!(err, "the ghost pen must sit inside the map");
+        }
+    }
+
+    #[test]
+    fn saved_games_load_back_as_they_were() {
+        let (mut game, mut rng) = small_game(3);
+        for _ in 0..150 {
+            let dir = demo_dir(&game);
+            tick(&mut game, &mut rng, dir, dir.is_some());
+        }
+        game.power_timer = 17;
+        game.bonus_pos = Some(game.player_spawn);
+        game.bonus_timer = 40;
+        game.bonus_tray = vec![BonusKind::Fruit(Fruit::Key), BonusKind::SpeedShoes];
+        game.ghost_states[1] = GhostState::Eyes;
+        game.ghost_last_seen[2] = Some(game.player);
+        game.splits.push(Split {
+            kind: SplitKind::Score(1000),
+            at: Duration::from_millis(12_345),
+        });
+        game.run_started -= Duration::from_secs(90);
+        let text = game_text(&game);
+
+        let config = GameConfig {
+            lives: 1,
+            ..GameConfig::default()
+        };
+        let loaded = parse_game(&text, config).unwrap();
+        // Everything but the clocks, which kept running, comes back the same.
+        let lines = |text: &str| -> Vec<String> {
+            let lines = text.lines().filter(|line| !line.starts_with("clock"));
+            lines.map(String::from).collect()
+        };
+        assert_eq!(lines(&game_text(&loaded)), lines(&text));
+        assert!(loaded.grid == game.grid);
+        assert_eq!(loaded.lives, game.lives);
+        assert_eq!(loaded.ghost_release, game.ghost_release);
+        assert_eq!(loaded.ghost_timers, game.ghost_timers);
+        assert!(loaded.run_started.elapsed() >= Duration::from_secs(90));
+
+        let err = parse_game(&text.replace("size ", "size 9"), GameConfig::default());
+        assert!(err.err().unwrap().starts_with("the grid isn't"));
+        let pellets = format!("pellets {}", game.pellets_left);
+        let err = parse_game(&text.replace(&pellets, "pellets 1"), GameConfig::default());
+        let want = format!("'pellets' is 1 but the grid has {}", game.pellets_left);
+        assert_eq!(err.err().unwrap(), want);
+        let spawn = format!("spawn {} {}", game.player_spawn.x, game.player_spawn.y);
+        let err = parse_game(&text.replace(&spawn, "spawn 0 0"), GameConfig::default());
+        assert_eq!(err.err().unwrap(), "a position is inside a wall");
+        let missing: Vec<&str> = text.lines().filter(|l| !l.starts_with("pen")).collect();
+        let err = parse_game(&missing.join("\n"), GameConfig::default());
+        assert_eq!(err.err().unwrap(), "missing 'pen' line");
+    }
+
+    #[test]
+    fn demo_bot_clears_pellets() {
+        let (mut game, mut rng) = small_game(5);
+        let pellets = game.pellets_left;
+        for _ in 0..300 {
+            let dir = demo_dir(&game);
+            tick(&mut game, &mut rng, dir, dir.is_some());
+        }
+        assert!(game.pellets_left + 50 < pellets || game.level > 1);
+    }
+
+    #[test]
+    fn danger_field_is_the_nearest_biting_ghost() {
+        let (mut game, mut rng) = small_game(3);
+        for _ in 0..40 {
+            tick(&mut game, &mut rng, None, false);
+        }
+        game.ghost_states[0] = GhostState::Eyes;
+        let (w, h) = (game.width, game.height);
+        let floods: Vec<Distances> = game.ghosts[1..]
+            .iter()
+            .map(|&pos| bfs_distance(&game.grid, w, h, pos, true))
+            .collect();
+        let field = ghost_danger_field(&game);
+        for (y, row) in field.iter().enumerate() {
+            for (x, &d) in row.iter().enumerate() {
+                let nearest = floods.iter().map(|dist| dist[y][x]).filter(|&d| d >= 0);
+                assert_eq!(d, nearest.min().unwrap_or(-1), "at {},{}", x, y);
+            }
+        }
+
+        game.power_timer = DEMO_CHASE_POWER;
+        assert!(ghost_danger_field(&game).iter().flatten().all(|&d| d == -1));
+        game.power_timer = DEMO_CHASE_POWER - 1;
+        assert!(ghost_danger_field(&game) == field);
+    }
+
+    #[test]
+    fn seeded_games_play_like_ones_given_the_rng() {
+        let (mut game, mut rng) = small_game(9);
+        let config = game.config.clone();
+        let mut own = Game::seeded(config, 9);
+        for n in 0..400 {
+            let dir = demo_dir(&game);
+            let events = tick(&mut game, &mut rng, dir, dir.is_some());
+            assert!(own.tick(dir, dir.is_some()) == events, "tick {}", n);
+            if game.death_freeze > 0 {
+                game.apply_skip(Skip::Freeze, &mut rng);
+                own.skip(Skip::Freeze);
+            }
+        }
+        assert!(own.grid == game.grid && own.ghosts == game.ghosts);
+        assert_eq!((own.score, own.lives), (game.score, game.lives));
+
+        let mut moved = Game::with_rng(game.config.clone(), own.take_rng().unwrap());
+        assert!(own.take_rng().is_none());
+        moved.tick(None, false);
+    }
+
+    #[test]
+    fn simulate_is_stable_for_a_seed_and_inputs() {
+        let dirs = [
+            Some(Dir::Left),
+            None,
+            Some(Dir::Up),
+            Some(Dir::Right),
+            Some(Dir::Down),
+        ];
+        let inputs: Vec<Option<Dir>> = (0..600).map(|t| dirs[t / 15 % dirs.len()]).collect();
+        let summary = simulate(42, &inputs, 800);
+        assert_eq!(simulate(42, &inputs, 800), summary);
+        assert!(summary.score > 0);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut game = Game::new_with_config(GameConfig::default(), &mut rng);
+        let mut played = 0;
+        while played < 800 && game.lives > 0 {
+            let input = inputs.get(played).copied().flatten();
+            tick(&mut game, &mut rng, input, input.is_some());
+            played += 1;
+        }
+        let by_hand = GameSummary {
+            score: game.score,
+            level: game.level,
+            lives: game.lives,
+            ticks: played,
+        };
+        assert_eq!(by_hand, summary);
+    }
+
+    #[test]
+    fn replay_reproduces_a_recorded_run() {
+        let (mut game, mut rng) = small_game(11);
+        let mut rec = Recording::new(11, MIN_GRID_W, MIN_GRID_H);
+        while game.lives > 0 && rec.ticks < 2000 {
+            if game.death_freeze > 0 {
+                game.apply_skip(Skip::Freeze, &mut rng);
+                rec.record_skip(Skip::Freeze);
+            }
+            // Let go of the keys now and then so `none` entries get recorded too.
+            let dir = demo_dir(&game).filter(|_| rec.ticks % 7 != 0);
+            rec.record_tick(dir);
+            tick(&mut game, &mut rng, dir, dir.is_some());
+        }
+        let text = recording_text(&rec);
+        assert!(text.starts_with(&format!("version {}\n", env!("CARGO_PKG_VERSION"))));
+        let rec = parse_recording(&text).unwrap();
+        let summary = replay(&rec, GameConfig::default());
+        assert_eq!(summary.score, game.score);
+        assert_eq!(summary.level, game.level);
+        assert_eq!(summary.lives, game.lives);
+        assert_eq!(summary.ticks, rec.ticks);
+    }
+
+    fn unreachable_pellets(game: &Game) -> usize {
+        let dist = bfs_distance(&game.grid, game.width, game.height, game.player, false);
+        (0..game.height)
+            .flat_map(|y| (0..game.width).map(move |x| Pos { x, y }))
+            .filter(|&p| matches!(game.grid.get(p), Tile::Pellet | Tile::Power))
+            .filter(|&p| dist.get(p) < 0)
+            .count()
+    }
+
+    // A pellet Pac-Man can't reach makes the level impossible to clear.
+    #[test]
+    fn generated_mazes_are_solvable() {
+        let sizes = [
+            (MIN_GRID_W, MIN_GRID_H),
+            (22, 16),
+            (DEFAULT_GRID_W, DEFAULT_GRID_H),
+            (41, 25),
+        ];
+        let usual = (BRAID_CHANCE, EXTRA_OPENINGS, 1.0);
+        let layouts = [
+            (0, DEFAULT_ROOM_SIZE, 1, false, usual),
+            (4, MIN_ROOM_SIZE, 0, true, (0.0, 0.0, 1.0)),
+            (6, 9, 4, false, (1.0, 1.0, 1.0)),
+            (3, DEFAULT_ROOM_SIZE, 2, true, (1.0, 0.0, 0.0)),
+            (0, DEFAULT_ROOM_SIZE, 1, false, (0.0, 0.0, 0.0)),
+        ];
+        let mut configs = Vec::new();
+        for (width, height) in sizes {
+            for gen in [MazeGen::Prim, MazeGen::Dfs, MazeGen::Kruskal] {
+                for (rooms, room_size, tunnels, symmetric, openness) in layouts {
+                    let (braid, extra_openings, dead_ends) = openness;
+                    let maze = MazeOptions {
+                        gen,
+                        rooms,
+                        room_size,
+                        tunnels,
+                        symmetric,
+                        braid,
+                        extra_openings,
+                        dead_ends,
+                    };
+                    configs.push(GameConfig {
+                        width,
+                        height,
+                        maze,
+                        ..GameConfig::default()
+                    });
+                }
+            }
+        }
+        for (idx, config) in configs.into_iter().enumerate() {
+            for seed in 0..8 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut game = Game::new_with_config(config.clone(), &mut rng);
+                // Later levels carve fresh mazes from the same rng.
+                for _ in 0..2 {
+                    let lost = unreachable_pellets(&game);
+                    assert_eq!(lost, 0, "config {} seed {} level {}", idx, seed, game.level);
+                    next_level(&mut game, &mut rng);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn each_carver_alone_leaves_a_connected_tree() {
+        let (w, h) = (DEFAULT_GRID_W, DEFAULT_GRID_H);
+        let (cells_w, cells_h) = ((w - 1) / 2, (h - 1) / 2);
+        type Carver = fn(&mut Grid, usize, usize, &mut StdRng);
+        let carvers: [(&str, Carver); 3] = [
+            ("prim", carve_prim),
+            ("dfs", carve_dfs),
+            ("kruskal", carve_kruskal),
+        ];
+        for seed in 0..10 {
+            for (name, carve) in carvers {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut grid = Grid::new(w, h, Tile::Wall);
+                carve(&mut grid, cells_w, cells_h, &mut rng);
+                let dist = bfs_distance(&grid, w, h, Pos { x: 1, y: 1 }, false);
+                for cy in 0..cells_h {
+                    for cx in 0..cells_w {
+                        let d = dist[cy * 2 + 1][cx * 2 + 1];
+                        assert!(d >= 0, "{} seed {}: cell {},{} cut off", name, seed, cx, cy);
+                    }
+                }
+                // A spanning tree opens exactly one wall fewer than it has cells.
+                let cells = cells_w * cells_h;
+                let open = (0..h).flat_map(|y| (0..w).map(move |x| (x, y)));
+                let open = open.filter(|&(x, y)| grid[y][x] == Tile::Empty).count();
+                assert_eq!(open, 2 * cells - 1, "{} seed {}", name, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_are_open_rectangles_joined_to_the_maze() {
+        let (w, h) = (DEFAULT_GRID_W, DEFAULT_GRID_H);
+        let (cells_w, cells_h) = ((w - 1) / 2, (h - 1) / 2);
+        let options = MazeOptions {
+            rooms: 3,
+            ..MazeOptions::default()
+        };
+        let (px0, py0, px1, py1) = pen_bounds(w, h);
+        // Prim's leaves one-wide corridors, so only rooms hold an open square.
+        let open = |grid: &Grid, x: usize, y: usize, size: usize| {
+            (y..y + size).all(|y| (x..x + size).all(|x| grid[y][x] == Tile::Empty))
+        };
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut grid = Grid::new(w, h, Tile::Wall);
+            carve_prim(&mut grid, cells_w, cells_h, &mut rng);
+            carve_rooms(&mut grid, w, h, options, &mut rng);
+            let dist = bfs_distance(&grid, w, h, Pos { x: 1, y: 1 }, false);
+            let mut floor = 0;
+            for y in 0..h - 1 {
+                for x in 0..w - 1 {
+                    if !open(&grid, x, y, 2) {
+                        continue;
+                    }
+                    floor += 1;
+                    assert!(x >= 1 && y >= 1 && x + 2 < w && y + 2 < h);
+                    assert!(x + 1 < px0 || x > px1 || y + 1 < py0 || y > py1);
+                    assert!(dist[y][x] >= 0, "seed {}", seed);
+                }
+            }
+            assert!(floor > 0, "seed {}", seed);
+            let size = MIN_ROOM_SIZE;
+            let corners = (1..h - size).flat_map(|y| (1..w - size).map(move |x| (x, y)));
+            let sized = corners.filter(|&(x, y)| open(&grid, x, y, size)).count();
+            assert!(sized > 0, "seed {}", seed);
+            for cy in 0..cells_h {
+                for cx in 0..cells_w {
+                    assert!(dist[cy * 2 + 1][cx * 2 + 1] >= 0, "seed {}", seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn braiding_opens_the_maze_up() {
+        let floor = |braid, extra_openings| {
+            let maze = MazeOptions {
+                braid,
+                extra_openings,
+                ..MazeOptions::default()
+            };
+            let mut rng = StdRng::seed_from_u64(1);
+            let (grid, ..) = generate_maze(&mut rng, DEFAULT_GRID_W, DEFAULT_GRID_H, maze);
+            grid.rows().flatten().filter(|&&t| t != Tile::Wall).count()
+        };
+        let tight = floor(0.0, 0.0);
+        let default = floor(BRAID_CHANCE, EXTRA_OPENINGS);
+        let open = floor(1.0, 1.0);
+        assert!(tight < default, "{} vs {}", tight, default);
+        assert!(default < open, "{} vs {}", default, open);
+    }
+
+    #[test]
+    fn dead_end_pass_opens_up_every_dead_end() {
+        let (cells_w, cells_h) = ((DEFAULT_GRID_W - 1) / 2, (DEFAULT_GRID_H - 1) / 2);
+        let dead_ends = |grid: &Grid| {
+            let cells = (0..cells_h).flat_map(|cy| (0..cells_w).map(move |cx| (cx, cy)));
+            cells
+                .filter(|&(cx, cy)| cell_open_neighbors(grid, cx, cy, cells_w, cells_h).len() == 1)
+                .count()
+        };
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut grid = Grid::new(DEFAULT_GRID_W, DEFAULT_GRID_H, Tile::Wall);
+        carve_dfs(&mut grid, cells_w, cells_h, &mut rng);
+        let before = dead_ends(&grid);
+
+        let mut half = grid.clone();
+        remove_dead_ends(&mut half, cells_w, cells_h, 0.05, &mut rng);
+        let allowed = (cells_w * cells_h) as f32 * 0.05;
+        assert!(dead_ends(&half) as f32 <= allowed && dead_ends(&half) > 0);
+
+        remove_dead_ends(&mut grid, cells_w, cells_h, 0.0, &mut rng);
+        assert!(before > 0);
+        assert_eq!(dead_ends(&grid), 0);
+    }
+
+    #[test]
+    fn symmetric_mazes_mirror_left_to_right() {
+        let sizes = [
+            (MIN_GRID_W, MIN_GRID_H),
+            (DEFAULT_GRID_W, DEFAULT_GRID_H),
+            (33, 21),
+        ];
+        for (width, height) in sizes {
+            for gen in [MazeGen::Prim, MazeGen::Dfs, MazeGen::Kruskal] {
+                for seed in 0..20 {
+                    let maze = MazeOptions {
+                        gen,
+                        rooms: 2,
+                        symmetric: true,
+                        ..MazeOptions::default()
+                    };
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    let (grid, ..) = generate_maze(&mut rng, width, height, maze);
+                    let mirrored = |row: &[Tile]| {
+                        let walls = row.iter().map(|t| *t == Tile::Wall);
+                        walls.clone().eq(walls.rev())
+                    };
+                    assert!(grid.rows().all(mirrored), "{}x{} {}", width, height, seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_and_even_sizes_build_playable_mazes() {
+        let sizes = [0, 1, 2, 9, 14, 15, 16, 22, 30, MIN_GRID_W - 1, MIN_GRID_W];
+        for width in sizes {
+            for height in sizes {
+                let mut game = Game::with_seed(1, 1, width, height);
+                let (w, h) = maze_size(width, height);
+                assert_eq!((game.width, game.height), (w, h));
+                assert!(w >= MIN_GRID_W && h >= MIN_GRID_H && w % 2 == 1 && h % 2 == 1);
+                assert_eq!((game.grid.width(), game.grid.height()), (w, h));
+                assert!(game.pellets_left > 0);
+                assert_eq!(unreachable_pellets(&game), 0, "{}x{}", width, height);
+                let mut rng = StdRng::seed_from_u64(1);
+                next_level(&mut game, &mut rng);
+                assert_eq!(game.grid.width(), w);
+            }
+        }
+    }
+
+    #[test]
+    fn pellet_count_never_drifts() {
+        let dirs = [
+            None,
+            Some(Dir::Up),
+            Some(Dir::Down),
+            Some(Dir::Left),
+            Some(Dir::Right),
+        ];
+        for seed in 0..6 {
+            let (mut game, mut rng) = small_game(seed);
+            game.config.magnet = seed % 2 == 1;
+            game.lives = 20;
+            let mut inputs = StdRng::seed_from_u64(seed + 100);
+            let (mut dir, mut bot) = (None, false);
+            for _ in 0..2000 {
+                // Random stretches of one held direction or of the demo bot,
+                // which gets far enough to clear levels.
+                if inputs.gen_bool(0.1) {
+                    dir = *dirs.choose(&mut inputs).unwrap();
+                    bot = inputs.gen_bool(0.5);
+                }
+                let input = if bot { demo_dir(&game) } else { dir };
+                tick(&mut game, &mut rng, input, input.is_some());
+                assert_eq!(game.pellets_left, count_pellets(&game.grid));
+            }
+        }
+    }
+
+    #[test]
+    fn tick_reports_eating_a_pellet() {
+        let (mut game, mut rng) = small_game(3);
+        let dir = open_dir(&game);
+        let next = step(game.player, dir, game.width);
+        game.grid.set(next, Tile::Pellet);
+        game.pellets_left = count_pellets(&game.grid);
+        let events = tick(&mut game, &mut rng, Some(dir), true);
+        assert_eq!(events.first(), Some(&GameEvent::PelletEaten));
+    }
+
+    #[test]
+    fn tick_reports_deaths_and_ghost_chains() {
+        let (mut game, mut rng) = small_game(3);
+        game.ghosts[0] = game.player;
+        game.ghost_release[0] = 0;
+        let lives = game.lives;
+        let events = tick(&mut game, &mut rng, None, false);
+        assert!(events.contains(&GameEvent::PlayerDied));
+        assert_eq!(game.lives, lives - 1);
+
+        let (mut game, mut rng) = small_game(3);
+        game.power_timer = POWER_TICKS;
+        for chain in 1..=2 {
+            let idx = chain as usize - 1;
+            game.ghosts[idx] = game.player;
+            game.ghost_release[idx] = 0;
+            // Keep the ghosts from stepping off Pac-Man before the collision check.
+            game.ghost_timers.fill(0.0);
+            let events = tick(&mut game, &mut rng, None, false);
+            assert!(events.contains(&GameEvent::GhostEaten { chain }));
+        }
+    }
+
+    #[test]
+    fn eaten_ghosts_come_home_biting_while_power_lasts() {
+        let (mut game, mut rng) = small_game(3);
+        game.power_timer = 10_000;
+        game.ghosts[0] = game.player;
+        game.ghost_release[0] = 0;
+        game.ghost_timers.fill(0.0);
+        let events = tick(&mut game, &mut rng, None, false);
+        assert!(events.contains(&GameEvent::GhostEaten { chain: 1 }));
+        for _ in 0..500 {
+            if game.ghost_states[0] == GhostState::Normal {
+                break;
+            }
+            tick(&mut game, &mut rng, None, false);
+        }
+        assert!(game.ghost_states[0] == GhostState::Normal);
+        assert!(game.ghost_revived[0] && !game.ghost_frightened(0));
+        let last = game.ghosts.len() - 1;
+        assert!(game.ghost_frightened(last), "the others stay edible");
+
+        game.ghosts[0] = game.player;
+        game.ghost_release[0] = 0;
+        game.ghost_timers.fill(0.0);
+        let lives = game.lives;
+        let events = tick(&mut game, &mut rng, None, false);
+        assert!(game.power_timer > 0);
+        assert!(events.contains(&GameEvent::PlayerDied));
+        assert_eq!(game.lives, lives - 1);
+    }
+
+    #[test]
+    fn persistent_pellets_level_up_a_region_at_a_time() {
+        let (mut game, mut rng) = small_game(3);
+        game.config.persistent_pellets = true;
+        game.config.death_freeze_ticks = 0;
+        game.ghost_release.fill(u32::MAX);
+        let dir = open_dir(&game);
+        let next = step(game.player, dir, game.width);
+        let band = next.y * BOARD_REGIONS / game.height;
+        // Leave the band around `next` one pellet short of cleared.
+        for y in (0..game.height).filter(|y| y * BOARD_REGIONS / game.height == band) {
+            for x in 0..game.width {
+                if game.grid[y][x] != Tile::Wall && game.grid[y][x] != Tile::Gate {
+                    game.grid[y][x] = Tile::Empty;
+                }
+            }
+        }
+        game.grid.set(next, Tile::Pellet);
+        game.pellets_left = count_pellets(&game.grid);
+        assert_eq!(game.regions_cleared(), 0);
+
+        let events = tick(&mut game, &mut rng, Some(dir), true);
+        assert!(events.contains(&GameEvent::LevelCleared));
+        assert_eq!((game.level, game.regions_cleared()), (2, 1));
+        assert_eq!(game.player, next);
+        let board = (game.grid.clone(), game.pellets_left);
+
+        // A death sends everyone home but leaves the board as it was.
+        game.ghosts[0] = game.player;
+        game.ghost_release[0] = 0;
+        game.ghost_timers.fill(0.0);
+        game.dir = None;
+        let events = tick(&mut game, &mut rng, None, false);
+        assert!(events.contains(&GameEvent::PlayerDied));
+        assert_eq!(game.player, game.player_spawn);
+        assert!(game.grid == board.0 && game.pellets_left == board.1);
+        assert_eq!(game.level, 2);
+    }
+
+    #[test]
+    fn power_pellets_turn_the_ghosts_around() {
+        let (mut game, mut rng) = small_game(3);
+        game.ghost_release.fill(0);
+        for _ in 0..60 {
+            game.update_ghosts(&mut rng);
+        }
+        let (w, dir) = (game.width, open_dir(&game));
+        game.grid.set(step(game.player, dir, w), Tile::Power);
+        game.pellets_left = count_pellets(&game.grid);
+        let before = game.ghost_dirs.clone();
+        let out: Vec<bool> = (0..game.ghosts.len())
+            .map(|idx| !is_in_pen_bounds(game.ghosts[idx], &game.pen_bounds))
+            .collect();
+        assert!(out.iter().filter(|&&out| out).count() > 1);
+
+        let events = tick(&mut game, &mut rng, Some(dir), true);
+        assert!(events.contains(&GameEvent::PowerEaten));
+        for idx in 0..game.ghosts.len() {
+            if out[idx] && game.ghost_states[idx] != GhostState::Eyes {
+                let back = before[idx].map(Dir::opposite);
+                assert_eq!(game.ghost_dirs[idx], back, "ghost {}", idx);
+            }
+        }
+    }
+
+    #[test]
+    fn magnet_pulls_in_neighbouring_pellets_but_not_power() {
+        let (mut game, _) = small_game(3);
+        let (w, h) = (game.width, game.height);
+        let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+        let open = |game: &Game, pos| -> Vec<Pos> {
+            dirs.into_iter()
+                .filter(|&d| can_move_player(&game.grid, w, h, pos, d))
+                .map(|d| step(pos, d, w))
+                .collect()
+        };
+        game.player = (0..h)
+            .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+            .find(|&pos| game.grid.get(pos) != Tile::Wall && open(&game, pos).len() >= 3)
+            .unwrap();
+        let around = open(&game, game.player);
+        game.grid.set(game.player, Tile::Empty);
+        game.grid.set(around[0], Tile::Power);
+        for &pos in &around[1..] {
+            game.grid.set(pos, Tile::Pellet);
+        }
+        game.pellets_left = count_pellets(&game.grid);
+
+        let mut events = Vec::new();
+        game.consume_tile(&mut events);
+        assert!(events.is_empty());
+
+        game.config.magnet = true;
+        let (score, left) = (game.score, game.pellets_left);
+        game.consume_tile(&mut events);
+        assert_eq!(events.len(), around.len() - 1);
+        assert_eq!(game.score, score + 10 * (around.len() as u32 - 1));
+        assert_eq!(game.pellets_left, left - (around.len() - 1));
+        assert!(around[1..]
+            .iter()
+            .all(|&pos| game.grid.get(pos) == Tile::Empty));
+        assert!(game.grid.get(around[0]) == Tile::Power);
+    }
+
+    #[test]
+    fn spawns_keep_their_distance_from_the_pen_gate() {
+        for seed in 0..8 {
+            for spawn_distance in [12, 10_000] {
+                let config = GameConfig {
+                    spawn_distance,
+                    ..GameConfig::default()
+                };
+                let game = Game::new_with_config(config, &mut StdRng::seed_from_u64(seed));
+                let (w, h) = (game.width, game.height);
+                let gate = (0..h)
+                    .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+                    .find(|&p| game.grid.get(p) == Tile::Gate)
+                    .unwrap();
+                let dist = bfs_distance(&game.grid, w, h, gate, true);
+                let at = dist[game.player.y][game.player.x];
+                // Too far for the maze falls back to the farthest tile there is.
+                let tiles = (0..h).flat_map(|y| (0..w).map(move |x| (x, y)));
+                let farthest = tiles.map(|(x, y)| dist[y][x]).max().unwrap();
+                let want = (spawn_distance as i32).min(farthest);
+                assert!(at >= want, "seed {}: {} steps, wanted {}", seed, at, want);
+            }
+        }
+    }
+
+    #[test]
+    fn head_on_swaps_still_collide() {
+        let swap = |power: bool| {
+            let (mut game, mut rng) = small_game(3);
+            game.ghost_release.fill(u32::MAX);
+            game.ghost_release[0] = 0;
+            game.power_timer = if power { POWER_TICKS } else { 0 };
+            let dir = open_dir(&game);
+            let (a, b) = (game.player, step(game.player, dir, game.width));
+            // Turn ghost 0 around so its one move this tick is onto Pac-Man's tile.
+            game.ghosts[0] = b;
+            game.ghost_dirs[0] = Some(dir);
+            game.turn_around(0);
+            game.ghost_timers[0] = game.ghost_interval(0) - 1.0;
+            let events = tick(&mut game, &mut rng, Some(dir), true);
+            assert_eq!((game.player, game.ghosts[0]), (b, a));
+            events
+        };
+        assert!(swap(false).contains(&GameEvent::PlayerDied));
+        assert!(swap(true).contains(&GameEvent::GhostEaten { chain: 1 }));
+    }
+
+    #[test]
+    fn caught_holds_the_board_before_the_reset() {
+        let (mut game, mut rng) = small_game(3);
+        game.ghost_release.fill(0);
+        game.ghosts[0] = game.player;
+        tick(&mut game, &mut rng, None, false);
+        assert_eq!(game.death_freeze, DEATH_FREEZE_TICKS);
+
+        let dir = open_dir(&game);
+        let caught = (game.player, game.ghosts.clone(), game.ticks);
+        for _ in 1..DEATH_FREEZE_TICKS {
+            tick(&mut game, &mut rng, Some(dir), true);
+            assert!((game.player, game.ghosts.clone(), game.ticks) == caught);
+        }
+        tick(&mut game, &mut rng, None, false);
+        assert_eq!(game.death_freeze, 0);
+        assert!(game.player == game.player_spawn && game.ghosts == game.ghost_spawns);
+        let stagger: Vec<u32> = (0..game.ghosts.len() as u32)
+            .map(|i| i * GHOST_RELEASE_INTERVAL)
+            .collect();
+        assert_eq!(game.ghost_release, stagger);
+    }
+
+    #[test]
+    fn quick_pellets_build_a_combo() {
+        let run = |combo: bool, gap: u32| {
+            let mut rng = StdRng::seed_from_u64(3);
+            let config = GameConfig {
+                width: MIN_GRID_W,
+                height: MIN_GRID_H,
+                combo,
+                ..GameConfig::default()
+            };
+            let mut game = Game::new_with_config(config, &mut rng);
+            game.grid.set(game.player, Tile::Empty);
+            game.pellets_left = count_pellets(&game.grid);
+            game.ghost_release.fill(u32::MAX);
+            for _ in 0..COMBO_STEP * 2 {
+                game.score_pellet(10);
+                for _ in 0..gap {
+                    tick(&mut game, &mut rng, None, false);
+                }
+            }
+            (game.score, game.combo_multiplier())
+        };
+        // A streak of 2 * COMBO_STEP pellets: one step at x1, one at x2.
+        assert_eq!(run(true, 1), (COMBO_STEP * 30, 3));
+        assert_eq!(run(true, COMBO_GAP_TICKS), (COMBO_STEP * 30, 3));
+        assert_eq!(run(true, COMBO_GAP_TICKS + 1), (COMBO_STEP * 20, 1));
+        assert_eq!(run(false, 1), (COMBO_STEP * 20, 1));
+    }
+
+    #[test]
+    fn lives_and_power_come_from_the_config() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            lives: 5,
+            power_ticks: 30,
+            ..GameConfig::default()
+        };
+        let mut game = Game::new_with_config(config, &mut rng);
+        assert_eq!(game.lives, 5);
+        game.grid.set(game.player, Tile::Power);
+        game.consume_tile(&mut Vec::new());
+        assert_eq!(game.power_timer, 30);
+
+        // Nothing to play with falls back to the defaults.
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            lives: 0,
+            power_ticks: 0,
+            ..GameConfig::default()
+        };
+        let game = Game::new_with_config(config, &mut rng);
+        assert_eq!(game.lives, START_LIVES);
+        assert_eq!(game.config.power_ticks, POWER_TICKS);
+    }
+
+    #[test]
+    fn lives_never_pass_the_cap() {
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            lives: 40,
+            ..GameConfig::default()
+        };
+        let mut game = Game::seeded(config.clone(), 3);
+        assert_eq!(game.lives, MAX_LIVES);
+        game.lives = 1;
+        for _ in 0..10 {
+            game.award_life();
+            assert!(game.lives <= MAX_LIVES);
+        }
+        assert_eq!(game.lives, MAX_LIVES);
+
+        let config = GameConfig {
+            max_lives: 2,
+            ..config
+        };
+        let mut game = Game::seeded(config, 3);
+        game.award_life();
+        assert_eq!(game.lives, 2);
+    }
+
+    #[test]
+    fn ghosts_move_on_their_own_intervals() {
+        let (mut game, _) = small_game(3);
+        game.ghost_intervals = vec![1.0, 2.0, 4.0, 2.0];
+        game.ghost_states[3] = GhostState::Eyes;
+        let mut moves = [0; 4];
+        for _ in 0..8 {
+            for (idx, count) in moves.iter_mut().enumerate() {
+                *count += game.ghost_moves(idx);
+            }
+        }
+        assert_eq!(moves, [8, 4, 2, 8]);
+
+        game.power_timer = POWER_TICKS;
+        game.ghost_timers.fill(0.0);
+        let frightened: u32 = (0..6).map(|_| game.ghost_moves(0)).sum();
+        assert_eq!(frightened, 4);
+    }
+
+    #[test]
+    fn blinky_speeds_up_as_pellets_run_low() {
+        let (mut game, _) = small_game(3);
+        let blinky = 0;
+        assert!(game.ghost_kinds[blinky] == GhostKind::Blinky);
+        let other = (blinky + 1) % game.ghosts.len();
+        let [first, second] = elroy_thresholds(game.level);
+        assert!(elroy_thresholds(game.level + 3)[0] > first);
+
+        let mut speeds = Vec::new();
+        for left in [first + 1, first, second + 1, second] {
+            game.pellets_left = left;
+            game.update_elroy();
+            game.ghost_timers.fill(0.0);
+            let moves: u32 = (0..100).map(|_| game.ghost_moves(blinky)).sum();
+            speeds.push(moves);
+            assert_eq!(game.ghost_intervals[other], ghost_move_interval(game.level));
+        }
+        assert!(speeds[0] < speeds[1], "{:?}", speeds);
+        assert_eq!(speeds[1], speeds[2]);
+        assert!(speeds[2] < speeds[3], "{:?}", speeds);
+    }
+
+    #[test]
+    fn queued_turns_fire_at_the_next_opening_then_expire() {
+        let (mut game, mut rng) = small_game(3);
+        game.ghost_release.fill(u32::MAX);
+        let (w, h) = (game.width, game.height);
+        let open = |game: &Game, pos, dir| can_move_player(&game.grid, w, h, pos, dir);
+        let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+        // A tile with a turn that opens up one step further along.
+        let (pos, ahead, turn) = (0..h)
+            .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+            .flat_map(|pos| dirs.map(|ahead| (pos, ahead)))
+            .filter(|&(pos, ahead)| game.grid.get(pos) != Tile::Wall && open(&game, pos, ahead))
+            .find_map(|(pos, ahead)| {
+                let next = step(pos, ahead, w);
+                let turn = dirs
+                    .into_iter()
+                    .filter(|&t| t != ahead && t != ahead.opposite())
+                    .filter(|&t| !open(&game, pos, t))
+                    .find(|&t| open(&game, next, t))?;
+                Some((pos, ahead, turn))
+            })
+            .unwrap();
+
+        game.player = pos;
+        game.dir = Some(ahead);
+        tick(&mut game, &mut rng, Some(turn), true);
+        assert_eq!(game.player, step(pos, ahead, w));
+        assert_eq!(game.queued_dir, Some(turn));
+        tick(&mut game, &mut rng, Some(ahead), true);
+        assert_eq!(game.player, step(step(pos, ahead, w), turn, w));
+        assert_eq!((game.dir, game.queued_dir), (Some(turn), None));
+
+        game.player = pos;
+        game.dir = None;
+        game.apply_input(Some(turn), true);
+        assert_eq!(game.queued_dir, Some(turn));
+        for _ in 0..TURN_BUFFER_TICKS {
+            game.apply_input(None, true);
+        }
+        assert_eq!(game.queued_dir, None);
+    }
+
+    #[test]
+    fn speed_shoes_give_an_extra_step_until_they_run_out() {
+        let (mut game, mut rng) = small_game(3);
+        game.ghost_release.fill(u32::MAX);
+        let (w, h) = (game.width, game.height);
+        let open = |game: &Game, pos, dir| can_move_player(&game.grid, w, h, pos, dir);
+        // A straight run of three steps, so both ticks can go full length.
+        let (pos, dir) = (0..h)
+            .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+            .filter(|&pos| game.grid.get(pos) != Tile::Wall)
+            .flat_map(|pos| [Dir::Up, Dir::Down, Dir::Left, Dir::Right].map(|dir| (pos, dir)))
+            .find(|&(pos, dir)| {
+                let (a, b) = (step(pos, dir, w), step(step(pos, dir, w), dir, w));
+                open(&game, pos, dir) && open(&game, a, dir) && open(&game, b, dir)
+            })
+            .unwrap();
+
+        game.player = pos;
+        game.speed_timer = 1;
+        tick(&mut game, &mut rng, Some(dir), true);
+        let two = step(step(pos, dir, w), dir, w);
+        assert_eq!((game.player, game.speed_timer), (two, 0));
+        tick(&mut game, &mut rng, Some(dir), true);
+        assert_eq!(game.player, step(two, dir, w));
+    }
+
+    #[test]
+    fn classic_maze_is_playable() {
+        let map = classic_maze();
+        let grid = &map.grid;
+        let (w, h) = (grid.width(), grid.height());
+        assert_eq!((w, h), (28, 31));
+        let powers: Vec<Pos> = (0..h)
+            .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+            .filter(|&p| grid.get(p) == Tile::Power)
+            .collect();
+        assert_eq!(powers.len(), 4);
+        let quadrant = |p: &Pos| (p.x < w / 2, p.y < h / 2);
+        for corner in [(true, true), (false, true), (true, false), (false, false)] {
+            assert!(powers.iter().any(|p| quadrant(p) == corner));
+        }
+        let tunnels = grid.rows().filter(|row| row[0] != Tile::Wall).count();
+        assert_eq!(tunnels, 1);
+        let gates = grid.rows().filter(|row| row.contains(&Tile::Gate)).count();
+        assert_eq!(gates, 1);
+
+        let config = GameConfig {
+            map: Some(map.clone()),
+            ..GameConfig::default()
+        };
+        let game = Game::new_with_config(config, &mut StdRng::seed_from_u64(1));
+        assert_eq!(game.player, map.player_spawn.unwrap());
+        assert_eq!(game.pellets_left, count_pellets(&map.grid));
+        assert_eq!(unreachable_pellets(&game), 0);
+    }
+
+    #[test]
+    fn rotation_plays_the_next_maze_each_level() {
+        let rotation = rotation_mazes();
+        assert_eq!(rotation.len(), 4);
+        let config = GameConfig {
+            rotation: rotation.clone(),
+            ..GameConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut game = Game::new_with_config(config, &mut rng);
+        for level in 0..rotation.len() + 1 {
+            let map = &rotation[level % rotation.len()];
+            assert!(game.grid == map.grid);
+            assert_eq!(game.width, map.grid.width());
+            assert_eq!(game.pellets_left, count_pellets(&map.grid));
+            assert_eq!(unreachable_pellets(&game), 0);
+            next_level(&mut game, &mut rng);
+        }
+    }
+
+    #[test]
+    fn ready_pause_holds_everyone_until_it_runs_out() {
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            ready_ticks: 5,
+            ..GameConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut game = Game::new_with_config(config, &mut rng);
+        let dir = open_dir(&game);
+        for round in 0..2 {
+            let (player, ghosts) = (game.player, game.ghosts.clone());
+            for _ in 0..5 {
+                assert!(tick(&mut game, &mut rng, Some(dir), true).is_empty());
+                assert_eq!((game.player, &game.ghosts), (player, &ghosts));
+            }
+            assert_eq!(game.ready_ticks, 0);
+            if round == 0 {
+                next_level(&mut game, &mut rng);
+                assert_eq!(game.ready_ticks, 5);
+            }
+        }
+        let player = game.player;
+        let dir = open_dir(&game);
+        tick(&mut game, &mut rng, Some(dir), true);
+        assert_ne!(game.player, player);
+    }
+
+    #[test]
+    fn bonus_tray_keeps_the_latest_bonuses() {
+        let (mut game, mut rng) = small_game(3);
+        let cherry = BonusKind::Fruit(Fruit::Cherry);
+        let mut events = Vec::new();
+        for i in 0..BONUS_TRAY_LEN + 2 {
+            game.bonus_pos = Some(game.player);
+            game.bonus_kind = if i % 3 == 0 {
+                BonusKind::SpeedShoes
+            } else {
+                cherry
+            };
+            game.try_collect_bonus(&mut rng, &mut events);
+        }
+        assert_eq!(game.bonus_tray.len(), BONUS_TRAY_LEN);
+        assert_eq!(game.bonus_tray[0], cherry);
+        assert_eq!(game.bonus_tray[BONUS_TRAY_LEN - 1], cherry);
+        assert_eq!(game.bonus_tray[BONUS_TRAY_LEN - 3], BonusKind::SpeedShoes);
+    }
+
+    #[test]
+    fn fruit_follows_the_level() {
+        assert_eq!(level_fruit(1), Fruit::Cherry);
+        assert_eq!(level_fruit(4), Fruit::Orange);
+        assert_eq!(level_fruit(13), Fruit::Key);
+        assert_eq!(level_fruit(99), Fruit::Key);
+
+        let (mut game, mut rng) = small_game(3);
+        game.level = 5;
+        game.bonus_spawn_in = 0;
+        game.update_bonus(&mut rng);
+        assert_eq!(game.bonus_kind, BonusKind::Fruit(Fruit::Apple));
+        game.bonus_pos = Some(game.player);
+        let score = game.score;
+        game.try_collect_bonus(&mut rng, &mut Vec::new());
+        assert_eq!(game.score, score + 700);
+    }
+
+    #[test]
+    fn bonus_spawns_by_the_pen_gate() {
+        let (mut game, mut rng) = small_game(3);
+        let spawn = fixed_bonus_spawn(&game).unwrap();
+        let pen = game.pen_bounds;
+        let open = |grid: &Grid, pos| !matches!(grid.get(pos), Tile::Wall | Tile::Gate);
+        assert!(!is_in_pen_bounds(spawn, &pen) && open(&game.grid, spawn));
+        assert!(game.grid.get(step(spawn, Dir::Down, game.width)) == Tile::Gate);
+
+        game.bonus_spawn_in = 0;
+        game.update_bonus(&mut rng);
+        assert_eq!(game.bonus_pos, Some(spawn));
+
+        game.player = spawn;
+        let moved = fixed_bonus_spawn(&game).unwrap();
+        assert_ne!(moved, spawn);
+        assert!(open(&game.grid, moved));
+    }
+
+    #[test]
+    fn tick_reports_clearing_a_level() {
+        let (mut game, mut rng) = small_game(3);
+        let dir = open_dir(&game);
+        let next = step(game.player, dir, game.width);
+        for row in game.grid.rows_mut() {
+            for tile in row.iter_mut() {
+                if matches!(tile, Tile::Pellet | Tile::Power) {
+                    *tile = Tile::Empty;
+                }
+            }
+        }
+        game.grid.set(next, Tile::Pellet);
+        game.pellets_left = count_pellets(&game.grid);
+        let events = tick(&mut game, &mut rng, Some(dir), true);
+        let cleared = vec![GameEvent::PelletEaten, GameEvent::LevelCleared];
+        assert_eq!(events, cleared);
+        assert_eq!(game.level, 2);
+    }
+}
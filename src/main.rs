@@ -1,94 +1,135 @@
 use crossterm::cursor::{Hide, MoveTo, Show};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
 use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{ExecutableCommand, QueueableCommand};
-use rand::seq::SliceRandom;
-use rand::Rng;
-use std::collections::VecDeque;
+use pacman_game::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::io::{self, Stdout, Write};
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{Duration, Instant};
-use unicode_width::UnicodeWidthStr;
-
-const PEN_W: usize = 9;
-const PEN_H: usize = 5;
-const GHOST_RELEASE_INTERVAL: u32 = 90;
-const BONUS_MIN_TICKS: u32 = 600;
-const BONUS_MAX_TICKS: u32 = 1100;
-const BONUS_LIFETIME_TICKS: u32 = 260;
-const BONUS_SCORE: u32 = 200;
-const BONUS_POWER_BOOST: u32 = 40;
+use std::time::{Duration, Instant, SystemTime};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 const CELL_W: usize = 2;
 const DEFAULT_TICK_MS: u64 = 70;
-const POWER_TICKS: u32 = 90;
 const DEFAULT_RENDER_FPS: u64 = 120;
-const BRAID_CHANCE: f32 = 0.45;
-const EXTRA_OPENINGS: f32 = 0.08;
+const MIN_AUTO_FPS: u64 = 30;
+const CALIBRATION_FRAMES: u32 = 5;
 const INPUT_HOLD_MS: u64 = 160;
-const GHOST_MOVE_INTERVAL_BASE: f32 = 2.0;
-const GHOST_MOVE_INTERVAL_MIN: f32 = 0.7;
-const GHOST_SPEED_LEVEL_SCALE: f32 = 0.08;
-const MIN_GRID_W: usize = 21;
-const MIN_GRID_H: usize = 15;
-const DEFAULT_GRID_W: usize = 31;
-const DEFAULT_GRID_H: usize = 21;
+const BELL_PELLET_MS: u64 = 250;
+const POWER_BAR_W: usize = 10;
+const DEMO_IDLE_SECS: u64 = 30;
+const FLASH_SECS: u64 = 2;
+const MAX_HIGH_SCORES: usize = 10;
+const MAX_NAME_LEN: usize = 8;
+const AUTOSAVE_REPLAYS: usize = 5;
+const WATCH_POLL_MS: u64 = 250;
+const COORD_MARGIN_W: u16 = 3;
+const COORD_MARGIN_H: u16 = 2;
+const HEAT_NEAR: (u8, u8, u8) = (140, 30, 0);
+const HEAT_FAR: (u8, u8, u8) = (0, 30, 140);
+const HINT_BG: (u8, u8, u8) = (0, 110, 40);
+const GAME_OVER_BANNER: &str = r"  ___   _   __  __ ___    _____   _____ ___
+ / __| /_\ |  \/  | __|  / _ \ \ / / __| _ \
+| (_ |/ _ \| |\/| | _|  | (_) \ V /| _||   /
+ \___/_/ \_\_|  |_|___|  \___/ \_/ |___|_|_\";
 
 #[derive(Clone, Copy, PartialEq)]
-enum Tile {
-    Wall,
-    Empty,
-    Pellet,
-    Power,
-    Gate,
+enum InputScheme {
+    All,
+    Vi,
+    Arrow,
+    Gamer,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-struct Pos {
-    x: usize,
-    y: usize,
+fn read_input_scheme() -> InputScheme {
+    match std::env::var("PACMAN_INPUT")
+        .ok()
+        .map(|v| v.to_lowercase())
+        .as_deref()
+    {
+        Some("arrow") | Some("arrows") => InputScheme::Arrow,
+        Some("gamer") | Some("wasd") => InputScheme::Gamer,
+        Some("vi") | Some("vim") => InputScheme::Vi,
+        _ => InputScheme::All,
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-enum Dir {
-    Up,
-    Down,
-    Left,
-    Right,
+fn read_game_config() -> GameConfig {
+    GameConfig {
+        maze: read_maze_options(),
+        speed_shoes: read_speed_shoes_setting(),
+        random_bonus: read_random_bonus_setting(),
+        death_freeze_ticks: read_death_freeze_setting(),
+        ghost_vision: read_ghost_vision_setting(),
+        fixed_ties: read_fixed_ties_setting(),
+        reveal: read_reveal_setting(),
+        split_scores: read_split_scores_setting(),
+        magnet: read_magnet_setting(),
+        combo: read_combo_setting(),
+        spawn_distance: read_spawn_distance_setting(),
+        ready_ticks: read_ready_setting(),
+        power_ticks: read_power_ticks_setting(),
+        max_lives: read_max_lives_setting(),
+        teleport_cooldown: read_teleport_cooldown_setting(),
+        ..GameConfig::default()
+    }
 }
 
-impl Dir {
-    fn delta(self) -> (isize, isize) {
-        match self {
-            Dir::Up => (0, -1),
-            Dir::Down => (0, 1),
-            Dir::Left => (-1, 0),
-            Dir::Right => (1, 0),
-        }
+fn read_maze_options() -> MazeOptions {
+    let rooms = std::env::var("PACMAN_ROOMS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let room_size = std::env::var("PACMAN_ROOM_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_ROOM_SIZE)
+        .max(MIN_ROOM_SIZE);
+    let tunnels = std::env::var("PACMAN_TUNNELS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(1);
+    MazeOptions {
+        gen: read_maze_gen_setting(),
+        rooms,
+        room_size,
+        tunnels,
+        braid: read_fraction_setting("PACMAN_BRAID", BRAID_CHANCE),
+        extra_openings: read_fraction_setting("PACMAN_EXTRA_OPENINGS", EXTRA_OPENINGS),
+        dead_ends: read_fraction_setting("PACMAN_DEAD_ENDS", 1.0),
+        ..MazeOptions::default()
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
-enum InputScheme {
-    Vi,
-    Arrow,
-    Gamer,
+// A chance between 0 and 1; anything outside is clamped onto the range.
+fn read_fraction_setting(name: &str, default: f32) -> f32 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<f32>().ok())
+        .filter(|v| !v.is_nan())
+        .map_or(default, |v| v.clamp(0.0, 1.0))
 }
 
-fn read_input_scheme() -> InputScheme {
-    match std::env::var("PACMAN_INPUT")
+fn read_maze_gen_setting() -> MazeGen {
+    match std::env::var("PACMAN_MAZE_GEN")
         .ok()
         .map(|v| v.to_lowercase())
         .as_deref()
     {
-        Some("arrow") | Some("arrows") => InputScheme::Arrow,
-        Some("gamer") | Some("wasd") => InputScheme::Gamer,
-        _ => InputScheme::Vi,
+        Some("dfs") | Some("backtracker") => MazeGen::Dfs,
+        Some("kruskal") => MazeGen::Kruskal,
+        _ => MazeGen::Prim,
     }
 }
 
 fn map_key_dir(scheme: InputScheme, code: KeyCode) -> Option<Dir> {
     match scheme {
+        InputScheme::All => [InputScheme::Vi, InputScheme::Arrow, InputScheme::Gamer]
+            .into_iter()
+            .find_map(|s| map_key_dir(s, code)),
         InputScheme::Vi => match code {
             KeyCode::Char('k') => Some(Dir::Up),
             KeyCode::Char('j') => Some(Dir::Down),
@@ -113,194 +154,237 @@ fn map_key_dir(scheme: InputScheme, code: KeyCode) -> Option<Dir> {
     }
 }
 
-fn ghost_move_interval(level: u32) -> f32 {
-    let scale = 1.0 + (level.saturating_sub(1) as f32) * GHOST_SPEED_LEVEL_SCALE;
-    (GHOST_MOVE_INTERVAL_BASE / scale).max(GHOST_MOVE_INTERVAL_MIN)
+// Keys a `PACMAN_KEYS` file binds to actions. An action the file leaves out
+// keeps its usual keys (the PACMAN_INPUT scheme for moving, `q`, `p`).
+#[derive(Clone)]
+struct KeyBindings {
+    // Up, down, left, right, like `last_seen`.
+    dirs: [Option<Vec<KeyCode>>; 4],
+    quit: Vec<KeyCode>,
+    pause: Vec<KeyCode>,
 }
 
-struct Game {
-    width: usize,
-    height: usize,
-    grid: Vec<Vec<Tile>>,
-    player: Pos,
-    player_spawn: Pos,
-    ghosts: Vec<Pos>,
-    ghost_spawns: Vec<Pos>,
-    score: u32,
-    lives: u32,
-    level: u32,
-    pellets_left: usize,
-    power_timer: u32,
-    dir: Option<Dir>,
-    ghost_timer: f32,
-    ghost_release: Vec<u32>,
-    pen_bounds: PenBounds,
-    bonus_pos: Option<Pos>,
-    bonus_timer: u32,
-    bonus_spawn_in: u32,
-}
-
-impl Game {
-    fn apply_input(&mut self, desired_dir: Option<Dir>, input_active: bool) {
-        if !input_active {
-            self.dir = None;
-        } else if let Some(dir) = desired_dir {
-            if can_move_player(&self.grid, self.width, self.height, self.player, dir) {
-                self.dir = Some(dir);
-            }
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            dirs: Default::default(),
+            quit: vec![KeyCode::Char('q')],
+            pause: vec![KeyCode::Char('p')],
         }
     }
+}
 
-    fn move_player(&mut self) {
-        if let Some(dir) = self.dir {
-            if can_move_player(&self.grid, self.width, self.height, self.player, dir) {
-                self.player = step(self.player, dir);
-            } else {
-                self.dir = None;
-            }
+impl KeyBindings {
+    fn dir(&self, scheme: InputScheme, code: KeyCode) -> Option<Dir> {
+        let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+        let bound = |idx: usize| self.dirs[idx].as_ref();
+        if let Some(idx) = (0..4).find(|&idx| bound(idx).is_some_and(|keys| keys.contains(&code))) {
+            return Some(dirs[idx]);
         }
+        let dir = map_key_dir(scheme, code)?;
+        let idx = dirs.iter().position(|&d| d == dir)?;
+        bound(idx).is_none().then_some(dir)
     }
 
-    fn consume_tile(&mut self) {
-        match self.grid[self.player.y][self.player.x] {
-            Tile::Pellet => {
-                self.grid[self.player.y][self.player.x] = Tile::Empty;
-                self.score += 10;
-                self.pellets_left = self.pellets_left.saturating_sub(1);
-            }
-            Tile::Power => {
-                self.grid[self.player.y][self.player.x] = Tile::Empty;
-                self.score += 50;
-                self.pellets_left = self.pellets_left.saturating_sub(1);
-                self.power_timer = POWER_TICKS;
-            }
-            _ => {}
-        }
+    fn quits(&self, code: KeyCode) -> bool {
+        self.quit.contains(&code)
     }
 
-    fn try_collect_bonus(&mut self, rng: &mut impl Rng) {
-        if let Some(pos) = self.bonus_pos {
-            if pos == self.player {
-                self.score += BONUS_SCORE;
-                self.power_timer = (self.power_timer + BONUS_POWER_BOOST).max(BONUS_POWER_BOOST);
-                self.bonus_pos = None;
-                self.bonus_timer = 0;
-                self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-            }
-        }
+    fn pauses(&self, code: KeyCode) -> bool {
+        self.pause.contains(&code)
     }
 
-    fn update_bonus(&mut self, rng: &mut impl Rng) {
-        if self.bonus_pos.is_some() {
-            if self.bonus_timer > 0 {
-                self.bonus_timer -= 1;
-            } else {
-                self.bonus_pos = None;
-                self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-            }
-        } else if self.bonus_spawn_in > 0 {
-            self.bonus_spawn_in -= 1;
-        } else {
-            if let Some(pos) = random_bonus_spawn(self, rng) {
-                self.bonus_pos = Some(pos);
-                self.bonus_timer = BONUS_LIFETIME_TICKS;
-            }
-            self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-        }
-    }
-
-    fn update_ghosts(&mut self, rng: &mut impl Rng) {
-        let interval = ghost_move_interval(self.level);
-        self.ghost_timer += 1.0;
-        if self.ghost_timer < interval {
-            return;
-        }
-
-        let mut moves = 0;
-        while self.ghost_timer >= interval {
-            self.ghost_timer -= interval;
-            moves += 1;
-        }
-
-        for _ in 0..moves {
-            let dist = bfs_distance(&self.grid, self.width, self.height, self.player, true);
-            for (idx, ghost) in self.ghosts.iter_mut().enumerate() {
-                if self.ghost_release[idx] > 0 {
-                    self.ghost_release[idx] = self.ghost_release[idx].saturating_sub(1);
-                    let dir = ghost_next_dir_pen(
-                        *ghost,
-                        &self.grid,
-                        self.width,
-                        self.height,
-                        &self.pen_bounds,
-                        rng,
-                    );
-                    if let Some(dir) = dir {
-                        *ghost = step(*ghost, dir);
-                    }
-                    continue;
-                }
-            let dir = if self.power_timer > 0 {
-                ghost_next_dir_flee(*ghost, &self.grid, self.width, self.height, &dist, rng, true)
-            } else {
-                ghost_next_dir(*ghost, &self.grid, self.width, self.height, &dist, rng, true)
-            };
-            if let Some(dir) = dir {
-                *ghost = step(*ghost, dir);
-            }
-        }
+    // For on-screen hints like "press p to resume".
+    fn quit_name(&self) -> String {
+        key_name(self.quit[0])
     }
+
+    fn pause_name(&self) -> String {
+        key_name(self.pause[0])
     }
+}
 
-    fn tick_power_timer(&mut self) {
-        if self.power_timer > 0 {
-            self.power_timer -= 1;
+// A key file, or the usual keys when PACMAN_KEYS is unset or its file is bad.
+fn read_key_bindings() -> KeyBindings {
+    std::env::var("PACMAN_KEYS")
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| parse_key_bindings(&text).ok())
+        .unwrap_or_default()
+}
+
+// The theme file's TOML subset: `action = "key"` or `action = ["key", ...]`
+// lines and `#` comments, for the actions up, down, left, right, quit and pause.
+fn parse_key_bindings(text: &str) -> Result<KeyBindings, String> {
+    let mut keys = KeyBindings::default();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+        let (action, value) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected action = key"))?;
+        let value = strip_toml_comment(value.trim());
+        let items = value
+            .strip_prefix('[')
+            .and_then(|v| v.strip_suffix(']'))
+            .unwrap_or(value);
+        let codes: Vec<KeyCode> = items
+            .split(',')
+            .map(parse_key)
+            .collect::<Option<_>>()
+            .ok_or_else(|| err("unknown key"))?;
+        match action.trim() {
+            "up" => keys.dirs[0] = Some(codes),
+            "down" => keys.dirs[1] = Some(codes),
+            "left" => keys.dirs[2] = Some(codes),
+            "right" => keys.dirs[3] = Some(codes),
+            "quit" => keys.quit = codes,
+            "pause" => keys.pause = codes,
+            _ => return Err(err("unknown action")),
         }
     }
+    Ok(keys)
+}
 
-    fn handle_collisions(&mut self, rng: &mut impl Rng) {
-        let mut hit = None;
-        for (idx, ghost) in self.ghosts.iter().enumerate() {
-            if *ghost == self.player {
-                hit = Some(idx);
-                break;
-            }
+// A single character stands for itself; longer names are the special keys.
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let name = name.trim().trim_matches('"');
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    let code = match name.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        name => {
+            let n = name.strip_prefix('f')?.parse::<u8>().ok()?;
+            KeyCode::F(n)
         }
+    };
+    Some(code)
+}
 
-        if let Some(idx) = hit {
-            if self.power_timer > 0 {
-                self.score += 200;
-                self.ghosts[idx] = self.ghost_spawns[idx];
-            } else {
-                if self.lives > 0 {
-                    self.lives -= 1;
-                }
-                self.player = self.player_spawn;
-                self.ghosts = self.ghost_spawns.clone();
-                self.ghost_release.clear();
-                for i in 0..self.ghost_spawns.len() {
-                    self.ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
-                }
-                self.power_timer = 0;
-                self.bonus_pos = None;
-                self.bonus_timer = 0;
-                self.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-            }
-        }
+fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        _ => "?".to_string(),
     }
 }
 
+const WALL_UP: u8 = 1;
+const WALL_DOWN: u8 = 2;
+const WALL_LEFT: u8 = 4;
+const WALL_RIGHT: u8 = 8;
+
+// Indexed by the `WALL_*` bits of a wall's neighbors. The second column
+// carries the line on to the right so neighboring connectors join up.
+const WALL_BOX: [&str; 16] = [
+    "■ ", "│ ", "│ ", "│ ", "─ ", "┘ ", "┐ ", "┤ ", "──", "└─", "┌─", "├─", "──", "┴─", "┬─", "┼─",
+];
+
 #[derive(Clone, Copy, PartialEq)]
 enum Glyph {
-    Player,
+    // Facing where Pac-Man is heading, mouth open or shut.
+    Player { facing: Option<Dir>, open: bool },
     Ghost,
     Frightened,
-    Wall,
+    FrightenedTinted,
+    FrightenedFlash,
+    Eyes,
+    // Solid block, or a box-drawing connector for the walls around it (`WALL_*` bits).
+    Wall(Option<u8>),
     Empty,
     Pellet,
     Power,
     Gate,
-    Bonus,
+    Fruit(Fruit),
+    SpeedShoes,
+    Caught,
+}
+
+impl Glyph {
+    fn text(self) -> &'static str {
+        match self {
+            Glyph::Player {
+                facing: Some(dir),
+                open: true,
+            } => match dir {
+                Dir::Up => "ᗢ",
+                Dir::Down => "ᗣ",
+                Dir::Left => "ᗤ",
+                Dir::Right => "ᗧ",
+            },
+            Glyph::Player { .. } => "😃",
+            Glyph::Ghost => "👻",
+            Glyph::Frightened | Glyph::FrightenedTinted => "😱",
+            Glyph::FrightenedFlash => "😨",
+            Glyph::Eyes => "👀",
+            Glyph::Wall(None) => "██",
+            Glyph::Wall(Some(mask)) => WALL_BOX[mask as usize],
+            Glyph::Empty => "  ",
+            Glyph::Pellet => "· ",
+            Glyph::Power => "● ",
+            Glyph::Gate => "━━",
+            Glyph::Fruit(Fruit::Cherry) => "🍒",
+            Glyph::Fruit(Fruit::Strawberry) => "🍓",
+            Glyph::Fruit(Fruit::Orange) => "🍊",
+            Glyph::Fruit(Fruit::Apple) => "🍎",
+            Glyph::Fruit(Fruit::Melon) => "🍈",
+            Glyph::Fruit(Fruit::Galaxian) => "🚀",
+            Glyph::Fruit(Fruit::Bell) => "🔔",
+            Glyph::Fruit(Fruit::Key) => "🔑",
+            Glyph::SpeedShoes => "👟",
+            Glyph::Caught => "💥",
+        }
+    }
+
+    fn ascii(self) -> &'static str {
+        match self {
+            Glyph::Player {
+                facing: Some(dir),
+                open: true,
+            } => match dir {
+                Dir::Up => "v",
+                Dir::Down => "^",
+                Dir::Left => ">",
+                Dir::Right => "<",
+            },
+            Glyph::Player { .. } => "C",
+            Glyph::Ghost => "M",
+            Glyph::Frightened | Glyph::FrightenedTinted => "W",
+            Glyph::FrightenedFlash => "w",
+            Glyph::Eyes => "\"",
+            Glyph::Wall(_) => "#",
+            Glyph::Empty => " ",
+            Glyph::Pellet => ".",
+            Glyph::Power => "o",
+            Glyph::Gate => "-",
+            // ASCII has one symbol for every fruit.
+            Glyph::Fruit(_) => "%",
+            Glyph::SpeedShoes => "S",
+            Glyph::Caught => "X",
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -309,86 +393,904 @@ struct Cell {
     color: Color,
 }
 
-#[derive(Clone, Copy)]
-struct PenBounds {
-    x0: usize,
-    y0: usize,
-    x1: usize,
-    y1: usize,
+// The status line above the maze. The power bar gets its own color, so it is
+// kept apart from the text.
+#[derive(Clone, Default, PartialEq)]
+struct Hud {
+    stats: String,
+    power: Option<(String, Color)>,
+    // The key that quits, for the hint at the end of the line.
+    quit: String,
 }
 
 struct Renderer {
-    last: Vec<Cell>,
-    last_hud: String,
+    // Each drawn cell with the heatmap background it was drawn on.
+    last: Vec<(Cell, Option<Color>)>,
+    last_hud: Hud,
+    last_tray: Vec<BonusKind>,
+    // The maze size the cells above were drawn for.
+    grid: (usize, usize),
     needs_full: bool,
     origin_x: u16,
     origin_y: u16,
+    // The terminal size the last frame was drawn for.
+    term_size: (u16, u16),
+    too_small: bool,
+    settings: RenderSettings,
+    heatmap: bool,
+    hint: bool,
+    // Distances and ghost targets drawn over the board.
+    debug: bool,
+}
+
+#[derive(Clone, Copy)]
+struct RenderSettings {
+    coords: bool,
+    square: bool,
+    colors: ColorDepth,
+    fright_tint: bool,
+    ascii: bool,
+    box_walls: bool,
+    theme: Theme,
+    hud_ticks: bool,
+    // The first quit binding, named in the HUD.
+    quit_key: KeyCode,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Theme {
+    wall: Color,
+    gate: Color,
+    pellet: Color,
+    power: Color,
+    player: Color,
+    // Blinky, Pinky, Inky and Clyde.
+    ghosts: [Color; 4],
+    frightened: Color,
+    bonus: Color,
+}
+
+const CLASSIC_THEME: Theme = Theme {
+    wall: Color::Blue,
+    gate: Color::Cyan,
+    pellet: Color::White,
+    power: Color::Magenta,
+    player: Color::Yellow,
+    ghosts: [Color::Red, Color::Magenta, Color::Cyan, Color::DarkYellow],
+    frightened: Color::Blue,
+    bonus: Color::Green,
+};
+
+const DARK_THEME: Theme = Theme {
+    wall: rgb(40, 44, 90),
+    gate: Color::DarkGrey,
+    pellet: Color::Grey,
+    power: Color::DarkMagenta,
+    player: Color::DarkYellow,
+    ghosts: [
+        Color::DarkRed,
+        Color::DarkMagenta,
+        Color::DarkCyan,
+        rgb(160, 100, 30),
+    ],
+    frightened: Color::DarkBlue,
+    bonus: Color::DarkGreen,
+};
+
+const SOLARIZED_THEME: Theme = Theme {
+    wall: rgb(38, 139, 210),
+    gate: rgb(42, 161, 152),
+    pellet: rgb(147, 161, 161),
+    power: rgb(211, 54, 130),
+    player: rgb(181, 137, 0),
+    ghosts: [
+        rgb(220, 50, 47),
+        rgb(211, 54, 130),
+        rgb(42, 161, 152),
+        rgb(203, 75, 22),
+    ],
+    frightened: rgb(108, 113, 196),
+    bonus: rgb(133, 153, 0),
+};
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+fn builtin_theme(name: &str) -> Option<Theme> {
+    match name.to_lowercase().as_str() {
+        "classic" => Some(CLASSIC_THEME),
+        "dark" => Some(DARK_THEME),
+        "solarized" => Some(SOLARIZED_THEME),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorDepth {
+    None,
+    Ansi8,
+    Ansi16,
+    Ansi256,
+    Rgb,
+}
+
+// xterm's default values for the 16 named colors, used to find nearest matches.
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+fn color_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb { r, g, b } => Some((r, g, b)),
+        Color::AnsiValue(n) if n < 16 => Some(ANSI_16[n as usize].1),
+        Color::AnsiValue(n) if n < 232 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            Some((level(n / 36), level((n / 6) % 6), level(n % 6)))
+        }
+        Color::AnsiValue(n) => {
+            let v = 8 + (n - 232) * 10;
+            Some((v, v, v))
+        }
+        _ => ANSI_16
+            .iter()
+            .find(|(c, _)| *c == color)
+            .map(|(_, rgb)| *rgb),
+    }
+}
+
+fn nearest_color(rgb: (u8, u8, u8), palette: &[(Color, (u8, u8, u8))]) -> Color {
+    let dist = |(r, g, b): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    palette
+        .iter()
+        .min_by_key(|(_, c)| dist(*c))
+        .map(|(c, _)| *c)
+        .unwrap_or(Color::Reset)
+}
+
+// Even mix of two colors; falls back to `a` for colors without a known RGB value.
+fn blend_colors(a: Color, b: Color) -> Color {
+    match (color_rgb(a), color_rgb(b)) {
+        (Some(x), Some(y)) => Color::Rgb {
+            r: ((x.0 as u16 + y.0 as u16) / 2) as u8,
+            g: ((x.1 as u16 + y.1 as u16) / 2) as u8,
+            b: ((x.2 as u16 + y.2 as u16) / 2) as u8,
+        },
+        _ => a,
+    }
+}
+
+impl ColorDepth {
+    // Maps a color to the closest one this terminal can display.
+    fn degrade(self, color: Color) -> Color {
+        if color == Color::Reset {
+            return color;
+        }
+        let Some(rgb) = color_rgb(color) else {
+            return color;
+        };
+        match self {
+            ColorDepth::Rgb => color,
+            ColorDepth::Ansi256 => match color {
+                Color::Rgb { r, g, b } => {
+                    let q = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+                    Color::AnsiValue(16 + 36 * q(r) + 6 * q(g) + q(b))
+                }
+                _ => color,
+            },
+            ColorDepth::Ansi16 => match color {
+                Color::Rgb { .. } | Color::AnsiValue(_) => nearest_color(rgb, &ANSI_16),
+                _ => color,
+            },
+            ColorDepth::Ansi8 => nearest_color(rgb, &ANSI_16[..8]),
+            ColorDepth::None => Color::Reset,
+        }
+    }
+}
+
+impl RenderSettings {
+    fn color(self, color: Color) -> Color {
+        self.colors.degrade(color)
+    }
+
+    // Extra columns/rows reserved around the maze for the coordinate ruler.
+    fn margins(self) -> (u16, u16) {
+        if self.coords {
+            (COORD_MARGIN_W, COORD_MARGIN_H)
+        } else {
+            (0, 0)
+        }
+    }
+
+    // Terminal rows per maze row; square cells add a filler row under each one.
+    fn row_h(self) -> u16 {
+        if self.square {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn maze_rows(self, height: usize) -> u16 {
+        height as u16 * self.row_h()
+    }
+
+    // Terminal columns per maze cell: emoji need two, ASCII glyphs one.
+    fn cell_w(self) -> usize {
+        if self.ascii {
+            1
+        } else {
+            CELL_W
+        }
+    }
+
+    fn glyph_text(self, glyph: Glyph) -> &'static str {
+        if self.ascii {
+            glyph.ascii()
+        } else {
+            glyph.text()
+        }
+    }
 }
 
 impl Renderer {
-    fn new(width: usize, height: usize) -> Self {
+    fn new(width: usize, height: usize, settings: RenderSettings) -> Self {
         Self {
             last: vec![
-                Cell {
-                    glyph: Glyph::Empty,
-                    color: Color::Reset,
-                };
+                (
+                    Cell {
+                        glyph: Glyph::Empty,
+                        color: Color::Reset,
+                    },
+                    None
+                );
                 width * height
             ],
-            last_hud: String::new(),
+            last_hud: Hud::default(),
+            last_tray: Vec::new(),
+            grid: (width, height),
             needs_full: true,
             origin_x: 0,
             origin_y: 1,
+            term_size: (0, 0),
+            too_small: false,
+            settings,
+            heatmap: false,
+            hint: false,
+            debug: false,
         }
     }
 }
 
+const USAGE: &str = "\
+Usage: pacman [OPTIONS]
+
+Options:
+  --width <N>    maze width in tiles (at least 21; even values round down)
+  --height <N>   maze height in tiles (at least 15; even values round down)
+  --seed <N>     seed the random generator to replay the same mazes
+  --map <PATH>   play a maze loaded from a text file (see the README)
+  --classic      play the arcade maze instead of a generated one
+  --watch        reload the --map file whenever it changes
+  --rotation     play the classic maze and three more in turn, a level each
+  --autofit      size the maze to fill the terminal, refitting it each level
+  --symmetric    mirror generated mazes left to right
+  --braid <0..1> chance to open up each dead end (default 0.45)
+  --extra <0..1> chance of an extra opening per maze cell (default 0.08)
+  --no-dead-ends open up every dead end of generated mazes
+  --lives <N>    lives to start each game with (1 to PACMAN_MAX_LIVES; default 3)
+  --fixed-ties   settle ghosts' equal-distance turns in a fixed order
+  --persistent-pellets
+                 play each board over several levels, one region at a time
+  --dump-map <PATH>
+                 write one maze in map-file format to PATH and exit
+  --demo         let the computer play until a key is pressed
+  --record <PATH>
+                 save the seed and inputs of the first game to PATH
+  --replay <PATH>
+                 play back a game saved with --record
+  --step         advance one tick per space press instead of on a timer
+  --save <PATH>  save the game in progress to PATH when F5 is pressed
+  --load <PATH>  resume a game saved with --save
+  -h, --help     print this help
+
+Gameplay tuning is done with PACMAN_* environment variables; see the README.";
+
+#[derive(Default)]
+struct CliArgs {
+    width: Option<usize>,
+    height: Option<usize>,
+    seed: Option<u64>,
+    map: Option<PathBuf>,
+    classic: bool,
+    watch: bool,
+    rotation: bool,
+    autofit: bool,
+    symmetric: bool,
+    braid: Option<f32>,
+    extra: Option<f32>,
+    no_dead_ends: bool,
+    lives: Option<u32>,
+    fixed_ties: bool,
+    persistent_pellets: bool,
+    dump_map: Option<PathBuf>,
+    demo: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    step: bool,
+    save: Option<PathBuf>,
+    load: Option<PathBuf>,
+}
+
+impl CliArgs {
+    fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliArgs, String> {
+    let mut cli = CliArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Both `--flag value` and `--flag=value` are accepted.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match flag.as_str() {
+            "--width" => cli.width = Some(parse_dimension("--width", &value()?, MIN_GRID_W)?),
+            "--height" => cli.height = Some(parse_dimension("--height", &value()?, MIN_GRID_H)?),
+            "--seed" => {
+                let seed = value()?;
+                let seed = seed
+                    .parse()
+                    .map_err(|_| format!("--seed must be a whole number, got '{}'", seed))?;
+                cli.seed = Some(seed);
+            }
+            "--map" => cli.map = Some(PathBuf::from(value()?)),
+            "--classic" => cli.classic = true,
+            "--watch" => cli.watch = true,
+            "--rotation" => cli.rotation = true,
+            "--autofit" => cli.autofit = true,
+            "--symmetric" => cli.symmetric = true,
+            "--braid" => cli.braid = Some(parse_fraction("--braid", &value()?)?),
+            "--extra" => cli.extra = Some(parse_fraction("--extra", &value()?)?),
+            "--no-dead-ends" => cli.no_dead_ends = true,
+            "--fixed-ties" => cli.fixed_ties = true,
+            "--persistent-pellets" => cli.persistent_pellets = true,
+            "--lives" => {
+                let lives = value()?;
+                let lives = lives
+                    .parse()
+                    .map_err(|_| format!("--lives must be a whole number, got '{}'", lives))?;
+                if lives == 0 {
+                    return Err("--lives must be at least 1".to_string());
+                }
+                cli.lives = Some(lives);
+            }
+            "--dump-map" => cli.dump_map = Some(PathBuf::from(value()?)),
+            "--demo" => cli.demo = true,
+            "--record" => cli.record = Some(PathBuf::from(value()?)),
+            "--replay" => cli.replay = Some(PathBuf::from(value()?)),
+            "--step" => cli.step = true,
+            "--save" => cli.save = Some(PathBuf::from(value()?)),
+            "--load" => cli.load = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+    if cli.map.is_some() && (cli.width.is_some() || cli.height.is_some()) {
+        return Err("--map sets the maze size; drop --width/--height".to_string());
+    }
+    if cli.classic && cli.map.is_some() {
+        return Err("--classic and --map both pick the maze; use one".to_string());
+    }
+    if cli.watch && cli.map.is_none() {
+        return Err("--watch needs a --map file to watch".to_string());
+    }
+    if cli.watch && (cli.record.is_some() || cli.replay.is_some() || cli.load.is_some()) {
+        return Err("--watch can't be combined with --record, --replay or --load".to_string());
+    }
+    if cli.rotation && (cli.map.is_some() || cli.classic) {
+        return Err("--rotation picks the mazes; drop --map/--classic".to_string());
+    }
+    if cli.rotation && (cli.width.is_some() || cli.height.is_some() || cli.autofit) {
+        return Err("--rotation sets the maze size; drop --width/--height/--autofit".to_string());
+    }
+    let drawn = cli.map.is_some() || cli.classic || cli.rotation;
+    if cli.symmetric && drawn {
+        return Err("--symmetric only applies to generated mazes".to_string());
+    }
+    let shaping = cli.braid.is_some() || cli.extra.is_some() || cli.no_dead_ends;
+    if shaping && drawn {
+        return Err("--braid, --extra and --no-dead-ends need a generated maze".to_string());
+    }
+    if cli.autofit && (cli.width.is_some() || cli.height.is_some()) {
+        return Err("--autofit sizes the maze to the terminal; drop --width/--height".to_string());
+    }
+    let fixed_size = cli.map.is_some() || cli.classic || cli.replay.is_some() || cli.load.is_some();
+    if cli.autofit && fixed_size {
+        return Err("--autofit can't go with --map, --classic, --replay or --load".to_string());
+    }
+    // A replay is rebuilt at the size its recording starts at.
+    if cli.autofit && cli.record.is_some() {
+        return Err("--autofit can't go with --record".to_string());
+    }
+    if cli.classic && (cli.width.is_some() || cli.height.is_some()) {
+        return Err("--classic sets the maze size; drop --width/--height".to_string());
+    }
+    if cli.replay.is_some() && (cli.width.is_some() || cli.height.is_some()) {
+        return Err("--replay sets the maze size; drop --width/--height".to_string());
+    }
+    if cli.replay.is_some() && (cli.seed.is_some() || cli.record.is_some() || cli.demo) {
+        return Err("--replay can't be combined with --seed, --record or --demo".to_string());
+    }
+    if cli.load.is_some() && (cli.replay.is_some() || cli.record.is_some() || cli.demo) {
+        return Err("--load can't be combined with --replay, --record or --demo".to_string());
+    }
+    if cli.step && cli.demo {
+        return Err("--step can't be combined with --demo".to_string());
+    }
+    Ok(cli)
+}
+
+// Mazes are carved on odd grids, so even sizes round down like the auto-fit ones.
+fn parse_dimension(flag: &str, value: &str, min: usize) -> Result<usize, String> {
+    let n: usize = value
+        .parse()
+        .map_err(|_| format!("{} must be a whole number, got '{}'", flag, value))?;
+    if n < min {
+        return Err(format!("{} must be at least {}, got {}", flag, min, n));
+    }
+    Ok(if n % 2 == 0 { n - 1 } else { n })
+}
+
+// Out-of-range chances are clamped rather than refused, like PACMAN_BRAID.
+fn parse_fraction(flag: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(n) if !n.is_nan() => Ok(n.clamp(0.0, 1.0)),
+        _ => Err(format!("{} must be from 0 to 1, got '{}'", flag, value)),
+    }
+}
+
+// The settings a new game starts with: the PACMAN_* environment plus the flags.
+fn game_config(cli: &CliArgs, map: Option<CustomMap>, width: usize, height: usize) -> GameConfig {
+    let mut config = GameConfig {
+        width,
+        height,
+        map,
+        ..read_game_config()
+    };
+    config.maze.symmetric = cli.symmetric;
+    if let Some(braid) = cli.braid {
+        config.maze.braid = braid;
+    }
+    if let Some(extra) = cli.extra {
+        config.maze.extra_openings = extra;
+    }
+    if cli.no_dead_ends {
+        config.maze.dead_ends = 0.0;
+    }
+    if let Some(lives) = cli.lives {
+        config.lives = lives;
+    }
+    config.fixed_ties |= cli.fixed_ties;
+    config.persistent_pellets = cli.persistent_pellets;
+    if cli.rotation {
+        config.rotation = rotation_mazes();
+    }
+    config
+}
+
+// A --save file, resumed with the current settings. It has to fit any
+// --width/--height given alongside it.
+fn load_saved_game(cli: &CliArgs, map: &Option<CustomMap>, path: &Path) -> Result<Game, String> {
+    let config = game_config(cli, map.clone(), DEFAULT_GRID_W, DEFAULT_GRID_H);
+    let mut game = load_game(path, config)?;
+    let (width, height) = (game.width, game.height);
+    if cli.width.is_some_and(|w| w != width) || cli.height.is_some_and(|h| h != height) {
+        return Err(format!(
+            "{}: the saved game is {}x{}, which doesn't match --width/--height",
+            path.display(),
+            width,
+            height
+        ));
+    }
+    // A moment to get your bearings before it picks up again.
+    game.ready_ticks = game.ready_ticks.max(game.config.ready_ticks);
+    Ok(game)
+}
+
+// Generates the maze the game would start with and saves it instead of playing.
+fn dump_map(cli: &CliArgs, map: Option<CustomMap>, path: &Path) -> io::Result<()> {
+    let (width, height) = match &map {
+        Some(map) => (map.grid.width(), map.grid.height()),
+        None if cli.autofit => current_grid_size(read_render_settings())?,
+        None => (
+            cli.width.unwrap_or(DEFAULT_GRID_W),
+            cli.height.unwrap_or(DEFAULT_GRID_H),
+        ),
+    };
+    let config = game_config(cli, map, width, height);
+    let mut game = Game::new_with_config(config, &mut cli.rng());
+    // Whatever sits under the spawn gets eaten on the first tick anyway.
+    let spawn = game.player;
+    game.grid.set(spawn, Tile::Empty);
+    save_maze(&game.grid, &game.pen_bounds, Some(spawn), path)
+}
+
 fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let cli = match parse_args(args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("pacman: {}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    // Load the map up front so a bad file is reported before the screen switches.
+    let map = match cli.map.as_deref().map(load_maze).transpose() {
+        Ok(None) if cli.classic => Some(classic_maze()),
+        Ok(map) => map,
+        Err(err) => {
+            eprintln!("pacman: {}", err);
+            std::process::exit(2);
+        }
+    };
+    let replay = match cli.replay.as_deref().map(load_recording).transpose() {
+        Ok(replay) => replay,
+        Err(err) => {
+            eprintln!("pacman: {}", err);
+            std::process::exit(2);
+        }
+    };
+    if let Some(path) = &cli.dump_map {
+        return dump_map(&cli, map, path);
+    }
+    let load = |path: &Path| load_saved_game(&cli, &map, path);
+    let loaded = match cli.load.as_deref().map(load).transpose() {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("pacman: {}", err);
+            std::process::exit(2);
+        }
+    };
+
     let mut stdout = io::stdout();
     let fullscreen = read_fullscreen_setting();
     terminal::enable_raw_mode()?;
+    let guard = TerminalGuard::new(fullscreen);
     if fullscreen {
         stdout.execute(EnterAlternateScreen)?;
         stdout.execute(Hide)?;
     }
 
-    let result = run(&mut stdout);
+    // Only calibrate on the alternate screen so the test frames never land in scrollback.
+    let (tick_ms, render_fps) = read_speed_settings();
+    let tuned_fps = match render_fps {
+        None if fullscreen => Some(calibrate_render_fps(&mut stdout)?),
+        _ => None,
+    };
+    let render_fps = render_fps.or(tuned_fps).unwrap_or(DEFAULT_RENDER_FPS);
+
+    let result = run(&mut stdout, &cli, map, replay, loaded, tick_ms, render_fps);
 
-    if fullscreen {
-        stdout.execute(Show)?;
-        stdout.execute(LeaveAlternateScreen)?;
+    drop(guard);
+    if let Some(fps) = tuned_fps {
+        eprintln!(
+            "Render rate auto-tuned to {} FPS, tick {} ms (set PACMAN_FPS to override).",
+            fps, tick_ms
+        );
     }
-    terminal::disable_raw_mode()?;
     result
 }
 
-fn run(stdout: &mut Stdout) -> io::Result<()> {
-    let mut rng = rand::thread_rng();
-    let full_maze = read_fullmaze_setting();
+// Puts the terminal back however `main` leaves: a normal return, `?` or a
+// panic. The panic hook restores it first so the message lands on the normal
+// screen instead of vanishing with the alternate one.
+struct TerminalGuard {
+    fullscreen: bool,
+}
+
+impl TerminalGuard {
+    fn new(fullscreen: bool) -> Self {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal(fullscreen);
+            default_hook(info);
+        }));
+        TerminalGuard { fullscreen }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal(self.fullscreen);
+    }
+}
+
+// Best effort: there is nowhere left to report a failure to.
+fn restore_terminal(fullscreen: bool) {
+    let mut stdout = io::stdout();
+    if fullscreen {
+        let _ = stdout.execute(Show);
+        let _ = stdout.execute(LeaveAlternateScreen);
+    }
+    let _ = terminal::disable_raw_mode();
+}
+
+// Times a few worst-case full-screen redraws and picks a render rate that
+// leaves half of each frame free, so diffed frames never stutter.
+fn calibrate_render_fps(stdout: &mut Stdout) -> io::Result<u64> {
+    let (term_w, term_h) = terminal::size()?;
+    let row = "█".repeat(term_w as usize);
+    let start = Instant::now();
+    for frame in 0..CALIBRATION_FRAMES {
+        let color = if frame % 2 == 0 {
+            Color::Blue
+        } else {
+            Color::DarkBlue
+        };
+        stdout.queue(SetForegroundColor(color))?;
+        for y in 0..term_h {
+            stdout.queue(MoveTo(0, y))?;
+            stdout.queue(Print(&row))?;
+        }
+        stdout.queue(ResetColor)?;
+        stdout.flush()?;
+    }
+    let per_frame = start.elapsed() / CALIBRATION_FRAMES;
+    stdout.queue(Clear(ClearType::All))?;
+    stdout.flush()?;
+
+    let budget_us = (per_frame.as_micros() as u64).max(1) * 2;
+    Ok((1_000_000 / budget_us).clamp(MIN_AUTO_FPS, DEFAULT_RENDER_FPS))
+}
+
+fn run(
+    stdout: &mut Stdout,
+    cli: &CliArgs,
+    mut map: Option<CustomMap>,
+    replay: Option<Recording>,
+    loaded: Option<Game>,
+    tick_ms: u64,
+    render_fps: u64,
+) -> io::Result<()> {
+    // A recording needs the seed spelled out, so draw one even when none was given.
+    let mut seed = match &replay {
+        Some(rec) => rec.seed,
+        None => cli.seed.unwrap_or_else(|| StdRng::from_entropy().gen()),
+    };
+    // The game owns the random source; each new game takes it over from the last.
+    let rng: OwnRng = Box::new(StdRng::seed_from_u64(seed));
+    let replay_size = replay.as_ref().map(|rec| (rec.width, rec.height));
+    let loaded_size = loaded.as_ref().map(|game| (game.width, game.height));
+    // An explicit size wins over fitting the maze to the terminal.
+    let sized = cli.width.is_some() || cli.height.is_some() || map.is_some() || cli.rotation;
+    let sized = sized || replay_size.is_some() || loaded_size.is_some();
+    // Replays need every level the size the recording starts at.
+    let sized = sized || cli.record.is_some();
+    let full_maze = (cli.autofit || read_fullmaze_setting()) && !sized;
     let input_scheme = read_input_scheme();
-    let (grid_w, grid_h) = if full_maze {
-        current_grid_size()?
-    } else {
-        (DEFAULT_GRID_W, DEFAULT_GRID_H)
+    let keys = read_key_bindings();
+    let render_settings = RenderSettings {
+        quit_key: keys.quit[0],
+        ..read_render_settings()
+    };
+    let banner = read_banner_setting();
+    let mut bell = read_sound_setting().then(Bell::default);
+    let score_file = read_score_file_setting();
+    // A replay is already saved, a loaded game can't be replayed from its seed,
+    // and a watched map or a fitted maze can change size under a game.
+    let unsaved = replay.is_some() || cli.watch || full_maze;
+    let autosave_dir = read_autosave_replay_setting().filter(|_| !unsaved);
+    let autosave = autosave_dir.as_deref();
+    let mut high_scores = score_file
+        .as_deref()
+        .map(load_high_scores)
+        .unwrap_or_default();
+    let grid_size = |map: &Option<CustomMap>| {
+        if let Some(size) = replay_size.or(loaded_size) {
+            Ok(size)
+        } else if let Some(map) = map {
+            Ok((map.grid.width(), map.grid.height()))
+        } else if full_maze {
+            current_grid_size(render_settings)
+        } else {
+            Ok((
+                cli.width.unwrap_or(DEFAULT_GRID_W),
+                cli.height.unwrap_or(DEFAULT_GRID_H),
+            ))
+        }
+    };
+    let start_game = |rng: OwnRng, map: &Option<CustomMap>, width: usize, height: usize| {
+        Game::with_rng(game_config(cli, map.clone(), width, height), rng)
+    };
+    let (grid_w, grid_h) = grid_size(&map)?;
+    let mut game = match loaded {
+        Some(mut game) => {
+            game.set_rng(rng);
+            game
+        }
+        None => start_game(rng, &map, grid_w, grid_h),
     };
-    let mut game = new_game(&mut rng, 1, grid_w, grid_h);
     let mut last_tick = Instant::now();
     let mut last_seen: [Option<Instant>; 4] = [None, None, None, None];
     let mut last_pressed: Option<Dir> = None;
-    let mut renderer = Renderer::new(grid_w, grid_h);
-    let (tick_ms, render_fps) = read_speed_settings();
+    let mut renderer = Renderer::new(game.width, game.height, render_settings);
+    let mut paused: Option<Instant> = None;
+    // Like `paused`, but for while the terminal is too small to show the board.
+    let mut hidden_since: Option<Instant> = None;
+    let mut showing_ready = false;
+    // A short message over the board, like where F2 saved the game state.
+    let mut flash: Option<(String, Instant)> = None;
     let frame_time = Duration::from_micros(1_000_000 / render_fps.max(1));
+    let mut tick_level = game.level;
+    let mut tick_interval = Duration::from_millis(level_tick_ms(tick_ms, tick_level));
+    let mut demo = cli.demo;
+    let mut restart = false;
+    // With --step, space presses not yet played out as ticks.
+    let mut steps_pending = 0u32;
+    let mut record_path = cli.record.clone();
+    let recorded = (record_path.is_some() || autosave.is_some()) && loaded_size.is_none();
+    let mut recording = recorded.then(|| Recording::new(seed, game.width, game.height));
+    let mut playback = replay.map(Playback::new);
+    let mut watch = cli.map.as_deref().filter(|_| cli.watch).map(MapWatch::new);
 
     loop {
         let frame_start = Instant::now();
+        if restart {
+            restart = false;
+            finish_recording(&mut recording, &mut record_path, autosave)?;
+            let (grid_w, grid_h) = grid_size(&map)?;
+            let mut rng = game.take_rng().expect("the game keeps the run's rng");
+            if autosave.is_some() {
+                // Every game gets a seed of its own so its replay can stand alone.
+                seed = rng.gen();
+                rng = Box::new(StdRng::seed_from_u64(seed));
+            }
+            game = start_game(rng, &map, grid_w, grid_h);
+            if autosave.is_some() {
+                recording = Some(Recording::new(seed, game.width, game.height));
+            }
+            let toggles = (renderer.heatmap, renderer.hint, renderer.debug);
+            renderer = Renderer::new(game.width, game.height, render_settings);
+            (renderer.heatmap, renderer.hint, renderer.debug) = toggles;
+            last_seen = [None; 4];
+            last_pressed = None;
+            last_tick = Instant::now();
+            // The game-over text and banner can reach past the maze.
+            stdout.queue(Clear(ClearType::All))?;
+        }
+        let reload = watch.as_mut().is_some_and(MapWatch::changed);
+        if let Some(watch) = watch.as_ref().filter(|_| reload) {
+            let text = match reload_map(&mut game, &watch.path) {
+                Ok(reloaded) => {
+                    map = Some(reloaded);
+                    let toggles = (renderer.heatmap, renderer.hint, renderer.debug);
+                    renderer = Renderer::new(game.width, game.height, render_settings);
+                    (renderer.heatmap, renderer.hint, renderer.debug) = toggles;
+                    stdout.queue(Clear(ClearType::All))?;
+                    format!(" Reloaded {} ", watch.path.display())
+                }
+                Err(err) => format!(" {} ", err),
+            };
+            flash = Some((text, Instant::now()));
+        }
+        if game.level != tick_level {
+            tick_level = game.level;
+            tick_interval = Duration::from_millis(level_tick_ms(tick_ms, tick_level));
+        }
         while event::poll(Duration::from_millis(0))? {
             if let Event::Key(key) = event::read()? {
                 match key.kind {
                     KeyEventKind::Press | KeyEventKind::Repeat => {
-                        if key.code == KeyCode::Char('q') {
+                        if keys.quits(key.code) {
+                            finish_recording(&mut recording, &mut record_path, autosave)?;
                             return Ok(());
                         }
-                        if let Some(dir) = map_key_dir(input_scheme, key.code) {
+                        if key.code == KeyCode::F(2) && key.kind == KeyEventKind::Press {
+                            let path = PathBuf::from(format!("pacman-state-{}.json", game.ticks));
+                            let text = match save_state(&game, seed, &path) {
+                                Ok(()) => format!(" Saved {} ", path.display()),
+                                Err(err) => format!(" Can't save the state: {} ", err),
+                            };
+                            flash = Some((text, Instant::now()));
+                        }
+                        if key.code == KeyCode::F(5) && key.kind == KeyEventKind::Press {
+                            let text = match &cli.save {
+                                Some(path) => match save_game(&game, path) {
+                                    Ok(()) => format!(" Saved to {} ", path.display()),
+                                    Err(err) => format!(" Can't save the game: {} ", err),
+                                },
+                                None => " Start with --save <PATH> to save ".to_string(),
+                            };
+                            flash = Some((text, Instant::now()));
+                        }
+                        if cli.step && key.code == KeyCode::Char(' ') {
+                            if key.kind == KeyEventKind::Press {
+                                steps_pending += 1;
+                            }
+                            continue;
+                        }
+                        // A replay ignores the keyboard; the recording has the keys.
+                        if playback.is_some() {
+                            continue;
+                        }
+                        // Any other key ends the demo and starts a real game.
+                        if demo {
+                            if key.kind == KeyEventKind::Press {
+                                demo = false;
+                                restart = true;
+                            }
+                            continue;
+                        }
+                        let press = key.kind == KeyEventKind::Press;
+                        if key.code == KeyCode::Char(' ') && press && game.death_freeze > 0 {
+                            game.skip(Skip::Freeze);
+                            if let Some(rec) = &mut recording {
+                                rec.record_skip(Skip::Freeze);
+                            }
+                        }
+                        if key.code == KeyCode::Char('b') && key.kind == KeyEventKind::Press {
+                            renderer.heatmap = !renderer.heatmap;
+                        }
+                        if key.code == KeyCode::Char('?') && key.kind == KeyEventKind::Press {
+                            renderer.hint = !renderer.hint;
+                        }
+                        if key.code == KeyCode::F(1) && key.kind == KeyEventKind::Press {
+                            renderer.debug = !renderer.debug;
+                            renderer.needs_full = true;
+                        }
+                        if keys.pauses(key.code) && key.kind == KeyEventKind::Press {
+                            paused = match paused {
+                                Some(since) => {
+                                    game.resume_clocks(since.elapsed());
+                                    // Repaint the cells the banner covered.
+                                    renderer.needs_full = true;
+                                    None
+                                }
+                                None => Some(Instant::now()),
+                            };
+                        }
+                        if press && game.intro_ticks > 0 {
+                            game.skip(Skip::Intro);
+                            if let Some(rec) = &mut recording {
+                                rec.record_skip(Skip::Intro);
+                            }
+                        }
+                        if let Some(dir) = keys.dir(input_scheme, key.code) {
                             let idx = match dir {
                                 Dir::Up => 0,
                                 Dir::Down => 1,
@@ -404,18 +1306,107 @@ fn run(stdout: &mut Stdout) -> io::Result<()> {
             }
         }
 
-        if last_tick.elapsed() >= Duration::from_millis(tick_ms) {
+        let replay_over = playback
+            .as_ref()
+            .is_some_and(|p| p.finished() || game.lives == 0);
+        if let Some(since) = &mut paused {
+            // Keep the tick clock current so resuming doesn't fire a tick straight away.
+            last_tick = Instant::now();
+            // Push the run clocks along too so the HUD time holds still.
+            game.resume_clocks(since.elapsed());
+            *since = Instant::now();
+            render(&mut Terminal(stdout), &mut game, &mut renderer, full_maze)?;
+            let text = format!(" PAUSED - press {} to resume ", keys.pause_name());
+            render_notice(stdout, &game, &renderer, &text)?;
+        } else if renderer.too_small {
+            // Nothing can be seen, so hold the game like a pause until the
+            // terminal is big enough again; render keeps the notice up.
             last_tick = Instant::now();
-            let desired_dir = active_dir_recent(&last_seen, last_pressed);
+            let since = hidden_since.get_or_insert(frame_start);
+            game.resume_clocks(since.elapsed());
+            *since = Instant::now();
+            render(&mut Terminal(stdout), &mut game, &mut renderer, full_maze)?;
+            if !renderer.too_small {
+                hidden_since = None;
+            }
+        } else if replay_over {
+            // The replay is over; hold the last frame until q.
+            render(&mut Terminal(stdout), &mut game, &mut renderer, full_maze)?;
+        } else if steps_pending > 0 || (!cli.step && last_tick.elapsed() >= tick_interval) {
+            last_tick = Instant::now();
+            steps_pending = steps_pending.saturating_sub(1);
+            let desired_dir = if let Some(playback) = &mut playback {
+                game.with_own_rng(|game, rng| playback.next_tick(game, rng))
+            } else if demo {
+                demo_dir(&game)
+            } else if cli.step {
+                // Steps come far apart, so the last direction pressed stays held.
+                last_pressed
+            } else {
+                active_dir_recent(&last_seen, last_pressed)
+            };
             let input_active = desired_dir.is_some();
-            tick(&mut game, &mut rng, desired_dir, input_active);
-            render(stdout, &mut game, &mut renderer, full_maze)?;
+            if let Some(rec) = &mut recording {
+                rec.record_tick(desired_dir);
+            }
+            let events = game.tick(desired_dir, input_active);
+            if let Some(bell) = &mut bell {
+                let rings = bell.rings(&events, Instant::now());
+                if rings > 0 {
+                    stdout.queue(Print("\x07".repeat(rings)))?;
+                }
+            }
+            render(&mut Terminal(stdout), &mut game, &mut renderer, full_maze)?;
             if game.lives == 0 {
-                render_game_over(stdout, &game, full_maze)?;
-                return Ok(());
+                finish_recording(&mut recording, &mut record_path, autosave)?;
+            }
+            if game.lives == 0 && demo {
+                restart = true;
+            } else if game.lives == 0 && playback.is_none() {
+                let saved_scores = high_scores.clone();
+                let post_game = render_game_over(
+                    stdout,
+                    &game,
+                    &renderer,
+                    banner.as_deref(),
+                    &mut high_scores,
+                    &keys,
+                )?;
+                match score_file.as_deref() {
+                    Some(path) if high_scores != saved_scores => {
+                        save_high_scores(path, &high_scores)?;
+                    }
+                    _ => {}
+                }
+                if let Some(path) = read_splits_file_setting() {
+                    std::fs::write(path, splits_table(&game).join("\n") + "\n")?;
+                }
+                match post_game {
+                    PostGame::Quit => return Ok(()),
+                    PostGame::Demo => demo = true,
+                    PostGame::Restart => {}
+                }
+                restart = true;
             }
         } else {
-            render(stdout, &mut game, &mut renderer, full_maze)?;
+            render(&mut Terminal(stdout), &mut game, &mut renderer, full_maze)?;
+        }
+
+        if game.ready_ticks > 0 && game.intro_ticks == 0 && paused.is_none() {
+            render_notice(stdout, &game, &renderer, " READY! ")?;
+            showing_ready = true;
+        } else if showing_ready {
+            // Repaint the cells READY! covered.
+            renderer.needs_full = true;
+            showing_ready = false;
+        }
+        if let Some((text, since)) = &flash {
+            if since.elapsed() < Duration::from_secs(FLASH_SECS) {
+                render_notice(stdout, &game, &renderer, text)?;
+            } else {
+                flash = None;
+                renderer.needs_full = true;
+            }
         }
 
         let elapsed = frame_start.elapsed();
@@ -425,7 +1416,84 @@ fn run(stdout: &mut Stdout) -> io::Result<()> {
     }
 }
 
-fn read_speed_settings() -> (u64, u64) {
+// Saves a game's inputs once it ends: the first game's to --record's path,
+// which is then used up, and every game's to the autosave slots.
+fn finish_recording(
+    recording: &mut Option<Recording>,
+    path: &mut Option<PathBuf>,
+    autosave: Option<&Path>,
+) -> io::Result<()> {
+    let Some(rec) = recording.take() else {
+        return Ok(());
+    };
+    if let Some(path) = path.take() {
+        save_recording(&rec, &path)?;
+    }
+    match autosave {
+        Some(dir) => autosave_replay(&rec, dir),
+        None => Ok(()),
+    }
+}
+
+// Keeps the last AUTOSAVE_REPLAYS games' replays in `dir`, the newest in
+// replay-1.txt, shifting the older ones up a number and dropping the last.
+fn autosave_replay(rec: &Recording, dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let slot = |n: usize| dir.join(format!("replay-{}.txt", n));
+    for n in (1..AUTOSAVE_REPLAYS).rev() {
+        if slot(n).exists() {
+            std::fs::rename(slot(n), slot(n + 1))?;
+        }
+    }
+    save_recording(rec, &slot(1))
+}
+
+// Polls a --watch map's modification time, at most every WATCH_POLL_MS.
+struct MapWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl MapWatch {
+    fn new(path: &Path) -> Self {
+        MapWatch {
+            path: path.to_path_buf(),
+            modified: modified_time(path),
+            checked: Instant::now(),
+        }
+    }
+
+    fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < Duration::from_millis(WATCH_POLL_MS) {
+            return false;
+        }
+        self.checked = Instant::now();
+        let modified = modified_time(&self.path);
+        // Editors can leave the file missing for a moment while saving; wait
+        // for it to come back rather than report it.
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Starts the level over on a --watch map that changed on disk. A map that
+// doesn't load leaves the game as it was.
+fn reload_map(game: &mut Game, path: &Path) -> Result<CustomMap, String> {
+    let map = load_maze(path)?;
+    game.config.map = Some(map.clone());
+    game.with_own_rng(restart_level);
+    Ok(map)
+}
+
+fn read_speed_settings() -> (u64, Option<u64>) {
     let tick_ms = std::env::var("PACMAN_TICK_MS")
         .ok()
         .and_then(|v| v.parse::<u64>().ok())
@@ -434,8 +1502,7 @@ fn read_speed_settings() -> (u64, u64) {
     let render_fps = std::env::var("PACMAN_FPS")
         .ok()
         .and_then(|v| v.parse::<u64>().ok())
-        .filter(|v| *v > 0)
-        .unwrap_or(DEFAULT_RENDER_FPS);
+        .filter(|v| *v > 0);
     (tick_ms, render_fps)
 }
 
@@ -455,231 +1522,951 @@ fn read_fullmaze_setting() -> bool {
         .unwrap_or(false)
 }
 
-fn current_grid_size() -> io::Result<(usize, usize)> {
-    let (term_w, term_h) = terminal::size()?;
-    let mut w = (term_w as usize) / CELL_W;
-    let mut h = term_h as usize;
-    if h > 2 {
-        h -= 2;
-    } else {
-        h = 1;
+fn read_split_scores_setting() -> Vec<u32> {
+    let mut scores = std::env::var("PACMAN_SPLIT_SCORES")
+        .ok()
+        .map(|v| {
+            v.split(',')
+                .filter_map(|s| s.trim().parse::<u32>().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(|| DEFAULT_SPLIT_SCORES.to_vec());
+    scores.sort_unstable();
+    scores.dedup();
+    scores
+}
+
+fn read_splits_file_setting() -> Option<String> {
+    std::env::var("PACMAN_SPLITS_FILE")
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+// PACMAN_SCORE_FILE, else the XDG data dir (~/.local/share by default).
+fn read_score_file_setting() -> Option<PathBuf> {
+    let file = std::env::var("PACMAN_SCORE_FILE").ok();
+    if let Some(path) = file.filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(path));
     }
+    Some(data_dir()?.join("scores.json"))
+}
+
+// Where the game keeps its files: pacman.rs under the XDG data dir.
+fn data_dir() -> Option<PathBuf> {
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var("HOME")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|home| Path::new(&home).join(".local/share"))
+        })?;
+    Some(data_home.join("pacman.rs"))
+}
+
+// The directory the last few games' replays are kept in, with
+// PACMAN_AUTOSAVE_REPLAY=1.
+fn read_autosave_replay_setting() -> Option<PathBuf> {
+    let on = std::env::var("PACMAN_AUTOSAVE_REPLAY")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .is_some_and(|v| v != 0);
+    on.then(data_dir).flatten().map(|dir| dir.join("replays"))
+}
+
+fn read_reveal_setting() -> bool {
+    std::env::var("PACMAN_REVEAL")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_combo_setting() -> bool {
+    std::env::var("PACMAN_COMBO")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_magnet_setting() -> bool {
+    std::env::var("PACMAN_MAGNET")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_ghost_vision_setting() -> bool {
+    std::env::var("PACMAN_GHOST_VISION")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_fixed_ties_setting() -> bool {
+    std::env::var("PACMAN_FIXED_TIES")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_spawn_distance_setting() -> u32 {
+    std::env::var("PACMAN_SPAWN_DISTANCE")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0)
+}
+
+fn read_death_freeze_setting() -> u32 {
+    std::env::var("PACMAN_DEATH_FREEZE")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEATH_FREEZE_TICKS)
+}
+
+fn read_power_ticks_setting() -> u32 {
+    std::env::var("PACMAN_POWER_TICKS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|&ticks| ticks > 0)
+        .unwrap_or(POWER_TICKS)
+}
+
+fn read_max_lives_setting() -> u32 {
+    std::env::var("PACMAN_MAX_LIVES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|&lives| lives > 0)
+        .unwrap_or(MAX_LIVES)
+}
+
+fn read_teleport_cooldown_setting() -> u32 {
+    std::env::var("PACMAN_TELEPORT_COOLDOWN")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0)
+}
+
+fn read_ready_setting() -> u32 {
+    std::env::var("PACMAN_READY")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(READY_TICKS)
+}
+
+fn read_speed_shoes_setting() -> bool {
+    std::env::var("PACMAN_SPEED_SHOES")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_random_bonus_setting() -> bool {
+    std::env::var("PACMAN_RANDOM_BONUS")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_coords_setting() -> bool {
+    std::env::var("PACMAN_COORDS")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_hud_ticks_setting() -> bool {
+    std::env::var("PACMAN_HUD_TICKS")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_square_setting() -> bool {
+    std::env::var("PACMAN_SQUARE_CELLS")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_box_walls_setting() -> bool {
+    std::env::var("PACMAN_WALLS")
+        .map(|v| !v.eq_ignore_ascii_case("solid"))
+        .unwrap_or(true)
+}
 
-    if w < MIN_GRID_W {
-        w = MIN_GRID_W;
+fn read_ascii_setting() -> bool {
+    std::env::var("PACMAN_ASCII")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+fn read_sound_setting() -> bool {
+    std::env::var("PACMAN_SOUND")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+// Terminal-bell effects: one pattern per tick, with pellet chirps rate-limited.
+#[derive(Default)]
+struct Bell {
+    last_pellet: Option<Instant>,
+}
+
+impl Bell {
+    fn rings(&mut self, events: &[GameEvent], now: Instant) -> usize {
+        let rings = events
+            .iter()
+            .map(|event| match event {
+                GameEvent::PlayerDied => 3,
+                GameEvent::GhostEaten { .. } | GameEvent::PowerEaten => 2,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        if rings > 0 || !events.contains(&GameEvent::PelletEaten) {
+            return rings;
+        }
+        let quiet = self.last_pellet.map_or(true, |t| {
+            now.duration_since(t) >= Duration::from_millis(BELL_PELLET_MS)
+        });
+        if !quiet {
+            return 0;
+        }
+        self.last_pellet = Some(now);
+        1
     }
-    if h < MIN_GRID_H {
-        h = MIN_GRID_H;
+}
+
+// A built-in palette by name, or a theme file; anything else keeps the classic colors.
+fn read_theme_setting() -> Theme {
+    let Ok(value) = std::env::var("PACMAN_THEME") else {
+        return CLASSIC_THEME;
+    };
+    if let Some(theme) = builtin_theme(&value) {
+        return theme;
     }
+    std::fs::read_to_string(&value)
+        .ok()
+        .and_then(|text| parse_theme(&text).ok())
+        .unwrap_or(CLASSIC_THEME)
+}
 
-    if w % 2 == 0 {
-        w = w.saturating_sub(1);
+// A small TOML subset: `key = "value"` lines and `#` comments. `base` picks the
+// built-in theme to start from; colors are names like "dark_red", "#rrggbb" or
+// a 0-255 palette index, and `ghosts` takes an array of four.
+fn parse_theme(text: &str) -> Result<Theme, String> {
+    let mut theme = CLASSIC_THEME;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected key = value"))?;
+        let value = strip_toml_comment(value.trim());
+        let color = || parse_theme_color(value).ok_or_else(|| err("bad color"));
+        match key.trim() {
+            "base" => {
+                theme = builtin_theme(value.trim_matches('"'))
+                    .ok_or_else(|| err("unknown base theme"))?;
+            }
+            "wall" => theme.wall = color()?,
+            "gate" => theme.gate = color()?,
+            "pellet" => theme.pellet = color()?,
+            "power" => theme.power = color()?,
+            "player" => theme.player = color()?,
+            "frightened" => theme.frightened = color()?,
+            "bonus" => theme.bonus = color()?,
+            "ghosts" => {
+                let items = value
+                    .strip_prefix('[')
+                    .and_then(|v| v.strip_suffix(']'))
+                    .ok_or_else(|| err("ghosts must be an array"))?;
+                let colors: Vec<Color> = items
+                    .split(',')
+                    .map(|c| parse_theme_color(c.trim()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| err("bad color"))?;
+                theme.ghosts = colors
+                    .try_into()
+                    .map_err(|_| err("ghosts needs four colors"))?;
+            }
+            _ => return Err(err("unknown key")),
+        }
     }
-    if h % 2 == 0 {
-        h = h.saturating_sub(1);
+    Ok(theme)
+}
+
+// Drops a trailing `# comment`, leaving a `#` inside quotes alone.
+fn strip_toml_comment(value: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return value[..i].trim_end(),
+            _ => {}
+        }
     }
+    value
+}
 
-    Ok((w, h))
+fn parse_theme_color(value: &str) -> Option<Color> {
+    let value = value.trim_matches('"').to_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+    if let Ok(n) = value.parse::<u8>() {
+        return Some(Color::AnsiValue(n));
+    }
+    Color::try_from(value.as_str()).ok()
 }
 
-fn new_game(rng: &mut impl Rng, level: u32, width: usize, height: usize) -> Game {
-    let (grid, pellets_left, ghost_spawns, pen_bounds) = generate_maze(rng, width, height);
-    let mut empties = empty_cells(&grid);
-    empties.shuffle(rng);
-    let player = empties
-        .iter()
-        .copied()
-        .find(|p| !ghost_spawns.contains(p) && !is_in_pen(*p, width, height))
-        .expect("maze has empty cells");
-    let player_spawn = player;
+fn read_color_depth() -> ColorDepth {
+    if let Ok(v) = std::env::var("PACMAN_COLORS") {
+        match v.to_lowercase().as_str() {
+            "none" | "0" | "mono" => return ColorDepth::None,
+            "8" => return ColorDepth::Ansi8,
+            "16" => return ColorDepth::Ansi16,
+            "256" => return ColorDepth::Ansi256,
+            "rgb" | "truecolor" | "24bit" => return ColorDepth::Rgb,
+            _ => {}
+        }
+    }
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return ColorDepth::None;
+    }
+    let colorterm = std::env::var("COLORTERM")
+        .unwrap_or_default()
+        .to_lowercase();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::Rgb;
+    }
+    let term = std::env::var("TERM").unwrap_or_default().to_lowercase();
+    if term == "dumb" {
+        ColorDepth::None
+    } else if term.contains("256color") {
+        ColorDepth::Ansi256
+    } else if term == "linux" || term.starts_with("vt") || term == "ansi" {
+        ColorDepth::Ansi8
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+fn read_fright_tint_setting() -> bool {
+    std::env::var("PACMAN_FRIGHT_TINT")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .map(|v| v != 0)
+        .unwrap_or(false)
+}
+
+// PACMAN_BANNER_FILE picks a custom game-over banner; PACMAN_BANNER=1 uses
+// the built-in one. An unreadable file falls back to the built-in banner.
+fn read_banner_setting() -> Option<Vec<String>> {
+    let builtin = || GAME_OVER_BANNER.lines().map(String::from).collect();
+    let file = std::env::var("PACMAN_BANNER_FILE").ok();
+    if let Some(path) = file.filter(|v| !v.is_empty()) {
+        return Some(load_banner(Path::new(&path)).unwrap_or_else(|_| builtin()));
+    }
+    std::env::var("PACMAN_BANNER")
+        .ok()
+        .and_then(|v| v.parse::<u8>().ok())
+        .filter(|v| *v != 0)
+        .map(|_| builtin())
+}
 
-    let mut ghost_release = Vec::new();
-    for i in 0..ghost_spawns.len() {
-        ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
+fn load_banner(path: &Path) -> io::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(text.lines().map(|l| l.trim_end().to_string()).collect())
+}
+
+fn read_render_settings() -> RenderSettings {
+    RenderSettings {
+        coords: read_coords_setting(),
+        square: read_square_setting(),
+        colors: read_color_depth(),
+        fright_tint: read_fright_tint_setting(),
+        ascii: read_ascii_setting(),
+        box_walls: read_box_walls_setting(),
+        theme: read_theme_setting(),
+        hud_ticks: read_hud_ticks_setting(),
+        quit_key: KeyCode::Char('q'),
     }
+}
 
-    let bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-    Game {
-        width,
-        height,
-        grid,
-        player,
-        player_spawn,
-        ghosts: ghost_spawns.clone(),
-        ghost_spawns,
-        score: 0,
-        lives: 3,
-        level,
-        pellets_left,
-        power_timer: 0,
-        dir: None,
-        ghost_timer: 0.0,
-        ghost_release,
-        pen_bounds,
-        bonus_pos: None,
-        bonus_timer: 0,
-        bonus_spawn_in,
-    }
-}
-
-fn next_level(game: &mut Game, rng: &mut impl Rng) {
-    game.level += 1;
-    let (grid, pellets_left, ghost_spawns, pen_bounds) = generate_maze(rng, game.width, game.height);
-    let mut empties = empty_cells(&grid);
-    empties.shuffle(rng);
-    game.grid = grid;
-    game.pellets_left = pellets_left;
-    game.player = empties
-        .iter()
-        .copied()
-        .find(|p| !ghost_spawns.contains(p) && !is_in_pen(*p, game.width, game.height))
-        .expect("maze has empty cells");
-    game.player_spawn = game.player;
-    game.ghost_spawns = ghost_spawns;
-    game.ghosts = game.ghost_spawns.clone();
-    game.ghost_release.clear();
-    for i in 0..game.ghost_spawns.len() {
-        game.ghost_release.push(i as u32 * GHOST_RELEASE_INTERVAL);
-    }
-    game.pen_bounds = pen_bounds;
-    game.power_timer = 0;
-    game.dir = None;
-    game.ghost_timer = 0.0;
-    game.bonus_pos = None;
-    game.bonus_timer = 0;
-    game.bonus_spawn_in = rng.gen_range(BONUS_MIN_TICKS..=BONUS_MAX_TICKS);
-}
-
-fn tick(game: &mut Game, rng: &mut impl Rng, desired_dir: Option<Dir>, input_active: bool) {
-    game.apply_input(desired_dir, input_active);
-    game.move_player();
-    game.consume_tile();
-    game.try_collect_bonus(rng);
-
-    if game.pellets_left == 0 {
-        next_level(game, rng);
-        return;
-    }
-
-    game.update_bonus(rng);
-    game.update_ghosts(rng);
-    game.tick_power_timer();
-    game.handle_collisions(rng);
+fn current_grid_size(settings: RenderSettings) -> io::Result<(usize, usize)> {
+    Ok(grid_size_for(terminal::size()?, settings))
+}
+
+fn grid_size_for((term_w, term_h): (u16, u16), settings: RenderSettings) -> (usize, usize) {
+    let (margin_w, margin_h) = settings.margins();
+    let w = (term_w.saturating_sub(margin_w) as usize) / settings.cell_w();
+    let mut h = term_h.saturating_sub(margin_h) as usize;
+    if h > 2 {
+        h -= 2;
+    } else {
+        h = 1;
+    }
+    h /= settings.row_h() as usize;
+    maze_size(w, h)
+}
+
+// Where `render` sends a frame. `render` and `Renderer` work out what changed;
+// a backend only puts it on its screen.
+trait Backend {
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn clear(&mut self) -> io::Result<()>;
+    fn draw_cell(
+        &mut self,
+        renderer: &Renderer,
+        x: usize,
+        y: usize,
+        cell: Cell,
+        bg: Option<Color>,
+    ) -> io::Result<()>;
+    fn draw_hud(&mut self, renderer: &Renderer, hud: &Hud) -> io::Result<()>;
+    // The bonus tray, right-aligned on the row below the maze.
+    fn draw_tray(
+        &mut self,
+        renderer: &Renderer,
+        width: usize,
+        height: usize,
+        tray: &[Cell],
+    ) -> io::Result<()>;
+    fn draw_coords(&mut self, renderer: &Renderer, width: usize, height: usize) -> io::Result<()>;
+    // Short text over the cell at (x, y), left-aligned in it.
+    fn draw_label(
+        &mut self,
+        renderer: &Renderer,
+        x: usize,
+        y: usize,
+        text: &str,
+        color: Color,
+    ) -> io::Result<()>;
+    // A message in the top-left corner, for when the board can't be drawn.
+    fn draw_notice(&mut self, text: &str) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+struct Terminal<'a>(&'a mut Stdout);
+
+impl Backend for Terminal<'_> {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.0.queue(Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn draw_cell(
+        &mut self,
+        renderer: &Renderer,
+        x: usize,
+        y: usize,
+        cell: Cell,
+        bg: Option<Color>,
+    ) -> io::Result<()> {
+        let out = &mut *self.0;
+        let settings = renderer.settings;
+        let text = settings.glyph_text(cell.glyph);
+        // Emoji ignore the foreground color; ASCII glyphs take it like any text.
+        let emoji = !settings.ascii
+            && matches!(
+                cell.glyph,
+                Glyph::Ghost
+                    | Glyph::Frightened
+                    | Glyph::FrightenedTinted
+                    | Glyph::FrightenedFlash
+                    | Glyph::Eyes
+            );
+        let fg_color = if emoji { Color::Reset } else { cell.color };
+        let cell_w = settings.cell_w();
+        let x_pos = renderer.origin_x + (x * cell_w) as u16;
+        let y_pos = renderer.origin_y + settings.maze_rows(y);
+        out.queue(MoveTo(x_pos, y_pos))?;
+        out.queue(SetForegroundColor(settings.color(fg_color)))?;
+        if emoji && matches!(cell.glyph, Glyph::FrightenedTinted | Glyph::FrightenedFlash) {
+            // The ghost's tint goes behind the emoji instead.
+            out.queue(SetBackgroundColor(settings.color(cell.color)))?;
+        } else if let Some(bg) = bg {
+            out.queue(SetBackgroundColor(settings.color(bg)))?;
+        }
+        out.queue(Print(text))?;
+        let w = UnicodeWidthStr::width(text);
+        if w < cell_w {
+            for _ in 0..(cell_w - w) {
+                out.queue(Print(' '))?;
+            }
+        }
+        if settings.square {
+            // Walls extend into the filler row so they stay joined; everything else leaves it blank.
+            let filler = match cell.glyph {
+                Glyph::Wall(Some(mask)) if mask & WALL_DOWN != 0 => "│ ",
+                Glyph::Wall(None) => text,
+                _ => &"  "[..cell_w],
+            };
+            out.queue(MoveTo(x_pos, y_pos + 1))?;
+            out.queue(Print(filler))?;
+        }
+        out.queue(ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_label(
+        &mut self,
+        renderer: &Renderer,
+        x: usize,
+        y: usize,
+        text: &str,
+        color: Color,
+    ) -> io::Result<()> {
+        let out = &mut *self.0;
+        let settings = renderer.settings;
+        let cell_w = settings.cell_w();
+        let x_pos = renderer.origin_x + (x * cell_w) as u16;
+        out.queue(MoveTo(x_pos, renderer.origin_y + settings.maze_rows(y)))?;
+        out.queue(SetForegroundColor(settings.color(color)))?;
+        out.queue(Print(format!("{:<1$}", text, cell_w)))?;
+        out.queue(ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_hud(&mut self, renderer: &Renderer, hud: &Hud) -> io::Result<()> {
+        let out = &mut *self.0;
+        let white = renderer.settings.color(Color::White);
+        out.queue(MoveTo(renderer.origin_x, renderer.origin_y - 1))?;
+        out.queue(SetForegroundColor(white))?;
+        out.queue(Clear(ClearType::CurrentLine))?;
+        out.queue(Print(&hud.stats))?;
+        if let Some((bar, color)) = &hud.power {
+            out.queue(Print("  Power: "))?;
+            out.queue(SetForegroundColor(renderer.settings.color(*color)))?;
+            out.queue(Print(bar))?;
+            out.queue(SetForegroundColor(white))?;
+        }
+        out.queue(Print(format!("  ({} to quit)", hud.quit)))?;
+        out.queue(ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_tray(
+        &mut self,
+        renderer: &Renderer,
+        width: usize,
+        height: usize,
+        tray: &[Cell],
+    ) -> io::Result<()> {
+        let out = &mut *self.0;
+        let settings = renderer.settings;
+        let cell_w = settings.cell_w();
+        let (_, margin_h) = settings.margins();
+        out.queue(MoveTo(
+            renderer.origin_x + ((width - tray.len()) * cell_w) as u16,
+            renderer.origin_y + settings.maze_rows(height) + margin_h,
+        ))?;
+        for cell in tray {
+            let text = settings.glyph_text(cell.glyph);
+            out.queue(SetForegroundColor(settings.color(cell.color)))?;
+            out.queue(Print(text))?;
+            let w = UnicodeWidthStr::width(text);
+            out.queue(Print(" ".repeat(cell_w.saturating_sub(w))))?;
+        }
+        out.queue(ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_coords(&mut self, renderer: &Renderer, width: usize, height: usize) -> io::Result<()> {
+        let out = &mut *self.0;
+        let cell_w = renderer.settings.cell_w();
+        out.queue(SetForegroundColor(renderer.settings.color(Color::DarkGrey)))?;
+        for y in 0..height {
+            out.queue(MoveTo(
+                renderer.origin_x - COORD_MARGIN_W,
+                renderer.origin_y + renderer.settings.maze_rows(y),
+            ))?;
+            out.queue(Print(format!("{:>2} ", y % 100)))?;
+        }
+        // Two ruler rows below the maze: tens digit every tenth column, then ones digits.
+        let ruler_y = renderer.origin_y + renderer.settings.maze_rows(height);
+        for row in 0..COORD_MARGIN_H {
+            out.queue(MoveTo(renderer.origin_x, ruler_y + row))?;
+            for x in 0..width {
+                let label = if row == 0 {
+                    if x % 10 == 0 {
+                        ((x / 10) % 10).to_string()
+                    } else {
+                        String::new()
+                    }
+                } else {
+                    (x % 10).to_string()
+                };
+                out.queue(Print(format!("{:<w$}", label, w = cell_w)))?;
+            }
+        }
+        out.queue(ResetColor)?;
+        Ok(())
+    }
+
+    fn draw_notice(&mut self, text: &str) -> io::Result<()> {
+        let out = &mut *self.0;
+        out.queue(MoveTo(0, 0))?;
+        out.queue(Print(text))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
 }
 
 fn render(
-    stdout: &mut Stdout,
+    backend: &mut impl Backend,
     game: &mut Game,
     renderer: &mut Renderer,
     full_maze: bool,
 ) -> io::Result<()> {
+    let (term_w, term_h) = backend.size()?;
     if full_maze {
-        let (new_w, new_h) = current_grid_size()?;
-        if new_w != game.width || new_h != game.height {
-            *game = new_game(&mut rand::thread_rng(), 1, new_w, new_h);
-            *renderer = Renderer::new(new_w, new_h);
-        }
+        // The game in progress keeps its maze through a resize; the next level
+        // is generated at the size that fits now.
+        let fit = grid_size_for((term_w, term_h), renderer.settings);
+        (game.config.width, game.config.height) = fit;
+    }
+    // --rotation and --autofit can hand the next level a maze of another size.
+    if renderer.grid != (game.width, game.height) {
+        let toggles = (renderer.heatmap, renderer.hint, renderer.debug);
+        *renderer = Renderer::new(game.width, game.height, renderer.settings);
+        (renderer.heatmap, renderer.hint, renderer.debug) = toggles;
+    }
+
+    let (margin_w, margin_h) = renderer.settings.margins();
+    let needed_h = renderer.settings.maze_rows(game.height) + 2 + margin_h;
+    let needed_w = (game.width * renderer.settings.cell_w()) as u16 + margin_w;
+
+    // A resize can leave cells from the old layout anywhere on screen, so
+    // start over from a blank one.
+    if (term_w, term_h) != renderer.term_size {
+        renderer.term_size = (term_w, term_h);
+        backend.clear()?;
+        renderer.needs_full = true;
     }
 
-    let needed_h = (game.height + 2) as u16;
-    let needed_w = (game.width * CELL_W) as u16;
-
-    stdout.queue(MoveTo(0, 0))?;
-
-    let (term_w, term_h) = terminal::size()?;
     if term_w < needed_w || term_h < needed_h {
-        stdout.queue(Clear(ClearType::All))?;
+        if !renderer.too_small {
+            backend.clear()?;
+            renderer.too_small = true;
+        }
         let msg = format!(
             "Terminal too small. Need at least {}x{} (cols x rows). Current: {}x{}.",
             needed_w, needed_h, term_w, term_h
         );
-        stdout.queue(Print(msg))?;
-        stdout.flush()?;
+        backend.draw_notice(&msg)?;
+        backend.flush()?;
         renderer.needs_full = true;
         return Ok(());
     }
+    if renderer.too_small {
+        // Wipe the notice before the board goes back up.
+        backend.clear()?;
+        renderer.too_small = false;
+        renderer.needs_full = true;
+    }
 
-    let (origin_x, origin_y) = if full_maze {
-        (0, 1)
-    } else {
-        ((term_w - needed_w) / 2, (term_h - needed_h) / 2 + 1)
-    };
+    let (origin_x, origin_y) = (
+        (term_w - needed_w) / 2 + margin_w,
+        (term_h - needed_h) / 2 + 1,
+    );
     if origin_x != renderer.origin_x || origin_y != renderer.origin_y {
+        // Clear the area the board used to cover.
+        backend.clear()?;
         renderer.origin_x = origin_x;
         renderer.origin_y = origin_y;
         renderer.needs_full = true;
     }
 
-    let hud = format!(
-        "Score: {}  Lives: {}  Level: {}  Pellets: {}  Power: {}  (q to quit)",
-        game.score, game.lives, game.level, game.pellets_left, game.power_timer
-    );
+    let speed = if game.speed_timer > 0 {
+        format!("  Speed: {}", game.speed_timer)
+    } else {
+        String::new()
+    };
+    let combo = if game.config.combo {
+        format!("  Combo: x{}", game.combo_multiplier())
+    } else {
+        String::new()
+    };
+    let regions = if game.config.persistent_pellets {
+        format!("  Regions: {}/{}", game.regions_cleared(), BOARD_REGIONS)
+    } else {
+        String::new()
+    };
+    let secs = game.run_started.elapsed().as_secs();
+    let mut time = format!("  Time: {:02}:{:02}", secs / 60, secs % 60);
+    if renderer.settings.hud_ticks {
+        time += &format!("  Ticks: {}", game.ticks);
+    }
+    let hud = Hud {
+        stats: format!(
+            "Score: {}  Lives: {}  Level: {}  Pellets: {}{}{}{}{}",
+            game.score, game.lives, game.level, game.pellets_left, regions, speed, combo, time
+        ),
+        power: power_bar(game, renderer.settings),
+        quit: key_name(renderer.settings.quit_key),
+    };
     if renderer.needs_full || hud != renderer.last_hud {
-        stdout.queue(MoveTo(renderer.origin_x, renderer.origin_y - 1))?;
-        stdout.queue(SetForegroundColor(Color::White))?;
-        stdout.queue(Clear(ClearType::CurrentLine))?;
-        stdout.queue(Print(&hud))?;
-        stdout.queue(ResetColor)?;
+        backend.draw_hud(renderer, &hud)?;
         renderer.last_hud = hud;
     }
 
+    if renderer.settings.coords && renderer.needs_full {
+        backend.draw_coords(renderer, game.width, game.height)?;
+    }
+
+    if renderer.needs_full || game.bonus_tray != renderer.last_tray {
+        let slots = BONUS_TRAY_LEN.min(game.width);
+        let shown = &game.bonus_tray[game.bonus_tray.len().saturating_sub(slots)..];
+        let blank = Cell {
+            glyph: Glyph::Empty,
+            color: Color::Reset,
+        };
+        let mut tray = vec![blank; slots - shown.len()];
+        let theme = renderer.settings.theme;
+        tray.extend(shown.iter().map(|&kind| bonus_cell(kind, theme)));
+        backend.draw_tray(renderer, game.width, game.height, &tray)?;
+        renderer.last_tray = game.bonus_tray.clone();
+    }
+
+    let heat = (renderer.heatmap && renderer.settings.colors != ColorDepth::None)
+        .then(|| bfs_distance(&game.grid, game.width, game.height, game.player, true));
+    let max_dist = heat.as_ref().map_or(0, Distances::max);
+    // The demo bot's pick for the next step, shown but never taken.
+    let hint = (renderer.hint && renderer.settings.colors != ColorDepth::None)
+        .then(|| demo_dir(game))
+        .flatten()
+        .filter(|_| game.death_freeze == 0)
+        .map(|dir| step(game.player, dir, game.width));
+
+    let cells = render_cells(game, renderer.settings);
     for y in 0..game.height {
         for x in 0..game.width {
-            let pos = Pos { x, y };
-            let cell = cell_for(game, pos);
             let idx = y * game.width + x;
-            if renderer.needs_full || cell != renderer.last[idx] {
-                renderer.last[idx] = cell;
-                draw_cell(stdout, renderer, x, y, cell)?;
+            let cell = cells[idx];
+            let bg = match &heat {
+                Some(dist) if y < game.revealed_rows() => heat_color(dist[y][x], max_dist),
+                _ => None,
+            };
+            let bg = if hint == Some(Pos { x, y }) {
+                let (r, g, b) = HINT_BG;
+                Some(Color::Rgb { r, g, b })
+            } else {
+                bg
+            };
+            if renderer.needs_full || (cell, bg) != renderer.last[idx] {
+                renderer.last[idx] = (cell, bg);
+                backend.draw_cell(renderer, x, y, cell, bg)?;
             }
         }
     }
-    renderer.needs_full = false;
+    if renderer.debug {
+        draw_debug(backend, game, renderer, &cells)?;
+    }
+    // The debug labels aren't in `last`, so the cells under them are redrawn
+    // every frame while they're up.
+    renderer.needs_full = renderer.debug;
 
-    stdout.flush()?;
+    backend.flush()
+}
+
+// The F1 view for working on the ghosts: how many steps each open tile is from
+// Pac-Man the way ghosts path, and an X in each ghost's color on its target.
+fn draw_debug(
+    backend: &mut impl Backend,
+    game: &Game,
+    renderer: &Renderer,
+    cells: &[Cell],
+) -> io::Result<()> {
+    let fresh;
+    let dist = match game.player_distances() {
+        Some(dist) => dist,
+        None => {
+            fresh = bfs_distance(&game.grid, game.width, game.height, game.player, false);
+            &fresh
+        }
+    };
+    let cell_w = renderer.settings.cell_w();
+    for y in 0..game.revealed_rows().min(game.height) {
+        for x in 0..game.width {
+            let steps = dist[y][x];
+            let glyph = cells[y * game.width + x].glyph;
+            if steps < 0 || !matches!(glyph, Glyph::Empty | Glyph::Pellet) {
+                continue;
+            }
+            let text = if cell_w >= 2 {
+                format!("{:>2}", steps % 100)
+            } else {
+                (steps % 10).to_string()
+            };
+            backend.draw_label(renderer, x, y, &text, Color::DarkGrey)?;
+        }
+    }
+    let ghosts = renderer.settings.theme.ghosts;
+    for (idx, target) in game.ghost_targets().into_iter().enumerate() {
+        if let Some(pos) = target {
+            backend.draw_label(renderer, pos.x, pos.y, "X", ghosts[idx % ghosts.len()])?;
+        }
+    }
     Ok(())
 }
 
-fn cell_for(game: &Game, pos: Pos) -> Cell {
+// Warns that power is running out by flashing white over the last stretch.
+fn power_flashing(game: &Game) -> bool {
+    game.power_timer > 0
+        && game.power_timer <= FRIGHT_FLASH_TICKS
+        && (game.power_timer / FRIGHT_FLASH_PERIOD) % 2 == 1
+}
+
+// Power left as a share of a full power pellet, colored like the frightened
+// ghosts and flashing white with them as it runs out. A bonus can top power up
+// past a full pellet's worth; the bar just stays full.
+fn power_bar(game: &Game, settings: RenderSettings) -> Option<(String, Color)> {
+    if game.power_timer == 0 {
+        return None;
+    }
+    let full = game.config.power_ticks as usize;
+    let left = (game.power_timer as usize).min(full);
+    let filled = (left * POWER_BAR_W + full - 1) / full;
+    let (full, empty) = if settings.ascii {
+        ("=", "-")
+    } else {
+        ("█", "░")
+    };
+    let bar = full.repeat(filled) + &empty.repeat(POWER_BAR_W - filled);
+    let color = if power_flashing(game) {
+        Color::White
+    } else {
+        settings.theme.frightened
+    };
+    Some((bar, color))
+}
+
+fn bonus_cell(kind: BonusKind, theme: Theme) -> Cell {
+    match kind {
+        BonusKind::Fruit(fruit) => Cell {
+            glyph: Glyph::Fruit(fruit),
+            color: theme.bonus,
+        },
+        BonusKind::SpeedShoes => Cell {
+            glyph: Glyph::SpeedShoes,
+            color: Color::DarkCyan,
+        },
+    }
+}
+
+// The whole board, row by row, as `render` would draw it.
+fn render_cells(game: &Game, settings: RenderSettings) -> Vec<Cell> {
+    let mut cells = Vec::with_capacity(game.width * game.height);
+    for y in 0..game.height {
+        for x in 0..game.width {
+            cells.push(cell_for(game, Pos { x, y }, settings));
+        }
+    }
+    cells
+}
+
+fn cell_for(game: &Game, pos: Pos, settings: RenderSettings) -> Cell {
+    let theme = settings.theme;
+    if pos.y >= game.revealed_rows() {
+        return Cell {
+            glyph: Glyph::Empty,
+            color: Color::Reset,
+        };
+    }
+    if pos == game.player && game.death_freeze > 0 {
+        return Cell {
+            glyph: Glyph::Caught,
+            color: Color::Red,
+        };
+    }
     if pos == game.player {
+        // Chomps on alternate ticks while moving; standing still keeps it shut.
         return Cell {
-            glyph: Glyph::Player,
-            color: Color::Yellow,
+            glyph: Glyph::Player {
+                facing: game.dir,
+                open: game.dir.is_some() && game.ticks % 2 == 0,
+            },
+            color: theme.player,
         };
     }
     if let Some((idx, _)) = game.ghosts.iter().enumerate().find(|(_, g)| **g == pos) {
-        if game.power_timer > 0 {
+        let color = theme.ghosts[idx % theme.ghosts.len()];
+        if game.ghost_states[idx] == GhostState::Eyes {
+            return Cell {
+                glyph: Glyph::Eyes,
+                color,
+            };
+        }
+        if !game.ghost_frightened(idx) {
+            return Cell {
+                glyph: Glyph::Ghost,
+                color,
+            };
+        }
+        if power_flashing(game) {
+            return Cell {
+                glyph: Glyph::FrightenedFlash,
+                color: Color::White,
+            };
+        }
+        if settings.fright_tint {
             return Cell {
-                glyph: Glyph::Frightened,
-                color: Color::Blue,
+                glyph: Glyph::FrightenedTinted,
+                color: blend_colors(color, theme.frightened),
             };
         }
-        let ghost_colors = [
-            Color::Red,                  // Blinky
-            Color::Magenta,              // Pinky
-            Color::Cyan,                 // Inky
-            Color::DarkYellow,           // Clyde
-        ];
-        let color = ghost_colors[idx % ghost_colors.len()];
         return Cell {
-            glyph: Glyph::Ghost,
-            color,
+            glyph: Glyph::Frightened,
+            color: theme.frightened,
         };
     }
     if game.bonus_pos == Some(pos) {
-        return Cell {
-            glyph: Glyph::Bonus,
-            color: Color::Green,
-        };
+        return bonus_cell(game.bonus_kind, theme);
     }
-    match game.grid[pos.y][pos.x] {
+    match game.grid.get(pos) {
         Tile::Wall => Cell {
-            glyph: Glyph::Wall,
-            color: Color::Blue,
+            glyph: Glyph::Wall(settings.box_walls.then(|| wall_connector(&game.grid, pos))),
+            color: theme.wall,
         },
         Tile::Gate => Cell {
             glyph: Glyph::Gate,
-            color: Color::Cyan,
+            color: theme.gate,
         },
         Tile::Empty => Cell {
             glyph: Glyph::Empty,
@@ -687,765 +2474,1137 @@ fn cell_for(game: &Game, pos: Pos) -> Cell {
         },
         Tile::Pellet => Cell {
             glyph: Glyph::Pellet,
-            color: Color::White,
+            color: theme.pellet,
         },
         Tile::Power => Cell {
             glyph: Glyph::Power,
-            color: Color::Magenta,
+            color: theme.power,
         },
     }
 }
 
-fn draw_cell(stdout: &mut Stdout, renderer: &Renderer, x: usize, y: usize, cell: Cell) -> io::Result<()> {
-    let (text, fg_color) = match cell.glyph {
-        Glyph::Player => ("😃", cell.color),
-        Glyph::Ghost => ("👻", Color::Reset),
-        Glyph::Frightened => ("😱", Color::Reset),
-        Glyph::Wall => ("██", cell.color),
-        Glyph::Empty => ("  ", cell.color),
-        Glyph::Pellet => ("· ", cell.color),
-        Glyph::Power => ("● ", cell.color),
-        Glyph::Gate => ("==", cell.color),
-        Glyph::Bonus => ("🍒", cell.color),
-    };
-    let x_pos = renderer.origin_x + (x * CELL_W) as u16;
-    let y_pos = renderer.origin_y + y as u16;
-    stdout.queue(MoveTo(x_pos, y_pos))?;
-    stdout.queue(SetForegroundColor(fg_color))?;
-    stdout.queue(Print(text))?;
-    let w = UnicodeWidthStr::width(text);
-    if w < CELL_W {
-        for _ in 0..(CELL_W - w) {
-            stdout.queue(Print(' '))?;
-        }
+// Which neighbors a wall joins up with. It is part of the wall's `Cell`, so
+// when a neighbor changes the renderer's diff redraws this wall too.
+fn wall_connector(grid: &Grid, pos: Pos) -> u8 {
+    let joins = |x: usize, y: usize| matches!(grid[y][x], Tile::Wall | Tile::Gate);
+    let (w, h) = (grid.width(), grid.height());
+    let mut mask = 0;
+    if pos.y > 0 && joins(pos.x, pos.y - 1) {
+        mask |= WALL_UP;
+    }
+    if pos.y + 1 < h && joins(pos.x, pos.y + 1) {
+        mask |= WALL_DOWN;
+    }
+    if pos.x > 0 && joins(pos.x - 1, pos.y) {
+        mask |= WALL_LEFT;
+    }
+    if pos.x + 1 < w && joins(pos.x + 1, pos.y) {
+        mask |= WALL_RIGHT;
+    }
+    mask
+}
+
+// Background for the BFS heatmap: warm next to the player, cool at the far end.
+fn heat_color(dist: i32, max_dist: i32) -> Option<Color> {
+    if dist < 0 {
+        return None;
+    }
+    let t = dist as f32 / max_dist.max(1) as f32;
+    let mix = |near: u8, far: u8| (near as f32 + (far as f32 - near as f32) * t) as u8;
+    Some(Color::Rgb {
+        r: mix(HEAT_NEAR.0, HEAT_FAR.0),
+        g: mix(HEAT_NEAR.1, HEAT_FAR.1),
+        b: mix(HEAT_NEAR.2, HEAT_FAR.2),
+    })
+}
+
+// One line of text across the middle of the maze, drawn over the last frame.
+fn render_notice(
+    stdout: &mut Stdout,
+    game: &Game,
+    renderer: &Renderer,
+    text: &str,
+) -> io::Result<()> {
+    let (term_w, term_h) = terminal::size()?;
+    let (margin_w, margin_h) = renderer.settings.margins();
+    let maze_rows = renderer.settings.maze_rows(game.height);
+    let maze_w = (game.width * renderer.settings.cell_w()) as u16;
+    if term_w < maze_w + margin_w || term_h < maze_rows + 2 + margin_h {
+        return Ok(());
     }
+    let text = fit_width(text, maze_w as usize);
+    let text_w = text.width() as u16;
+    stdout.queue(MoveTo(
+        renderer.origin_x + (maze_w - text_w) / 2,
+        renderer.origin_y + renderer.settings.maze_rows(game.height / 2),
+    ))?;
+    stdout.queue(SetForegroundColor(renderer.settings.color(Color::Yellow)))?;
+    stdout.queue(Print(text))?;
     stdout.queue(ResetColor)?;
-    Ok(())
+    stdout.flush()
+}
+
+// The longest start of `text` that fits in `max` columns, cut between chars:
+// notices can quote file names and errors, so neither bytes nor chars are columns.
+fn fit_width(text: &str, max: usize) -> &str {
+    let mut used = 0;
+    let end = text
+        .char_indices()
+        .take_while(|&(_, c)| {
+            used += c.width().unwrap_or(0);
+            used <= max
+        })
+        .last()
+        .map_or(0, |(at, c)| at + c.len_utf8());
+    &text[..end]
 }
 
-fn render_game_over(stdout: &mut Stdout, game: &Game, full_maze: bool) -> io::Result<()> {
+fn render_game_over(
+    stdout: &mut Stdout,
+    game: &Game,
+    renderer: &Renderer,
+    banner: Option<&[String]>,
+    high_scores: &mut Vec<HighScore>,
+    keys: &KeyBindings,
+) -> io::Result<PostGame> {
     let (term_w, term_h) = terminal::size()?;
-    let needed_h = (game.height + 2) as u16;
-    let needed_w = (game.width * CELL_W) as u16;
-    if term_w < needed_w || term_h < needed_h {
-        stdout.queue(MoveTo(0, needed_h))?;
+    let (margin_w, margin_h) = renderer.settings.margins();
+    let maze_rows = renderer.settings.maze_rows(game.height);
+    let needed_h = maze_rows + 2 + margin_h;
+    let needed_w = (game.width * renderer.settings.cell_w()) as u16 + margin_w;
+    let fits = term_w >= needed_w && term_h >= needed_h;
+    let (line_x, line_y) = if fits {
+        (renderer.origin_x, renderer.origin_y + maze_rows + margin_h)
     } else {
-        let (origin_x, origin_y) = if full_maze {
-            (0, 1)
-        } else {
-            ((term_w - needed_w) / 2, (term_h - needed_h) / 2 + 1)
-        };
-        stdout.queue(MoveTo(origin_x, origin_y + game.height as u16))?;
+        (0, needed_h)
+    };
+    let best = high_scores.first().map_or(0, |s| s.score);
+    stdout.queue(MoveTo(line_x, line_y))?;
+    let quit = keys.quit_name();
+    stdout.queue(Print(game_over_line(game.score, best, &quit)))?;
+    let mut splits_rows = maze_rows.saturating_sub(2);
+    if let Some(banner) = banner.filter(|_| fits) {
+        let banner_w = banner.iter().map(|l| l.width()).max().unwrap_or(0) as u16;
+        let banner_h = banner.len() as u16;
+        let maze_w = (game.width * renderer.settings.cell_w()) as u16;
+        // Too big for the board: the plain GAME OVER line above is enough.
+        if banner_w + 2 <= maze_w && banner_h + 2 <= maze_rows {
+            let x = renderer.origin_x + (maze_w - banner_w - 2) / 2;
+            let top = renderer.origin_y + (maze_rows - banner_h) / 2;
+            stdout.queue(SetForegroundColor(renderer.settings.color(Color::Red)))?;
+            for (i, line) in banner.iter().enumerate() {
+                let pad = banner_w as usize - line.width();
+                stdout.queue(MoveTo(x, top + i as u16))?;
+                stdout.queue(Print(format!(" {}{} ", line, " ".repeat(pad))))?;
+            }
+            stdout.queue(ResetColor)?;
+            splits_rows = (top - renderer.origin_y).saturating_sub(2);
+        }
+    }
+    if fits && !game.splits.is_empty() {
+        // Overlay the splits on the (now idle) maze, keeping a row of maze visible top and bottom.
+        let lines = splits_table(game);
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+        let max_lines = splits_rows as usize;
+        for (i, line) in lines.iter().take(max_lines).enumerate() {
+            stdout.queue(MoveTo(
+                renderer.origin_x + renderer.settings.cell_w() as u16,
+                renderer.origin_y + 1 + i as u16,
+            ))?;
+            stdout.queue(Print(format!(" {:<w$} ", line, w = width)))?;
+        }
     }
-    stdout.queue(Print(format!(
-        "GAME OVER - Final Score: {} (press q to quit)",
-        game.score
-    )))?;
     stdout.flush()?;
+    // Magnet and combo runs play by different scoring rules, so they stay off the table.
+    let other_rules = game.config.magnet || game.config.combo;
+    let rank = high_score_rank(high_scores, game.score).filter(|_| !other_rules);
+    if let Some(rank) = rank {
+        if let Some(name) = prompt_name(stdout, line_x, line_y, game.score)? {
+            let score = game.score;
+            high_scores.insert(rank, HighScore { name, score });
+            high_scores.truncate(MAX_HIGH_SCORES);
+        }
+        stdout.queue(MoveTo(line_x, line_y))?;
+        stdout.queue(Clear(ClearType::UntilNewLine))?;
+        let best = best.max(game.score);
+        stdout.queue(Print(game_over_line(game.score, best, &quit)))?;
+        stdout.flush()?;
+    }
+    // Left alone long enough, the game-over screen turns into the demo.
+    let idle_since = Instant::now();
     loop {
+        if idle_since.elapsed() >= Duration::from_secs(DEMO_IDLE_SECS) {
+            return Ok(PostGame::Demo);
+        }
         if event::poll(Duration::from_millis(50))? {
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
-                    return Ok(());
+                if key.kind != KeyEventKind::Press {
+                    continue;
                 }
-            }
-        }
-    }
-}
-
-fn active_dir_recent(last_seen: &[Option<Instant>; 4], last_pressed: Option<Dir>) -> Option<Dir> {
-    let now = Instant::now();
-    if let Some(dir) = last_pressed {
-        if let Some(t) = last_seen[idx_for_dir(dir)] {
-            if now.duration_since(t) <= Duration::from_millis(INPUT_HOLD_MS) {
-                return Some(dir);
-            }
-        }
-    }
-    let mut best: Option<(Dir, Instant)> = None;
-    for (idx, dir) in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter().enumerate() {
-        if let Some(t) = last_seen[idx] {
-            if now.duration_since(t) <= Duration::from_millis(INPUT_HOLD_MS) {
-                match best {
-                    None => best = Some((*dir, t)),
-                    Some((_, bt)) if t > bt => best = Some((*dir, t)),
-                    _ => {}
+                if keys.quits(key.code) {
+                    return Ok(PostGame::Quit);
+                }
+                if key.code == KeyCode::Char('r') {
+                    return Ok(PostGame::Restart);
                 }
             }
         }
     }
-    best.map(|(dir, _)| dir)
 }
 
-fn idx_for_dir(dir: Dir) -> usize {
-    match dir {
-        Dir::Up => 0,
-        Dir::Down => 1,
-        Dir::Left => 2,
-        Dir::Right => 3,
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum PostGame {
+    Restart,
+    Quit,
+    Demo,
 }
 
-fn empty_cells(grid: &[Vec<Tile>]) -> Vec<Pos> {
-    let mut cells = Vec::new();
-    for y in 0..grid.len() {
-        for x in 0..grid[y].len() {
-            if grid[y][x] != Tile::Wall && grid[y][x] != Tile::Gate {
-                cells.push(Pos { x, y });
-            }
-        }
-    }
-    cells
+fn game_over_line(score: u32, best: u32, quit: &str) -> String {
+    format!(
+        "GAME OVER - Final Score: {}  Best: {} (r to restart, {} to quit)",
+        score, best, quit
+    )
 }
 
-fn can_move_player(grid: &[Vec<Tile>], width: usize, height: usize, pos: Pos, dir: Dir) -> bool {
-    let (dx, dy) = dir.delta();
-    let nx = pos.x as isize + dx;
-    let ny = pos.y as isize + dy;
-    if nx < 0 || ny < 0 {
-        return false;
-    }
-    let nx = nx as usize;
-    let ny = ny as usize;
-    if nx >= width || ny >= height {
-        return false;
-    }
-    match grid[ny][nx] {
-        Tile::Wall | Tile::Gate => false,
-        _ => true,
-    }
-}
-
-fn can_move_ghost(
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    pos: Pos,
-    dir: Dir,
-    gate_open: bool,
-) -> bool {
-    let (dx, dy) = dir.delta();
-    let nx = pos.x as isize + dx;
-    let ny = pos.y as isize + dy;
-    if nx < 0 || ny < 0 {
-        return false;
-    }
-    let nx = nx as usize;
-    let ny = ny as usize;
-    if nx >= width || ny >= height {
-        return false;
-    }
-    match grid[ny][nx] {
-        Tile::Wall => false,
-        Tile::Gate => gate_open,
-        _ => true,
-    }
-}
-
-fn step(pos: Pos, dir: Dir) -> Pos {
-    let (dx, dy) = dir.delta();
-    Pos {
-        x: (pos.x as isize + dx) as usize,
-        y: (pos.y as isize + dy) as usize,
-    }
-}
-
-fn bfs_distance(
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    start: Pos,
-    gate_open: bool,
-) -> Vec<Vec<i32>> {
-    let mut dist = vec![vec![-1; width]; height];
-    let mut q = VecDeque::new();
-    dist[start.y][start.x] = 0;
-    q.push_back(start);
-
-    while let Some(pos) = q.pop_front() {
-        let base = dist[pos.y][pos.x];
-        for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-            if !can_move_ghost(grid, width, height, pos, dir, gate_open) {
-                continue;
-            }
-            let next = step(pos, dir);
-            if dist[next.y][next.x] == -1 {
-                dist[next.y][next.x] = base + 1;
-                q.push_back(next);
-            }
-        }
+fn prompt_name(stdout: &mut Stdout, x: u16, y: u16, score: u32) -> io::Result<Option<String>> {
+    // Drop keys still queued from play so a held direction doesn't type itself in.
+    while event::poll(Duration::from_millis(0))? {
+        event::read()?;
     }
-    dist
-}
-
-fn ghost_next_dir(
-    pos: Pos,
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    dist: &[Vec<i32>],
-    rng: &mut impl Rng,
-    gate_open: bool,
-) -> Option<Dir> {
-    let mut options = Vec::new();
-    let mut best = i32::MAX;
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-        if !can_move_ghost(grid, width, height, pos, dir, gate_open) {
+    let mut name = String::new();
+    loop {
+        stdout.queue(MoveTo(x, y))?;
+        stdout.queue(Clear(ClearType::UntilNewLine))?;
+        stdout.queue(Print(format!(
+            "NEW HIGH SCORE {}! Name: {}_ (Enter to save, Esc to skip)",
+            score, name
+        )))?;
+        stdout.flush()?;
+        let Event::Key(key) = event::read()? else {
             continue;
-        }
-        let next = step(pos, dir);
-        let d = dist[next.y][next.x];
-        if d >= 0 && d < best {
-            best = d;
-            options.clear();
-            options.push(dir);
-        } else if d >= 0 && d == best {
-            options.push(dir);
-        }
-    }
-    if options.is_empty() {
-        None
-    } else {
-        Some(*options.choose(rng).unwrap())
-    }
-}
-
-fn ghost_next_dir_flee(
-    pos: Pos,
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    dist: &[Vec<i32>],
-    rng: &mut impl Rng,
-    gate_open: bool,
-) -> Option<Dir> {
-    let mut options = Vec::new();
-    let mut best = -1;
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-        if !can_move_ghost(grid, width, height, pos, dir, gate_open) {
+        };
+        if key.kind != KeyEventKind::Press {
             continue;
         }
-        let next = step(pos, dir);
-        let d = dist[next.y][next.x];
-        if d >= 0 && d > best {
-            best = d;
-            options.clear();
-            options.push(dir);
-        } else if d >= 0 && d == best {
-            options.push(dir);
+        match key.code {
+            KeyCode::Enter if name.is_empty() => return Ok(Some("???".to_string())),
+            KeyCode::Enter => return Ok(Some(name)),
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) if c.is_ascii_alphanumeric() && name.len() < MAX_NAME_LEN => {
+                name.push(c.to_ascii_uppercase());
+            }
+            _ => {}
         }
     }
-    if options.is_empty() {
-        None
-    } else {
-        Some(*options.choose(rng).unwrap())
-    }
-}
-
-fn generate_maze(
-    rng: &mut impl Rng,
-    width: usize,
-    height: usize,
-) -> (Vec<Vec<Tile>>, usize, Vec<Pos>, PenBounds) {
-    let mut grid = vec![vec![Tile::Wall; width]; height];
-    let cells_w = (width - 1) / 2;
-    let cells_h = (height - 1) / 2;
-    let mut in_maze = vec![vec![false; cells_w]; cells_h];
-    let mut frontier: Vec<(usize, usize)> = Vec::new();
-
-    let start = (rng.gen_range(0..cells_w), rng.gen_range(0..cells_h));
-    in_maze[start.1][start.0] = true;
-    carve_cell(&mut grid, start.0, start.1);
-    add_frontier(start.0, start.1, cells_w, cells_h, &in_maze, &mut frontier);
-
-    while !frontier.is_empty() {
-        let idx = rng.gen_range(0..frontier.len());
-        let (cx, cy) = frontier.swap_remove(idx);
-        if in_maze[cy][cx] {
-            continue;
-        }
+}
 
-        let mut neighbors = Vec::new();
-        if cy > 0 && in_maze[cy - 1][cx] {
-            neighbors.push((cx, cy - 1));
-        }
-        if cy + 1 < cells_h && in_maze[cy + 1][cx] {
-            neighbors.push((cx, cy + 1));
-        }
-        if cx > 0 && in_maze[cy][cx - 1] {
-            neighbors.push((cx - 1, cy));
-        }
-        if cx + 1 < cells_w && in_maze[cy][cx + 1] {
-            neighbors.push((cx + 1, cy));
-        }
+#[derive(Clone, PartialEq)]
+struct HighScore {
+    name: String,
+    score: u32,
+}
 
-        if neighbors.is_empty() {
-            continue;
-        }
+// A missing or unreadable table just starts empty.
+fn load_high_scores(path: &Path) -> Vec<HighScore> {
+    let mut scores = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| parse_high_scores(&text))
+        .unwrap_or_default();
+    scores.sort_by_key(|s| std::cmp::Reverse(s.score));
+    scores.truncate(MAX_HIGH_SCORES);
+    scores
+}
 
-        let (nx, ny) = *neighbors.choose(rng).unwrap();
-        in_maze[cy][cx] = true;
-        carve_between(&mut grid, cx, cy, nx, ny);
-        carve_cell(&mut grid, cx, cy);
-        add_frontier(cx, cy, cells_w, cells_h, &in_maze, &mut frontier);
+fn save_high_scores(path: &Path, scores: &[HighScore]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
     }
+    let entries: Vec<String> = scores
+        .iter()
+        .map(|s| {
+            let name = s.name.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("  {{\"name\": \"{}\", \"score\": {}}}", name, s.score)
+        })
+        .collect();
+    std::fs::write(path, format!("[\n{}\n]\n", entries.join(",\n")))
+}
 
-    braid_maze(&mut grid, cells_w, cells_h, rng);
-
-    let (pen_all, _door, pen_spawns, pen_bounds) = carve_ghost_pen(&mut grid, width, height);
-    ensure_connected(&mut grid, width, height, &pen_bounds);
-
-    let mut pellets = 0;
-    for y in 1..height - 1 {
-        for x in 1..width - 1 {
-            if grid[y][x] == Tile::Empty && !pen_all.iter().any(|p| p.x == x && p.y == y) {
-                grid[y][x] = Tile::Pellet;
-                pellets += 1;
+// Reads the array of {"name": ..., "score": ...} objects written above.
+fn parse_high_scores(text: &str) -> Option<Vec<HighScore>> {
+    let mut p = JsonReader {
+        chars: text.chars().collect(),
+        at: 0,
+    };
+    let mut scores = Vec::new();
+    p.expect('[')?;
+    if p.eat(']') {
+        return p.at_end().then_some(scores);
+    }
+    loop {
+        p.expect('{')?;
+        let (mut name, mut score) = (None, None);
+        loop {
+            let key = p.string()?;
+            p.expect(':')?;
+            match key.as_str() {
+                "name" => name = Some(p.string()?),
+                "score" => score = Some(p.number()?),
+                _ => return None,
+            }
+            if !p.eat(',') {
+                break;
             }
         }
+        p.expect('}')?;
+        scores.push(HighScore {
+            name: name?,
+            score: score?,
+        });
+        if !p.eat(',') {
+            break;
+        }
     }
+    p.expect(']')?;
+    p.at_end().then_some(scores)
+}
 
-    let power_spots = [
-        Pos { x: 1, y: 1 },
-        Pos { x: width - 2, y: 1 },
-        Pos { x: 1, y: height - 2 },
-        Pos { x: width - 2, y: height - 2 },
-    ];
-    for pos in power_spots {
-        if grid[pos.y][pos.x] != Tile::Wall {
-            grid[pos.y][pos.x] = Tile::Power;
+struct JsonReader {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl JsonReader {
+    fn skip_ws(&mut self) {
+        while self.chars.get(self.at).is_some_and(|c| c.is_whitespace()) {
+            self.at += 1;
         }
     }
 
-    // Ensure pen cells have no pellets (keep the gate intact).
-    for pos in &pen_all {
-        if grid[pos.y][pos.x] == Tile::Gate {
-            continue;
-        }
-        if grid[pos.y][pos.x] != Tile::Wall {
-            grid[pos.y][pos.x] = Tile::Empty;
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.chars.get(self.at) == Some(&c) {
+            self.at += 1;
+            true
+        } else {
+            false
         }
     }
 
-    let ghost_spawns = pick_ghost_spawns(&pen_spawns);
-    (grid, pellets, ghost_spawns, pen_bounds)
-}
-
-fn add_frontier(
-    cx: usize,
-    cy: usize,
-    cells_w: usize,
-    cells_h: usize,
-    in_maze: &[Vec<bool>],
-    frontier: &mut Vec<(usize, usize)>,
-) {
-    if cy > 0 && !in_maze[cy - 1][cx] {
-        frontier.push((cx, cy - 1));
-    }
-    if cy + 1 < cells_h && !in_maze[cy + 1][cx] {
-        frontier.push((cx, cy + 1));
-    }
-    if cx > 0 && !in_maze[cy][cx - 1] {
-        frontier.push((cx - 1, cy));
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.eat(c).then_some(())
     }
-    if cx + 1 < cells_w && !in_maze[cy][cx + 1] {
-        frontier.push((cx + 1, cy));
-    }
-}
-
-fn carve_cell(grid: &mut [Vec<Tile>], cx: usize, cy: usize) {
-    let gx = cx * 2 + 1;
-    let gy = cy * 2 + 1;
-    grid[gy][gx] = Tile::Empty;
-}
 
-fn carve_between(grid: &mut [Vec<Tile>], cx: usize, cy: usize, nx: usize, ny: usize) {
-    let gx = cx * 2 + 1;
-    let gy = cy * 2 + 1;
-    let ngx = nx * 2 + 1;
-    let ngy = ny * 2 + 1;
-    let wall_x = (gx + ngx) / 2;
-    let wall_y = (gy + ngy) / 2;
-    grid[wall_y][wall_x] = Tile::Empty;
-}
-
-fn carve_ghost_pen(
-    grid: &mut [Vec<Tile>],
-    width: usize,
-    height: usize,
-) -> (Vec<Pos>, Pos, Vec<Pos>, PenBounds) {
-    let (x0, y0, x1, y1) = pen_bounds(width, height);
-
-    let mut pen_all = Vec::new();
-    let mut pen_spawns = Vec::new();
+    fn at_end(&mut self) -> bool {
+        self.skip_ws();
+        self.at == self.chars.len()
+    }
 
-    for y in y0..=y1 {
-        for x in x0..=x1 {
-            if y == y0 || y == y1 || x == x0 || x == x1 {
-                grid[y][x] = Tile::Wall;
-            } else {
-                grid[y][x] = Tile::Empty;
-                pen_all.push(Pos { x, y });
-                pen_spawns.push(Pos { x, y });
+    fn string(&mut self) -> Option<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let c = *self.chars.get(self.at)?;
+            self.at += 1;
+            match c {
+                '"' => return Some(out),
+                '\\' => {
+                    out.push(*self.chars.get(self.at)?);
+                    self.at += 1;
+                }
+                _ => out.push(c),
             }
         }
     }
 
-    let door_x = (x0 + x1) / 2;
-    let door = Pos { x: door_x, y: y0 };
-    grid[door.y][door.x] = Tile::Gate;
-    pen_all.push(door);
-
-    // Carve a vertical corridor from the gate upward until we hit open space,
-    // guaranteeing connectivity between the pen and the maze.
-    let mut y = door.y.saturating_sub(1);
-    while y > 0 {
-        if grid[y][door.x] != Tile::Wall {
-            break;
+    fn number(&mut self) -> Option<u32> {
+        self.skip_ws();
+        let start = self.at;
+        while self.chars.get(self.at).is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
         }
-        grid[y][door.x] = Tile::Empty;
-        y = y.saturating_sub(1);
+        self.chars[start..self.at]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
     }
-
-    (
-        pen_all,
-        door,
-        pen_spawns,
-        PenBounds { x0, y0, x1, y1 },
-    )
 }
 
-fn pick_ghost_spawns(pen_spawns: &[Pos]) -> Vec<Pos> {
-    let mut spawns = Vec::new();
-    if pen_spawns.is_empty() {
-        return spawns;
-    }
-    for pos in pen_spawns.iter().take(4) {
-        spawns.push(*pos);
-    }
-    while spawns.len() < 4 {
-        spawns.push(pen_spawns[0]);
+// Where `score` would land in the table, if it makes the cut at all.
+fn high_score_rank(scores: &[HighScore], score: u32) -> Option<usize> {
+    if score == 0 {
+        return None;
     }
-    spawns
+    let rank = scores
+        .iter()
+        .position(|s| score > s.score)
+        .unwrap_or(scores.len());
+    (rank < MAX_HIGH_SCORES).then_some(rank)
 }
 
-fn pen_bounds(width: usize, height: usize) -> (usize, usize, usize, usize) {
-    let mut pen_w = PEN_W.min(width.saturating_sub(2));
-    let mut pen_h = PEN_H.min(height.saturating_sub(2));
-    if pen_w % 2 == 0 {
-        pen_w = pen_w.saturating_sub(1);
-    }
-    if pen_h % 2 == 0 {
-        pen_h = pen_h.saturating_sub(1);
+fn splits_table(game: &Game) -> Vec<String> {
+    let mut lines = vec![format!("{:<18} {:>8} {:>9}", "Split", "Time", "Segment")];
+    for split in &game.splits {
+        let line = match split.kind {
+            SplitKind::LevelCleared { level, took } => format!(
+                "{:<18} {:>8} {:>9}",
+                format!("Level {} cleared", level),
+                format_split(split.at),
+                format!("+{}", format_split(took))
+            ),
+            SplitKind::Score(score) => format!(
+                "{:<18} {:>8}",
+                format!("Score {}", score),
+                format_split(split.at)
+            ),
+        };
+        lines.push(line);
     }
-    pen_w = pen_w.max(3);
-    pen_h = pen_h.max(3);
-
-    let x0 = (width - pen_w) / 2;
-    let y0 = (height - pen_h) / 2;
-    let x1 = x0 + pen_w - 1;
-    let y1 = y0 + pen_h - 1;
-    (x0, y0, x1, y1)
-}
-
-fn is_in_pen(pos: Pos, width: usize, height: usize) -> bool {
-    let (x0, y0, x1, y1) = pen_bounds(width, height);
-    pos.x >= x0 && pos.x <= x1 && pos.y >= y0 && pos.y <= y1
-}
-
-fn in_pen_interior(pos: Pos, pen: &PenBounds) -> bool {
-    pos.x > pen.x0 && pos.x < pen.x1 && pos.y > pen.y0 && pos.y < pen.y1
+    lines
 }
 
-fn is_pen_wall(pos: Pos, pen: &PenBounds) -> bool {
-    (pos.x >= pen.x0 && pos.x <= pen.x1 && (pos.y == pen.y0 || pos.y == pen.y1))
-        || (pos.y >= pen.y0 && pos.y <= pen.y1 && (pos.x == pen.x0 || pos.x == pen.x1))
+fn format_split(d: Duration) -> String {
+    let tenths = d.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, (tenths / 10) % 60, tenths % 10)
 }
 
-fn ensure_connected(grid: &mut [Vec<Tile>], width: usize, height: usize, pen: &PenBounds) {
-    let start = find_start(grid, width, height, pen);
-    if start.is_none() {
-        return;
-    }
-    let mut reachable = flood(grid, width, height, pen, start.unwrap());
-
-    let mut iterations = 0;
-    while has_unreachable(grid, width, height, pen, &reachable) && iterations < width * height {
-        let mut carved = false;
-        for y in 1..height - 1 {
-            for x in 1..width - 1 {
-                let pos = Pos { x, y };
-                if grid[y][x] != Tile::Wall {
-                    continue;
-                }
-                if is_pen_wall(pos, pen) {
-                    continue;
-                }
-                if grid[y][x] == Tile::Gate {
-                    continue;
-                }
-                let mut has_reach = false;
-                let mut has_unreach = false;
-                for (dx, dy) in [(0isize, -1isize), (0, 1), (-1, 0), (1, 0)] {
-                    let nx = (x as isize + dx) as usize;
-                    let ny = (y as isize + dy) as usize;
-                    let npos = Pos { x: nx, y: ny };
-                    if !is_walkable_for_player(grid, width, height, pen, npos) {
-                        continue;
-                    }
-                    if reachable[ny][nx] {
-                        has_reach = true;
-                    } else {
-                        has_unreach = true;
-                    }
-                }
-                if has_reach && has_unreach {
-                    grid[y][x] = Tile::Empty;
-                    carved = true;
-                    break;
-                }
-            }
-            if carved {
-                break;
+fn active_dir_recent(last_seen: &[Option<Instant>; 4], last_pressed: Option<Dir>) -> Option<Dir> {
+    let now = Instant::now();
+    if let Some(dir) = last_pressed {
+        if let Some(t) = last_seen[idx_for_dir(dir)] {
+            if now.duration_since(t) <= Duration::from_millis(INPUT_HOLD_MS) {
+                return Some(dir);
             }
         }
-
-        if !carved {
-            break;
-        }
-        reachable = flood(grid, width, height, pen, start.unwrap());
-        iterations += 1;
     }
-}
-
-fn find_start(
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    pen: &PenBounds,
-) -> Option<Pos> {
-    for y in 1..height - 1 {
-        for x in 1..width - 1 {
-            let pos = Pos { x, y };
-            if is_walkable_for_player(grid, width, height, pen, pos) {
-                return Some(pos);
+    let mut best: Option<(Dir, Instant)> = None;
+    for (idx, dir) in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter().enumerate() {
+        if let Some(t) = last_seen[idx] {
+            if now.duration_since(t) <= Duration::from_millis(INPUT_HOLD_MS) {
+                match best {
+                    None => best = Some((*dir, t)),
+                    Some((_, bt)) if t > bt => best = Some((*dir, t)),
+                    _ => {}
+                }
             }
         }
     }
-    None
+    best.map(|(dir, _)| dir)
 }
 
-fn is_walkable_for_player(
-    grid: &[Vec<Tile>],
-    _width: usize,
-    _height: usize,
-    pen: &PenBounds,
-    pos: Pos,
-) -> bool {
-    if is_in_pen_bounds(pos, pen) {
-        return false;
-    }
-    match grid[pos.y][pos.x] {
-        Tile::Wall | Tile::Gate => false,
-        _ => true,
+fn idx_for_dir(dir: Dir) -> usize {
+    match dir {
+        Dir::Up => 0,
+        Dir::Down => 1,
+        Dir::Left => 2,
+        Dir::Right => 3,
     }
 }
 
-fn is_in_pen_bounds(pos: Pos, pen: &PenBounds) -> bool {
-    pos.x >= pen.x0 && pos.x <= pen.x1 && pos.y >= pen.y0 && pos.y <= pen.y1
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: RenderSettings = RenderSettings {
+        coords: false,
+        square: false,
+        colors: ColorDepth::Rgb,
+        fright_tint: false,
+        ascii: false,
+        box_walls: false,
+        theme: CLASSIC_THEME,
+        hud_ticks: false,
+        quit_key: KeyCode::Char('q'),
+    };
 
-fn flood(
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    pen: &PenBounds,
-    start: Pos,
-) -> Vec<Vec<bool>> {
-    let mut seen = vec![vec![false; width]; height];
-    let mut q = VecDeque::new();
-    seen[start.y][start.x] = true;
-    q.push_back(start);
-    while let Some(pos) = q.pop_front() {
-        for (dx, dy) in [(0isize, -1isize), (0, 1), (-1, 0), (1, 0)] {
-            let nx = pos.x as isize + dx;
-            let ny = pos.y as isize + dy;
-            if nx <= 0 || ny <= 0 || nx >= (width - 1) as isize || ny >= (height - 1) as isize
-            {
-                continue;
-            }
-            let nx = nx as usize;
-            let ny = ny as usize;
-            let npos = Pos { x: nx, y: ny };
-            if seen[ny][nx] {
-                continue;
-            }
-            if !is_walkable_for_player(grid, width, height, pen, npos) {
-                continue;
-            }
-            seen[ny][nx] = true;
-            q.push_back(npos);
+    // The board's glyphs as plain text, one line per maze row with trailing
+    // blanks trimmed, for comparing a frame without a terminal.
+    fn render_text(game: &Game, settings: RenderSettings) -> String {
+        render_cells(game, settings)
+            .chunks(game.width)
+            .map(|row| {
+                let line: String = row.iter().map(|cell| cell.glyph.text()).collect();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn small_game(seed: u64) -> (Game, StdRng) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let config = GameConfig {
+            width: MIN_GRID_W,
+            height: MIN_GRID_H,
+            ..GameConfig::default()
+        };
+        let game = Game::new_with_config(config, &mut rng);
+        (game, rng)
+    }
+
+    #[test]
+    fn render_text_matches_snapshot() {
+        let (mut game, mut rng) = small_game(7);
+        for _ in 0..5 {
+            tick(&mut game, &mut rng, Some(Dir::Left), true);
         }
+        assert_eq!(render_text(&game, SETTINGS), SNAPSHOT.join("\n"));
     }
-    seen
-}
 
-fn has_unreachable(
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    pen: &PenBounds,
-    reachable: &[Vec<bool>],
-) -> bool {
-    for y in 1..height - 1 {
-        for x in 1..width - 1 {
-            let pos = Pos { x, y };
-            if is_walkable_for_player(grid, width, height, pen, pos) && !reachable[y][x] {
-                return true;
-            }
+    #[test]
+    fn ascii_glyphs_fill_one_column() {
+        let settings = RenderSettings {
+            ascii: true,
+            ..SETTINGS
+        };
+        assert_eq!(settings.cell_w(), 1);
+        let (game, _) = small_game(7);
+        for cell in render_cells(&game, settings) {
+            let text = settings.glyph_text(cell.glyph);
+            assert!(text.is_ascii() && text.len() == 1);
+        }
+        let (w, _) = grid_size_for((200, 40), settings);
+        assert_eq!(w, 199);
+    }
+
+    #[test]
+    fn theme_file_overrides_its_base() {
+        let text = "# muted walls\nbase = \"dark\"\nwall = \"#102030\" # hex\n\
+                    pellet = 250\nghosts = [\"red\", \"dark_magenta\", \"cyan\", \"yellow\"]\n";
+        let theme = parse_theme(text).unwrap();
+        assert!(theme.wall == rgb(16, 32, 48));
+        assert!(theme.pellet == Color::AnsiValue(250));
+        assert!(theme.ghosts[3] == Color::Yellow);
+        assert!(theme.gate == DARK_THEME.gate);
+    }
+
+    #[test]
+    fn theme_file_errors_name_the_line() {
+        let err = parse_theme("wall = \"blue\"\nwalls = \"red\"").err();
+        assert_eq!(err.as_deref(), Some("line 2: unknown key"));
+        assert!(parse_theme("ghosts = [\"red\"]").is_err());
+        assert!(parse_theme("player = \"#12345\"").is_err());
+        assert!(builtin_theme("Solarized").is_some() && builtin_theme("neon").is_none());
+    }
+
+    #[test]
+    fn key_file_rebinds_only_the_actions_it_names() {
+        let text = std::fs::read_to_string("keys/example.toml").unwrap();
+        let keys = parse_key_bindings(&text).unwrap();
+        assert!(keys.dir(InputScheme::All, KeyCode::Char('i')) == Some(Dir::Up));
+        assert!(keys.dir(InputScheme::All, KeyCode::Char('j')) == Some(Dir::Left));
+        assert!(keys.dir(InputScheme::All, KeyCode::Char('w')).is_none());
+        assert!(keys.quits(KeyCode::Char('x')) && !keys.quits(KeyCode::Char('q')));
+        assert!(keys.pauses(KeyCode::Esc) && keys.pauses(KeyCode::Char('p')));
+        assert_eq!(keys.quit_name(), "x");
+
+        let keys = parse_key_bindings("quit = \"f10\" # far away\nleft = \"space\"").unwrap();
+        assert!(keys.quits(KeyCode::F(10)));
+        assert!(keys.dir(InputScheme::All, KeyCode::Char(' ')) == Some(Dir::Left));
+        assert!(keys.dir(InputScheme::All, KeyCode::Char('h')).is_none());
+        assert!(keys.dir(InputScheme::All, KeyCode::Char('k')) == Some(Dir::Up));
+        assert!(keys.pauses(KeyCode::Char('p')) && keys.pause_name() == "p");
+
+        let err = parse_key_bindings("up = \"w\"\njump = \"x\"").err();
+        assert_eq!(err.as_deref(), Some("line 2: unknown action"));
+        assert!(parse_key_bindings("up = \"hyper\"").is_err());
+    }
+
+    #[test]
+    fn walls_join_their_neighbors() {
+        // ####
+        // #  #
+        // ####
+        let mut grid = Grid::new(4, 3, Tile::Wall);
+        grid[1][1..3].fill(Tile::Empty);
+        let text = |x, y| WALL_BOX[wall_connector(&grid, Pos { x, y }) as usize];
+        assert_eq!(text(0, 0), "┌─");
+        assert_eq!(text(1, 0), "──");
+        assert_eq!(text(3, 0), "┐ ");
+        assert_eq!(text(0, 1), "│ ");
+        assert_eq!(text(3, 2), "┘ ");
+    }
+
+    #[test]
+    fn dimension_flags_round_even_and_reject_tiny_sizes() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        let cli = args(&["--width", "22", "--height=15"]).unwrap();
+        assert_eq!((cli.width, cli.height), (Some(21), Some(15)));
+        let err = args(&["--height", "8"]).err().unwrap();
+        assert_eq!(err, "--height must be at least 15, got 8");
+        assert!(args(&["--width", "-3"]).is_err());
+    }
+
+    #[test]
+    fn lives_flag_needs_at_least_one_life() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        assert_eq!(args(&["--lives", "5"]).unwrap().lives, Some(5));
+        assert_eq!(args(&[]).unwrap().lives, None);
+        let err = args(&["--lives=0"]).err().unwrap();
+        assert_eq!(err, "--lives must be at least 1");
+        assert!(args(&["--lives", "many"]).is_err());
+
+        // However many are asked for, the game starts with at most the cap.
+        let cli = args(&["--lives", "4000000000"]).unwrap();
+        let config = game_config(&cli, None, MIN_GRID_W, MIN_GRID_H);
+        assert_eq!(Game::seeded(config, 1).lives, MAX_LIVES);
+    }
+
+    #[test]
+    fn autofit_is_for_generated_mazes_of_any_size() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        assert!(args(&["--autofit", "--seed", "3"]).unwrap().autofit);
+        assert!(args(&["--autofit", "--width", "41"]).is_err());
+        assert!(args(&["--autofit", "--record", "run.txt"]).is_err());
+        for other in ["--classic", "--map=m.txt", "--replay=r.txt", "--load=s.txt"] {
+            assert!(args(&["--autofit", other]).is_err(), "{}", other);
         }
+        // The fitted size obeys the generator's odd minimums.
+        let (w, h) = grid_size_for((20, 10), SETTINGS);
+        assert_eq!((w, h), (MIN_GRID_W, MIN_GRID_H));
+        let (w, h) = grid_size_for((200, 60), SETTINGS);
+        assert!(w % 2 == 1 && h % 2 == 1 && w > DEFAULT_GRID_W && h > DEFAULT_GRID_H);
+    }
+
+    #[test]
+    fn dumped_maps_load_back_as_the_same_maze() {
+        let path = std::env::temp_dir().join(format!("pacman-dump-{}.txt", std::process::id()));
+        for seed in 0..5 {
+            let cli = parse_args(["--seed".to_string(), seed.to_string()]).unwrap();
+            dump_map(&cli, None, &path).unwrap();
+            let map = load_maze(&path).unwrap();
+
+            let config = game_config(&cli, None, DEFAULT_GRID_W, DEFAULT_GRID_H);
+            let mut game = Game::new_with_config(config, &mut cli.rng());
+            game.grid.set(game.player, Tile::Empty);
+            let bounds = |p: &PenBounds| (p.x0, p.y0, p.x1, p.y1);
+            assert!(map.grid == game.grid, "seed {}", seed);
+            assert_eq!(bounds(&map.pen_bounds), bounds(&game.pen_bounds));
+            assert_eq!(map.player_spawn, Some(game.player));
+            assert_eq!(map.ghost_spawns, game.ghost_spawns);
+        }
+        std::fs::remove_file(&path).unwrap();
     }
-    false
-}
 
-fn ghost_next_dir_pen(
-    pos: Pos,
-    grid: &[Vec<Tile>],
-    width: usize,
-    height: usize,
-    pen: &PenBounds,
-    rng: &mut impl Rng,
-) -> Option<Dir> {
-    let mut options = Vec::new();
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-        if !can_move_ghost(grid, width, height, pos, dir, false) {
-            continue;
+    #[test]
+    fn autosaved_replays_keep_only_the_newest_few() {
+        let dir = std::env::temp_dir().join(format!("pacman-replays-{}", std::process::id()));
+        for seed in 0..AUTOSAVE_REPLAYS as u64 + 2 {
+            let mut rec = Some(Recording::new(seed, MIN_GRID_W, MIN_GRID_H));
+            finish_recording(&mut rec, &mut None, Some(&dir)).unwrap();
+        }
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, AUTOSAVE_REPLAYS);
+        let newest = load_recording(&dir.join("replay-1.txt")).unwrap();
+        let oldest = load_recording(&dir.join(format!("replay-{}.txt", AUTOSAVE_REPLAYS)));
+        assert_eq!(newest.seed, AUTOSAVE_REPLAYS as u64 + 1);
+        assert_eq!(oldest.unwrap().seed, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watched_maps_reload_only_when_they_load() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        assert!(args(&["--watch"]).is_err());
+        assert!(args(&["--map", "m.txt", "--watch", "--record", "r.txt"]).is_err());
+        let path = std::env::temp_dir().join(format!("pacman-watch-{}.txt", std::process::id()));
+        let cli = args(&["--seed", "1"]).unwrap();
+        dump_map(&cli, None, &path).unwrap();
+        let config = game_config(&cli, Some(load_maze(&path).unwrap()), 0, 0);
+        let mut game = Game::seeded(config, 1);
+        game.score = 1234;
+
+        let cli = args(&["--seed", "2", "--width", "25", "--height", "17"]).unwrap();
+        dump_map(&cli, None, &path).unwrap();
+        let map = reload_map(&mut game, &path).unwrap();
+        assert!(game.grid == map.grid);
+        assert_eq!((game.width, game.height), (25, 17));
+        assert_eq!((game.player, game.score), (map.player_spawn.unwrap(), 1234));
+
+        std::fs::write(&path, "#####\n").unwrap();
+        assert!(reload_map(&mut game, &path).is_err());
+        assert!(game.grid == map.grid);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn braid_and_extra_flags_are_clamped_chances() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        let cli = args(&["--braid", "0.2", "--extra=3"]).unwrap();
+        assert_eq!((cli.braid, cli.extra), (Some(0.2), Some(1.0)));
+        assert_eq!(args(&["--braid=-1"]).unwrap().braid, Some(0.0));
+        assert!(args(&["--braid", "lots"]).is_err());
+        assert!(args(&["--extra", "0.5", "--classic"]).is_err());
+
+        let config = game_config(&cli, None, DEFAULT_GRID_W, DEFAULT_GRID_H);
+        assert_eq!(config.maze.braid, 0.2);
+        assert_eq!(config.maze.extra_openings, 1.0);
+        assert_eq!(config.maze.dead_ends, 1.0);
+
+        let cli = args(&["--no-dead-ends"]).unwrap();
+        let config = game_config(&cli, None, DEFAULT_GRID_W, DEFAULT_GRID_H);
+        assert_eq!(config.maze.dead_ends, 0.0);
+        assert!(args(&["--no-dead-ends", "--map", "maps/example.txt"]).is_err());
+    }
+
+    #[test]
+    fn step_flag_is_for_played_and_replayed_games() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        assert!(args(&["--step", "--seed", "42"]).unwrap().step);
+        assert!(args(&["--step", "--replay", "run.txt"]).unwrap().step);
+        let err = args(&["--demo", "--step"]).err().unwrap();
+        assert_eq!(err, "--step can't be combined with --demo");
+    }
+
+    #[test]
+    fn rotation_flag_picks_the_mazes_and_their_size() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        let cli = args(&["--rotation"]).unwrap();
+        let config = game_config(&cli, None, DEFAULT_GRID_W, DEFAULT_GRID_H);
+        assert_eq!(config.rotation.len(), 4);
+        for other in ["--classic", "--map=maps/example.txt", "--width=31"] {
+            assert!(args(&["--rotation", other]).is_err());
         }
-        let next = step(pos, dir);
-        if in_pen_interior(next, pen) {
-            options.push(dir);
+        assert!(args(&["--rotation", "--autofit"]).is_err());
+    }
+
+    #[test]
+    fn load_flag_resumes_only_a_played_game() {
+        let args = |list: &[&str]| parse_args(list.iter().map(|a| a.to_string()));
+        let cli = args(&["--load", "game.sav", "--save=game.sav"]).unwrap();
+        assert_eq!(cli.load, Some(PathBuf::from("game.sav")));
+        assert_eq!(cli.save, cli.load);
+        for other in ["--demo", "--record=run.txt", "--replay=run.txt"] {
+            let err = args(&["--load", "game.sav", other]).err().unwrap();
+            assert!(err.starts_with("--load can't be combined with"));
         }
     }
-    options.choose(rng).copied()
-}
 
-fn random_bonus_spawn(game: &Game, rng: &mut impl Rng) -> Option<Pos> {
-    let mut empty_candidates = Vec::new();
-    let mut pellet_candidates = Vec::new();
-    for y in 1..game.height - 1 {
-        for x in 1..game.width - 1 {
-            let tile = game.grid[y][x];
-            if tile != Tile::Empty && tile != Tile::Pellet && tile != Tile::Power {
-                continue;
-            }
-            let pos = Pos { x, y };
-            if is_in_pen(pos, game.width, game.height) {
-                continue;
-            }
-            if game.player == pos {
-                continue;
-            }
-            if game.ghosts.iter().any(|g| *g == pos) {
-                continue;
-            }
-            if tile == Tile::Empty {
-                empty_candidates.push(pos);
-            } else {
-                pellet_candidates.push(pos);
+    #[test]
+    fn notices_are_cut_to_whole_chars_by_width() {
+        assert_eq!(fit_width(" Saved scores.json ", 7), " Saved ");
+        assert_eq!(fit_width("mapé.txt: bad", 4), "mapé");
+        // Wide chars take two columns, so one that would straddle the edge goes.
+        assert_eq!(fit_width("地図.txt", 3), "地");
+        assert_eq!(fit_width("地図.txt", 20), "地図.txt");
+        assert_eq!(fit_width("地図", 1), "");
+    }
+
+    #[test]
+    fn render_cells_covers_the_board() {
+        let (game, _) = small_game(7);
+        let cells = render_cells(&game, SETTINGS);
+        assert_eq!(cells.len(), game.width * game.height);
+        let player = cells[game.player.y * game.width + game.player.x];
+        assert!(matches!(player.glyph, Glyph::Player { .. }));
+    }
+
+    // Records what `render` asks for instead of drawing it.
+    struct Recorder {
+        size: (u16, u16),
+        cells: Vec<(usize, usize, Cell)>,
+        // Backgrounds, for the cells drawn with one.
+        bgs: Vec<(usize, usize, Color)>,
+        huds: Vec<Hud>,
+        trays: Vec<Vec<Cell>>,
+        notices: Vec<String>,
+        labels: Vec<(usize, usize, String)>,
+        clears: usize,
+        flushes: usize,
+    }
+
+    impl Recorder {
+        fn new(size: (u16, u16)) -> Self {
+            Recorder {
+                size,
+                cells: Vec::new(),
+                bgs: Vec::new(),
+                huds: Vec::new(),
+                trays: Vec::new(),
+                notices: Vec::new(),
+                labels: Vec::new(),
+                clears: 0,
+                flushes: 0,
             }
         }
     }
-    if !empty_candidates.is_empty() {
-        return empty_candidates.choose(rng).copied();
-    }
-    pellet_candidates.choose(rng).copied()
-}
 
-fn braid_maze(grid: &mut [Vec<Tile>], cells_w: usize, cells_h: usize, rng: &mut impl Rng) {
-    for cy in 0..cells_h {
-        for cx in 0..cells_w {
-            let open = cell_open_neighbors(grid, cx, cy, cells_w, cells_h);
-            let closed = cell_closed_neighbors(grid, cx, cy, cells_w, cells_h);
+    impl Backend for Recorder {
+        fn size(&self) -> io::Result<(u16, u16)> {
+            Ok(self.size)
+        }
 
-            if open.len() == 1 && !closed.is_empty() && rng.gen::<f32>() < BRAID_CHANCE {
-                let dir = *closed.choose(rng).unwrap();
-                carve_between_dir(grid, cx, cy, dir);
-            } else if !closed.is_empty() && rng.gen::<f32>() < EXTRA_OPENINGS {
-                let dir = *closed.choose(rng).unwrap();
-                carve_between_dir(grid, cx, cy, dir);
-            }
+        fn clear(&mut self) -> io::Result<()> {
+            self.clears += 1;
+            Ok(())
         }
-    }
-}
 
-fn carve_between_dir(grid: &mut [Vec<Tile>], cx: usize, cy: usize, dir: Dir) {
-    let (dx, dy) = dir.delta();
-    let nx = (cx as isize + dx) as usize;
-    let ny = (cy as isize + dy) as usize;
-    carve_between(grid, cx, cy, nx, ny);
-    carve_cell(grid, nx, ny);
-}
+        fn draw_cell(
+            &mut self,
+            _: &Renderer,
+            x: usize,
+            y: usize,
+            cell: Cell,
+            bg: Option<Color>,
+        ) -> io::Result<()> {
+            self.cells.push((x, y, cell));
+            self.bgs.extend(bg.map(|bg| (x, y, bg)));
+            Ok(())
+        }
 
-fn cell_open_neighbors(
-    grid: &[Vec<Tile>],
-    cx: usize,
-    cy: usize,
-    cells_w: usize,
-    cells_h: usize,
-) -> Vec<Dir> {
-    let mut open = Vec::new();
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-        let (dx, dy) = dir.delta();
-        let nx = cx as isize + dx;
-        let ny = cy as isize + dy;
-        if nx < 0 || ny < 0 {
-            continue;
+        fn draw_hud(&mut self, _: &Renderer, hud: &Hud) -> io::Result<()> {
+            self.huds.push(hud.clone());
+            Ok(())
         }
-        let nx = nx as usize;
-        let ny = ny as usize;
-        if nx >= cells_w || ny >= cells_h {
-            continue;
+
+        fn draw_tray(&mut self, _: &Renderer, _: usize, _: usize, tray: &[Cell]) -> io::Result<()> {
+            self.trays.push(tray.to_vec());
+            Ok(())
         }
-        if is_open_between(grid, cx, cy, nx, ny) {
-            open.push(dir);
+
+        fn draw_coords(&mut self, _: &Renderer, _: usize, _: usize) -> io::Result<()> {
+            Ok(())
         }
-    }
-    open
-}
 
-fn cell_closed_neighbors(
-    grid: &[Vec<Tile>],
-    cx: usize,
-    cy: usize,
-    cells_w: usize,
-    cells_h: usize,
-) -> Vec<Dir> {
-    let mut closed = Vec::new();
-    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-        let (dx, dy) = dir.delta();
-        let nx = cx as isize + dx;
-        let ny = cy as isize + dy;
-        if nx < 0 || ny < 0 {
-            continue;
+        fn draw_label(
+            &mut self,
+            _: &Renderer,
+            x: usize,
+            y: usize,
+            text: &str,
+            _: Color,
+        ) -> io::Result<()> {
+            self.labels.push((x, y, text.to_string()));
+            Ok(())
         }
-        let nx = nx as usize;
-        let ny = ny as usize;
-        if nx >= cells_w || ny >= cells_h {
-            continue;
+
+        fn draw_notice(&mut self, text: &str) -> io::Result<()> {
+            self.notices.push(text.to_string());
+            Ok(())
         }
-        if !is_open_between(grid, cx, cy, nx, ny) {
-            closed.push(dir);
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
         }
     }
-    closed
-}
 
-fn is_open_between(grid: &[Vec<Tile>], cx: usize, cy: usize, nx: usize, ny: usize) -> bool {
-    let gx = cx * 2 + 1;
-    let gy = cy * 2 + 1;
-    let ngx = nx * 2 + 1;
-    let ngy = ny * 2 + 1;
-    let wall_x = (gx + ngx) / 2;
-    let wall_y = (gy + ngy) / 2;
-    grid[wall_y][wall_x] != Tile::Wall
+    #[test]
+    fn render_redraws_only_changed_cells() {
+        let (mut game, mut rng) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.cells.len(), game.width * game.height);
+        assert_eq!(backend.huds.len(), 1);
+
+        backend.cells.clear();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.cells.is_empty());
+        assert_eq!(backend.huds.len(), 1);
+
+        tick(&mut game, &mut rng, Some(Dir::Left), true);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        let player = game.player;
+        assert!(backend
+            .cells
+            .iter()
+            .any(|&(x, y, cell)| Pos { x, y } == player
+                && matches!(cell.glyph, Glyph::Player { .. })));
+        assert!(backend.cells.len() < game.width * game.height);
+        assert_eq!(backend.flushes, 3);
+    }
+
+    #[test]
+    fn autofit_keeps_the_maze_through_a_resize_until_the_next_level() {
+        let (mut game, mut rng) = small_game(7);
+        let grid = game.grid.clone();
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, true).unwrap();
+        assert!(game.grid == grid && (game.width, game.height) == (MIN_GRID_W, MIN_GRID_H));
+        assert_eq!(backend.cells.len(), MIN_GRID_W * MIN_GRID_H);
+        // Centered in the bigger terminal rather than drawn from the corner.
+        let (margin_w, _) = SETTINGS.margins();
+        let maze_w = (MIN_GRID_W * SETTINGS.cell_w()) as u16;
+        assert_eq!(renderer.origin_x, (100 - maze_w - margin_w) / 2 + margin_w);
+
+        let fit = grid_size_for((100, 40), SETTINGS);
+        next_level(&mut game, &mut rng);
+        assert_eq!((game.width, game.height), fit);
+        backend.cells.clear();
+        render(&mut backend, &mut game, &mut renderer, true).unwrap();
+        assert_eq!(renderer.grid, fit);
+        assert_eq!(backend.cells.len(), fit.0 * fit.1);
+    }
+
+    #[test]
+    fn render_repaints_the_bonus_tray_only_when_it_changes() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.trays.len(), 1);
+        assert!(backend.trays[0].iter().all(|c| c.glyph == Glyph::Empty));
+
+        game.bonus_tray = vec![BonusKind::Fruit(Fruit::Cherry); BONUS_TRAY_LEN - 1];
+        game.bonus_tray.push(BonusKind::SpeedShoes);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.trays.len(), 2);
+        let tray = &backend.trays[1];
+        assert_eq!(tray.len(), BONUS_TRAY_LEN);
+        assert!(tray[0].glyph == Glyph::Fruit(Fruit::Cherry));
+        assert!(tray[BONUS_TRAY_LEN - 1].glyph == Glyph::SpeedShoes);
+    }
+
+    #[test]
+    fn player_glyph_faces_its_direction_and_chomps() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        game.dir = Some(Dir::Left);
+        game.ticks = 2;
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        let at = game.player.y * game.width + game.player.x;
+        let glyph = renderer.last[at].0.glyph;
+        assert_eq!((glyph.text(), glyph.ascii()), ("ᗤ", ">"));
+
+        // Nothing moved, but the mouth shut: only the player is redrawn.
+        backend.cells.clear();
+        game.ticks += 1;
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.cells.len(), 1);
+        assert_eq!(backend.cells[0].2.glyph.ascii(), "C");
+
+        game.ticks += 1;
+        game.dir = Some(Dir::Up);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.cells[1].2.glyph.ascii(), "v");
+    }
+
+    #[test]
+    fn power_bar_drains_and_flashes() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[0].power.is_none());
+
+        game.power_timer = POWER_TICKS + BONUS_POWER_BOOST;
+        let (bar, color) = power_bar(&game, SETTINGS).unwrap();
+        assert_eq!(bar, "█".repeat(POWER_BAR_W));
+        assert_eq!(color, SETTINGS.theme.frightened);
+        game.power_timer = POWER_TICKS / 2;
+        let (bar, _) = power_bar(&game, SETTINGS).unwrap();
+        assert_eq!(bar.matches('█').count(), POWER_BAR_W / 2);
+        game.power_timer = FRIGHT_FLASH_PERIOD;
+        assert_eq!(power_bar(&game, SETTINGS).unwrap().1, Color::White);
+
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.huds.len(), 2);
+        assert!(backend.huds[1].power.is_some());
+        game.power_timer = 0;
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[2].power.is_none());
+    }
+
+    #[test]
+    fn hud_shows_the_run_time_and_optional_ticks() {
+        let (mut game, _) = small_game(7);
+        game.run_started -= Duration::from_secs(125);
+        game.ticks = 42;
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[0].stats.ends_with("  Time: 02:05"));
+
+        let settings = RenderSettings {
+            hud_ticks: true,
+            ..SETTINGS
+        };
+        let mut renderer = Renderer::new(game.width, game.height, settings);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[1].stats.ends_with("  Time: 02:05  Ticks: 42"));
+    }
+
+    #[test]
+    fn hud_names_the_bound_quit_key() {
+        let (mut game, _) = small_game(7);
+        let mut backend = Recorder::new((100, 40));
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.huds[0].quit, "q");
+
+        let keys = parse_key_bindings("quit = \"f10\"").unwrap();
+        let settings = RenderSettings {
+            quit_key: keys.quit[0],
+            ..SETTINGS
+        };
+        let mut renderer = Renderer::new(game.width, game.height, settings);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.huds[1].quit, "F10");
+    }
+
+    #[test]
+    fn hud_shows_the_combo_multiplier_when_on() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(!backend.huds[0].stats.contains("Combo"));
+
+        game.config.combo = true;
+        game.combo = COMBO_STEP;
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[1].stats.contains("  Combo: x2  Time: "));
+    }
+
+    #[test]
+    fn persistent_pellets_flag_shows_region_progress() {
+        let cli = parse_args(["--persistent-pellets".to_string()]).unwrap();
+        let config = game_config(&cli, None, MIN_GRID_W, MIN_GRID_H);
+        let mut game = Game::new_with_config(config, &mut StdRng::seed_from_u64(7));
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.huds[0].stats.contains("  Regions: 0/4"));
+    }
+
+    #[test]
+    fn render_reports_a_terminal_too_small() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((20, 10));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.cells.is_empty());
+        assert_eq!(backend.notices.len(), 1);
+        assert!(backend.notices[0].starts_with("Terminal too small"));
+    }
+
+    #[test]
+    fn resizing_clears_the_screen_and_repaints_everything() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        let full = backend.cells.len();
+        assert_eq!(full, game.width * game.height);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.cells.len(), full);
+        let clears = backend.clears;
+
+        // Too small: one clear for the notice, not one per frame.
+        backend.size = (20, 10);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.notices.len(), 2);
+        assert_eq!(backend.clears, clears + 2);
+        assert_eq!(backend.cells.len(), full);
+
+        // Fits again: wipe the notice and redraw the whole board.
+        backend.size = (100, 40);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.clears > clears + 2);
+        assert_eq!(backend.cells.len(), 2 * full);
+
+        // A bigger terminal moves the board, so it all goes again.
+        let clears = backend.clears;
+        let origin = (renderer.origin_x, renderer.origin_y);
+        backend.size = (120, 50);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.clears > clears);
+        assert_eq!(backend.cells.len(), 3 * full);
+        assert_eq!(renderer.origin_x, origin.0 + 10);
+        assert_eq!(renderer.origin_y, origin.1 + 5);
+    }
+
+    #[test]
+    fn hint_tints_the_bots_next_step() {
+        let (mut game, _) = small_game(7);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.bgs.is_empty());
+
+        renderer.hint = true;
+        let before = (game.player, game.dir, game.score);
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        let next = step(game.player, demo_dir(&game).unwrap(), game.width);
+        let (r, g, b) = HINT_BG;
+        assert_eq!(backend.bgs, vec![(next.x, next.y, Color::Rgb { r, g, b })]);
+        assert_eq!((game.player, game.dir, game.score), before);
+
+        // Turning it off repaints the cell without the tint.
+        renderer.hint = false;
+        let drawn = backend.cells.len();
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert_eq!(backend.cells[drawn..].len(), 1);
+        assert_eq!(backend.bgs.len(), 1);
+    }
+
+    #[test]
+    fn debug_view_labels_distances_and_targets() {
+        let (mut game, _) = small_game(7);
+        game.ghost_release.fill(0);
+        let mut renderer = Renderer::new(game.width, game.height, SETTINGS);
+        let mut backend = Recorder::new((100, 40));
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        assert!(backend.labels.is_empty());
+
+        renderer.debug = true;
+        render(&mut backend, &mut game, &mut renderer, false).unwrap();
+        let (w, h) = (game.width, game.height);
+        let dist = bfs_distance(&game.grid, w, h, game.player, false);
+        let labels = &backend.labels;
+        let numbers: Vec<_> = labels.iter().filter(|(_, _, text)| text != "X").collect();
+        assert!(!numbers.is_empty());
+        for (x, y, text) in numbers {
+            assert_eq!(*text, format!("{:>2}", dist[*y][*x] % 100));
+        }
+        for target in game.ghost_targets().into_iter().flatten() {
+            let marker = (target.x, target.y, "X".to_string());
+            assert!(labels.contains(&marker));
+        }
+        // Everything under the labels is repainted on the next frame.
+        assert!(renderer.needs_full);
+    }
+
+    #[test]
+    fn bell_throttles_pellets() {
+        let mut bell = Bell::default();
+        let start = Instant::now();
+        let pellet = [GameEvent::PelletEaten];
+        assert_eq!(bell.rings(&pellet, start), 1);
+        assert_eq!(bell.rings(&pellet, start + Duration::from_millis(70)), 0);
+        let died = [GameEvent::PelletEaten, GameEvent::PlayerDied];
+        assert_eq!(bell.rings(&died, start + Duration::from_millis(140)), 3);
+        let later = start + Duration::from_millis(BELL_PELLET_MS);
+        assert_eq!(bell.rings(&pellet, later), 1);
+        assert_eq!(bell.rings(&[], start + Duration::from_secs(1)), 0);
+    }
+
+    // Seed 7 on the smallest board after five ticks holding left. A change here
+    // means maze generation, the AI or the glyphs changed; update it on purpose.
+    const SNAPSHOT: [&str; 15] = [
+        "██████████████████████████████████████████",
+        "██● ██· · · ██· · · · · · · · · · · · ● ██",
+        "██· ██· ██· ██████· · ████████████· ██· ██",
+        "██· ██· ██· · · · · · · · · ██· · · · · ██",
+        "██· ██· ██· ██· ██· · · ██· ██· ██· ██· ██",
+        "██· · · ██· ████████━━████████· ██· · · ██",
+        "██· ██████████  👻👻👻👻    ██· ██████████",
+        "  · · · · · ██              ██· · · · ·",
+        "██· ██· ██████              ██████████· ██",
+        "██· ██· ██· ██████████████████· · · ██· ██",
+        "██· ██· ██· ██· ██· ██████· ██████· ██· ██",
+        "██· · · · · ██· ██· · · · · · · · · · · ██",
+        "██· ██· ██· ██· ██· ██████· ██████████· ██",
+        "██● · · · · ██😃      · ██· · · · · · ● ██",
+        "██████████████████████████████████████████",
+    ];
 }